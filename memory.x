@@ -20,3 +20,13 @@ MEMORY
   ECC : ORIGIN = 0x20020000, LENGTH = 128K
 }
   /*RAM : ORIGIN = 0x20010000, LENGTH = 192K*/
+
+/* Reference digests for the ICM firmware integrity monitor, patched in after
+   linking. Block aligned so it stays out of the monitored .rodata range. */
+SECTIONS
+{
+  .icm_digests ALIGN(64) :
+  {
+    KEEP(*(.icm_digests));
+  } > FLASH
+} INSERT AFTER .rodata;
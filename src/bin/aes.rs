@@ -4,6 +4,10 @@
 
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
+    crashlog::record(
+        crashlog::Kind::Panic,
+        info.location().map_or(0, |location| location.line()),
+    );
     unsafe {
         if let Some(u) = UART0_TX.as_mut() {
             write!(u as &mut dyn Write<_, Error = _>, "{}\r\n", info).unwrap()
@@ -14,29 +18,120 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
     }
 }
 
-use atsamd_demo::{clear_line, clear_screen, uart::*};
+use atsamd_demo::{
+    board::{
+        hal::{
+            dsu::Dsu,
+            nvm::{smart_eeprom::SmartEepromMode, Nvm},
+            prelude::*,
+        },
+        Board,
+    },
+    board_parts, clear_line, clear_screen, crashlog,
+    crypto::{
+        self,
+        dma::{self, AesDma, Buffers, Mode},
+        drbg::DrbgRng,
+        encrypt_words,
+        keystore::{self, KeyStore},
+        Direction,
+    },
+    icm::{
+        config::{Interrupt, Memory},
+        events::{self, Consumer, Event, Producer, Queue},
+        monitor::{self, Monitor, Region},
+    },
+    trng::Trng,
+    uart::*,
+};
 
 use core::fmt::Write as _;
+use cortex_m::peripheral::DWT;
 
-use atsamd_hal_aes::{
-    aes::*,
-    clock::GenericClockController,
-    dsu::Dsu,
-    gpio::Pins,
-    ehal::serial::Write,
-    nvm::{smart_eeprom::SmartEepromMode, Nvm},
-    prelude::*,
-    time::U32Ext,
-};
+use atsamd_hal_aes::aes::*;
 use nb::block;
 
 use rtic::app;
 
 static mut UART0_TX: Option<Uart0Tx> = None;
 
-#[app(device = atsamd_hal_aes::pac, peripherals = true, dispatchers = [FREQM])]
+/// CTR_DRBG on the AES peripheral, seeded from the TRNG
+type Rng = DrbgRng<Aes256, Trng>;
+
+/// Size of the throughput benchmark buffers in words, 4 KiB
+const BENCH_WORDS: usize = 1024;
+/// Key used by the throughput benchmark
+const BENCH_KEY: [u8; 16] = *b"benchmark key 16";
+
+/// Reset the device when the firmware integrity monitor reports a mismatch
+const RESET_ON_MISMATCH: bool = false;
+
+/// Offset of the key slots in SmartEEPROM
+///
+/// `w` commands touching the [`keystore::SIZE`] bytes from here on corrupt the
+/// stored keys.
+const KEYSTORE_OFFSET: usize = 256;
+
+/// 128-bit serial number, unique to each device
+fn serial_number() -> [u8; 16] {
+    const WORDS: [usize; 4] = [0x0080_61fc, 0x0080_6010, 0x0080_6014, 0x0080_6018];
+    let mut serial = [0; 16];
+    for (bytes, address) in serial.chunks_exact_mut(4).zip(WORDS.iter()) {
+        let word = unsafe { core::ptr::read_volatile(*address as *const u32) };
+        bytes.copy_from_slice(&word.to_le_bytes());
+    }
+    serial
+}
+
+/// Run an `aes`, `cmac` or `key` command against the key slots in SmartEEPROM
+fn crypto_command<'a, W: core::fmt::Write + ?Sized>(
+    name: &str,
+    args: impl Iterator<Item = &'a str>,
+    nvm: &mut Nvm,
+    rng: &mut Rng,
+    tx: &mut W,
+) {
+    let mut se = match nvm.smart_eeprom() {
+        Ok(SmartEepromMode::Unlocked(se)) => se,
+        Ok(SmartEepromMode::Locked(se)) => se.unlock(),
+        Err(_) => {
+            tx.write_str("SmartEEPROM not available\r\n").unwrap();
+            return;
+        }
+    };
+
+    // Key slots are wrapped with a key bound to this chip
+    let kek = keystore::device_kek::<Aes128>(&serial_number()).unwrap();
+    let mut keys = KeyStore::new(&mut se, KEYSTORE_OFFSET, kek);
+    if let Err(e) = crypto::console::execute::<Aes128, Aes192, Aes256, _, _, _, _, _>(
+        name, args, &mut keys, rng, tx,
+    ) {
+        write!(tx, "error: {:?}\r\n{}", e, crypto::console::USAGE).unwrap();
+    }
+}
+
+/// Print the throughput of one benchmark run
+fn report<W: core::fmt::Write + ?Sized>(
+    tx: &mut W,
+    name: &str,
+    bytes: usize,
+    cycles: u32,
+    sysclk_hz: u32,
+) {
+    let cycles = core::cmp::max(cycles, 1);
+    let kib_per_sec = bytes as u64 * sysclk_hz as u64 / cycles as u64 / 1024;
+    write!(
+        tx,
+        "{:>9}: {} bytes in {} cycles, {} KiB/s\r\n",
+        name, bytes, cycles, kib_per_sec
+    )
+    .unwrap();
+}
+
+#[app(device = atsamd_demo::board::pac, peripherals = true, dispatchers = [FREQM])]
 mod app {
     use super::*;
+    use rtic::Mutex;
 
     #[shared]
     struct SharedResources {
@@ -44,198 +139,122 @@ mod app {
         nvm: Nvm,
         dsu: Dsu,
         buffer: String,
+        aes_dma: AesDma,
+        rng: Rng,
+        bench_buffers: Option<Buffers>,
+        bench_started: u32,
+        #[lock_free]
+        sysclk_hz: u32,
+        #[lock_free]
+        crypto_ok: bool,
     }
 
     #[local]
-    struct LocalResources {}
+    struct LocalResources {
+        icm_monitor: Monitor,
+        icm_producer: Producer<'static>,
+        icm_consumer: Consumer<'static>,
+        icm_regions: [Region; 2],
+    }
 
-    #[init]
+    #[init(local = [
+        icm_queue: Queue = Queue::new(),
+        bench_source: [u32; BENCH_WORDS] = [0; BENCH_WORDS],
+        bench_destination: [u32; BENCH_WORDS] = [0; BENCH_WORDS],
+    ])]
     fn init(cx: init::Context) -> (SharedResources, LocalResources, init::Monotonics()) {
-        let mut device = cx.device;
-
-        let pins = Pins::new(device.PORT);
-
-        let mclk = &mut device.MCLK;
-
-        let mut clocks = GenericClockController::with_external_32kosc(
-            device.GCLK,
-            mclk,
-            &mut device.OSC32KCTRL,
-            &mut device.OSCCTRL,
-            &mut device.NVMCTRL,
-        );
-
-        let gclk0 = clocks.gclk0();
-
-        let mut uart0 = Config::new(
-            mclk,
-            device.SERCOM0,
-            Pads::default().rx(pins.pa05).tx(pins.pa04),
-            clocks.sercom0_core(&gclk0).unwrap().freq(),
-        )
-        .baud(115_200.hz(), BaudMode::Arithmetic(Oversampling::Bits16))
-        .enable();
-        uart0.enable_interrupts(Flags::RXC);
-
-        let nvm = Nvm::new(device.NVMCTRL);
-        let dsu = Dsu::new(device.DSU, &device.PAC).unwrap();
+        let device = cx.device;
+        let mut core = cx.core;
+
+        // Cycle counter used by the throughput benchmark
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let Board {
+            console: mut uart0,
+            nvm,
+            dsu,
+            sysclk_hz,
+            mut mclk,
+            ..
+        } = Board::new(board_parts!(device));
 
         // Enable bus clocking for AES peripheral
         mclk.apbcmask.modify(|_, w| w.aes_().set_bit());
 
-        let key = [
-            0x54, 0x68, 0x61, 0x74, 0x73, 0x20, 0x6D, 0x79, 0x20, 0x4B, 0x75, 0x6E, 0x67, 0x20,
-            0x46, 0x75,
-        ];
-
-        let message = [
-            0x54, 0x77, 0x6F, 0x20, 0x4F, 0x6E, 0x65, 0x20, 0x4E, 0x69, 0x6E, 0x65, 0x20, 0x54,
-            0x77, 0x6F,
-        ];
-
-        let ciphertext = *aes::Block::from_slice(&[
-            0x29, 0xC3, 0x50, 0x5F, 0x57, 0x14, 0x20, 0xF6, 0x40, 0x22, 0x99, 0xB3, 0x1A, 0x02,
-            0xD7, 0x3A,
-        ]);
-
-        let aeskey = GenericArray::from_slice(&key);
-        let mut aesmsg = *aes::Block::from_slice(&message);
-
-        // Atsamd peripheral init
-        let aes = Aes::new(device.AES);
-
-        // Store AES hardware peripheral in AesRustCrypto to ensure
-        // no uses of the AES peripheral outside RustCrypto
-        let _aes_rc = aes.activate_rustcrypto_backend();
-
-        let aes128 = Aes128::new(aeskey);
-
-        write!(
-            &mut uart0 as &mut dyn Write<_, Error = _>,
-            "Begin AES demo\r\n"
-        )
-        .unwrap();
+        // DMAC feeding the AES peripheral for bulk encryption
+        let aes_dma = AesDma::new(device.DMAC, &mut mclk);
 
-        for x in aesmsg {
-            write!(&mut uart0 as &mut dyn Write<_, Error = _>, "{}", x as char).unwrap();
-        }
-        write!(&mut uart0 as &mut dyn Write<_, Error = _>, "\r\n").unwrap();
-        for x in aesmsg {
-            write!(&mut uart0 as &mut dyn Write<_, Error = _>, "{:#04x} ", x).unwrap();
+        // Entropy for the DRBG providing IVs and generated keys
+        let trng = Trng::new(device.TRNG, &mut mclk);
+        if let Some(failure) = trng.failure() {
+            write!(
+                &mut uart0 as &mut dyn Write<_, Error = _>,
+                "TRNG start-up test failed: {:?}\r\n",
+                failure
+            )
+            .unwrap();
         }
-        write!(&mut uart0 as &mut dyn Write<_, Error = _>, "\r\n").unwrap();
-
-        // Encrypt message
-        aes128.encrypt_block(&mut aesmsg);
 
-        for x in aesmsg {
-            write!(&mut uart0 as &mut dyn Write<_, Error = _>, "{}", x as char).unwrap();
-        }
-        write!(&mut uart0 as &mut dyn Write<_, Error = _>, "\r\n").unwrap();
-        for x in aesmsg {
-            write!(&mut uart0 as &mut dyn Write<_, Error = _>, "{:#04x} ", x).unwrap();
+        // Events recorded before the last reset
+        for entry in crashlog::entries() {
+            write!(
+                &mut uart0 as &mut dyn Write<_, Error = _>,
+                "crash log: {:?} {:#x}\r\n",
+                entry.kind, entry.data
+            )
+            .unwrap();
         }
-        write!(
-            &mut uart0 as &mut dyn Write<_, Error = _>,
-            "   <- AES ciphertext\r\n"
-        )
-        .unwrap();
-        for x in ciphertext {
-            write!(&mut uart0 as &mut dyn Write<_, Error = _>, "{:#04x} ", x).unwrap();
+
+        // Keep the ICM checking the application flash
+        let regions = monitor::firmware_regions();
+        let icm_monitor = Monitor::new(device.ICM, &mut mclk, Memory::take().unwrap(), &regions)
+            .map_err(|(error, ..)| error)
+            .unwrap();
+        for region in icm_monitor.regions() {
+            write!(
+                &mut uart0 as &mut dyn Write<_, Error = _>,
+                "ICM monitoring {} {:#010x}..{:#010x}\r\n",
+                region.name, region.start, region.end
+            )
+            .unwrap();
         }
         write!(
             &mut uart0 as &mut dyn Write<_, Error = _>,
-            "   <- known ciphertext\r\n"
+            "ICM reference digests: {:?}\r\n",
+            icm_monitor.reference()
         )
         .unwrap();
+        let (icm_producer, icm_consumer) = cx.local.icm_queue.split();
 
-        assert_eq!(aesmsg, ciphertext);
-
-        // Decrypt message
-        aes128.decrypt_block(&mut aesmsg);
-
-        for x in aesmsg {
-            write!(&mut uart0 as &mut dyn Write<_, Error = _>, "{}", x as char).unwrap();
-        }
-        write!(&mut uart0 as &mut dyn Write<_, Error = _>, "\r\n").unwrap();
-        for x in aesmsg {
-            write!(&mut uart0 as &mut dyn Write<_, Error = _>, "{:#04x} ", x).unwrap();
+        // Fill the benchmark source with a recognizable pattern
+        for (index, word) in cx.local.bench_source.iter_mut().enumerate() {
+            *word = index as u32;
         }
-        write!(&mut uart0 as &mut dyn Write<_, Error = _>, "\r\n").unwrap();
-
-        cortex_m::asm::bkpt();
-
-        // AES RustCrypto Example
-
-        let key = GenericArray::<u8, U16>::from_slice(&[0u8; 16]);
-        let mut block = aes::Block::default();
-
-        let aes128 = Aes128::new(key);
-
-        // Initialize cipher
-        let cipher = aes128;
-
-        let block_copy = block;
-
-        // Encrypt block in-place
-        cipher.encrypt_block(&mut block);
-
-        // And decrypt it back
-        cipher.decrypt_block(&mut block);
-        assert_eq!(block, block_copy);
-
-        cortex_m::asm::bkpt();
-
-        // AES CMAC RustCrypto Example
-
-        use cmac::{Cmac, Mac, NewMac};
-
-        // Create `Mac` trait implementation, namely CMAC-AES128
-        let mut mac = Cmac::<Aes128>::new_from_slice(b"very secret key.").unwrap();
-        mac.update(b"input message");
-
-        // `result` has type `Output` which is a thin wrapper around array of
-        // bytes for providing constant time equality check
-        let result = mac.finalize();
-        // To get underlying array use the `into_bytes` method, but be careful,
-        // since incorrect use of the tag value may permit timing attacks which
-        // defeat the security provided by the `Output` wrapper
-        let tag_bytes = result.into_bytes();
-
-        //To verify the message:
-
-        let mut mac = Cmac::<Aes128>::new_from_slice(b"very secret key.").unwrap();
-
-        mac.update(b"input message");
-
-        // `verify` will return `Ok(())` if tag is correct, `Err(MacError)` otherwise
-        mac.verify(&tag_bytes).unwrap();
-        cortex_m::asm::bkpt();
-
-        // AES Counter RustCrypto Example
-        use ctr::cipher::{NewCipher, StreamCipher, StreamCipherSeek};
-
-        // `aes` crate provides AES block cipher implementation
-        type Aes128Ctr = ctr::Ctr128BE<atsamd_hal_aes::aes::Aes128>;
-
-        let mut data = [1, 2, 3, 4, 5, 6, 7];
-
-        let key = b"very secret key.";
-        let nonce = b"and secret nonce";
+        let bench_buffers = Buffers {
+            source: cx.local.bench_source,
+            destination: cx.local.bench_destination,
+        };
 
-        // create cipher instance
-        let mut cipher = Aes128Ctr::new(key.into(), nonce.into());
-
-        // apply keystream (encrypt)
-        cipher.apply_keystream(&mut data);
-        assert_eq!(data, [6, 245, 126, 124, 180, 146, 37]);
+        // Atsamd peripheral init
+        let aes = Aes::new(device.AES);
 
-        // seek to the keystream beginning and apply it again to the `data` (decrypt)
-        cipher.seek(0);
-        cipher.apply_keystream(&mut data);
-        assert_eq!(data, [1, 2, 3, 4, 5, 6, 7]);
+        // Store AES hardware peripheral in AesRustCrypto to ensure
+        // no uses of the AES peripheral outside RustCrypto
+        let _aes_rc = aes.activate_rustcrypto_backend();
 
-        cortex_m::asm::bkpt();
+        // Power-on self-test, crypto commands stay disabled if it fails
+        let crypto_ok = crypto::selftest::run::<Aes128, Aes192, Aes256, _>(
+            &mut uart0 as &mut dyn Write<_, Error = _>,
+        )
+        .is_ok();
+        if !crypto_ok {
+            write!(
+                &mut uart0 as &mut dyn Write<_, Error = _>,
+                "AES self-test failed, crypto commands disabled\r\n"
+            )
+            .unwrap();
+        }
 
         write!(
             &mut uart0 as &mut dyn Write<_, Error = _>,
@@ -255,8 +274,19 @@ mod app {
                 nvm,
                 dsu,
                 buffer: heapless::String::new(),
+                aes_dma,
+                rng: DrbgRng::new(trng),
+                bench_buffers: Some(bench_buffers),
+                bench_started: 0,
+                sysclk_hz,
+                crypto_ok,
+            },
+            LocalResources {
+                icm_monitor,
+                icm_producer,
+                icm_consumer,
+                icm_regions: regions,
             },
-            LocalResources {},
             init::Monotonics(),
         )
     }
@@ -267,10 +297,13 @@ mod app {
         Write,
     }
 
-    #[task(shared = [buffer, nvm], capacity = 10)]
+    #[task(shared = [buffer, nvm, aes_dma, rng, crypto_ok], capacity = 10)]
     fn uart_handle(cx: uart_handle::Context, uart_data: UartCommand) {
         let mut buffer = cx.shared.buffer;
         let mut nvm = cx.shared.nvm;
+        let mut aes_dma = cx.shared.aes_dma;
+        let mut rng = cx.shared.rng;
+        let crypto_ok = *cx.shared.crypto_ok;
         let uart0_tx = unsafe { UART0_TX.as_mut().unwrap() as &mut dyn Write<_, Error = _> };
         match uart_data {
             UartCommand::Return => {
@@ -279,8 +312,50 @@ mod app {
                     // custom action start
 
                     let mut iterator = b.split_whitespace();
-                    let (action, arg1, arg2) = match iterator
-                        .next()
+                    let command = iterator.next();
+
+                    if command == Some("bench") {
+                        if bench::spawn().is_err() {
+                            uart0_tx.write_str("benchmark already queued\r\n").unwrap();
+                        }
+                        b.clear();
+                        return;
+                    }
+
+                    if command == Some("selftest") {
+                        if selftest::spawn().is_err() {
+                            uart0_tx.write_str("self-test already queued\r\n").unwrap();
+                        }
+                        b.clear();
+                        return;
+                    }
+
+                    if let Some(name @ ("aes" | "cmac" | "key" | "random")) = command {
+                        if !crypto_ok {
+                            uart0_tx
+                                .write_str("AES self-test failed, refusing\r\n")
+                                .unwrap();
+                        } else if aes_dma.lock(|d| d.is_busy()) {
+                            uart0_tx.write_str("AES busy, try again\r\n").unwrap();
+                        } else {
+                            (&mut nvm, &mut rng)
+                                .lock(|n, rng| crypto_command(name, iterator, n, rng, uart0_tx));
+                        }
+                        b.clear();
+                        return;
+                    }
+
+                    if command == Some("help") {
+                        uart0_tx
+                            .write_str("r <offset> <count>\r\nw <offset> <count>\r\n")
+                            .unwrap();
+                        uart0_tx.write_str("bench\r\nselftest\r\n").unwrap();
+                        uart0_tx.write_str(crypto::console::USAGE).unwrap();
+                        b.clear();
+                        return;
+                    }
+
+                    let (action, arg1, arg2) = match command
                         .and_then(|v| match v {
                             "r" => Some(Action::Read),
                             "w" => Some(Action::Write),
@@ -366,6 +441,212 @@ mod app {
         }
     }
 
+    /// Rerun the crypto self-test, re-enabling or disabling crypto commands
+    #[task(shared = [aes_dma, crypto_ok])]
+    fn selftest(cx: selftest::Context) {
+        let uart0_tx = unsafe { UART0_TX.as_mut().unwrap() as &mut dyn Write<_, Error = _> };
+        let mut aes_dma = cx.shared.aes_dma;
+
+        if aes_dma.lock(|d| d.is_busy()) {
+            uart0_tx.write_str("AES busy, try again\r\n").unwrap();
+            return;
+        }
+
+        *cx.shared.crypto_ok = crypto::selftest::run::<Aes128, Aes192, Aes256, _>(uart0_tx).is_ok();
+    }
+
+    /// Compare software AES, polled hardware AES and DMA-driven hardware AES
+    #[task(shared = [aes_dma, bench_buffers, bench_started, sysclk_hz, crypto_ok])]
+    fn bench(cx: bench::Context) {
+        let uart0_tx = unsafe { UART0_TX.as_mut().unwrap() as &mut dyn Write<_, Error = _> };
+        let mut aes_dma = cx.shared.aes_dma;
+        let mut bench_buffers = cx.shared.bench_buffers;
+        let mut bench_started = cx.shared.bench_started;
+        let sysclk_hz = *cx.shared.sysclk_hz;
+
+        if !*cx.shared.crypto_ok {
+            uart0_tx
+                .write_str("AES self-test failed, refusing\r\n")
+                .unwrap();
+            return;
+        }
+
+        let buffers = match bench_buffers.lock(|b| b.take()) {
+            Some(buffers) => buffers,
+            None => {
+                uart0_tx.write_str("benchmark already running\r\n").unwrap();
+                return;
+            }
+        };
+        let bytes = buffers.source.len() * 4;
+        let key = GenericArray::from_slice(&BENCH_KEY);
+
+        // Pure software, the `aes` crate
+        let software = aes::Aes128::new(key);
+        buffers.destination.copy_from_slice(buffers.source);
+        let start = DWT::cycle_count();
+        encrypt_words(&software, buffers.destination);
+        let cycles = DWT::cycle_count().wrapping_sub(start);
+        report(uart0_tx, "software", bytes, cycles, sysclk_hz);
+
+        // AES peripheral, polled one block at a time through RustCrypto
+        let hardware = Aes128::new(key);
+        buffers.destination.copy_from_slice(buffers.source);
+        let start = DWT::cycle_count();
+        encrypt_words(&hardware, buffers.destination);
+        let cycles = DWT::cycle_count().wrapping_sub(start);
+        report(uart0_tx, "polled", bytes, cycles, sysclk_hz);
+
+        // AES peripheral fed by the DMAC, reported by `bench_done`
+        bench_started.lock(|s| *s = DWT::cycle_count());
+        if let Err((e, buffers)) =
+            aes_dma.lock(|d| d.start(&BENCH_KEY, Mode::Ecb, Direction::Encrypt, buffers))
+        {
+            write!(
+                uart0_tx as &mut dyn Write<_, Error = _>,
+                "dma: failed to start: {:?}\r\n",
+                e
+            )
+            .unwrap();
+            bench_buffers.lock(|b| b.replace(buffers));
+        }
+    }
+
+    /// Report the DMA benchmark run and verify its output against software
+    #[task(shared = [bench_buffers, sysclk_hz])]
+    fn bench_done(
+        cx: bench_done::Context,
+        result: Result<(), dma::Error>,
+        buffers: Buffers,
+        cycles: u32,
+    ) {
+        let uart0_tx = unsafe { UART0_TX.as_mut().unwrap() as &mut dyn Write<_, Error = _> };
+        let mut bench_buffers = cx.shared.bench_buffers;
+        let sysclk_hz = *cx.shared.sysclk_hz;
+
+        match result {
+            Ok(()) => {
+                report(uart0_tx, "dma", buffers.source.len() * 4, cycles, sysclk_hz);
+
+                let software = aes::Aes128::new(GenericArray::from_slice(&BENCH_KEY));
+                let verified = buffers
+                    .source
+                    .chunks_exact(4)
+                    .zip(buffers.destination.chunks_exact(4))
+                    .all(|(source, destination)| {
+                        let mut block = [source[0], source[1], source[2], source[3]];
+                        encrypt_words(&software, &mut block);
+                        block == destination
+                    });
+                write!(
+                    uart0_tx,
+                    "dma: output {}\r\n",
+                    if verified { "verified" } else { "MISMATCH" }
+                )
+                .unwrap();
+            }
+            Err(e) => write!(
+                uart0_tx as &mut dyn Write<_, Error = _>,
+                "dma: transfer failed: {:?}\r\n",
+                e
+            )
+            .unwrap(),
+        }
+
+        bench_buffers.lock(|b| b.replace(buffers));
+    }
+
+    /// Hand a finished DMA transfer over to `bench_done`, or put the buffers
+    /// back if it can't be spawned so the next `bench` still finds them
+    fn dma_finished(
+        mut aes_dma: impl Mutex<T = AesDma>,
+        mut bench_buffers: impl Mutex<T = Option<Buffers>>,
+        mut bench_started: impl Mutex<T = u32>,
+    ) {
+        let now = DWT::cycle_count();
+        if let Some((result, buffers)) = aes_dma.lock(|d| d.complete()) {
+            let cycles = now.wrapping_sub(bench_started.lock(|s| *s));
+            if let Err((_, buffers, _)) = bench_done::spawn(result, buffers, cycles) {
+                bench_buffers.lock(|b| b.replace(buffers));
+            }
+        }
+    }
+
+    // Write channel, only raised on transfer errors
+    #[task(binds = DMAC_0, shared = [aes_dma, bench_buffers, bench_started], priority = 2)]
+    fn dmac_write(cx: dmac_write::Context) {
+        dma_finished(
+            cx.shared.aes_dma,
+            cx.shared.bench_buffers,
+            cx.shared.bench_started,
+        );
+    }
+
+    // Read channel, raised when the last block has been drained
+    #[task(binds = DMAC_1, shared = [aes_dma, bench_buffers, bench_started], priority = 2)]
+    fn dmac_read(cx: dmac_read::Context) {
+        dma_finished(
+            cx.shared.aes_dma,
+            cx.shared.bench_buffers,
+            cx.shared.bench_started,
+        );
+    }
+
+    /// Queue firmware integrity monitor events for `icm_report`
+    #[task(binds = ICM, local = [icm_monitor, icm_producer], priority = 2)]
+    fn icm_interrupt(cx: icm_interrupt::Context) {
+        cx.local.icm_monitor.capture(cx.local.icm_producer);
+
+        // Fails while a report is pending, which drains the queue anyway
+        icm_report::spawn().ok();
+    }
+
+    /// Report queued firmware integrity monitor events and record failures in
+    /// the crash log
+    #[task(local = [icm_consumer, icm_regions])]
+    fn icm_report(cx: icm_report::Context) {
+        let uart0_tx = unsafe { UART0_TX.as_mut().unwrap() as &mut dyn Write<_, Error = _> };
+
+        let mut failed = false;
+        while let Some(event) = cx.local.icm_consumer.dequeue() {
+            let (kind, regions) = match event {
+                Event::Region(Interrupt::DigestMismatch, regions) => {
+                    (crashlog::Kind::IcmMismatch, regions)
+                }
+                Event::Region(Interrupt::BusError, regions) => {
+                    (crashlog::Kind::IcmBusError, regions)
+                }
+                event => {
+                    write!(uart0_tx, "ICM: {}\r\n", event).unwrap();
+                    continue;
+                }
+            };
+            failed = true;
+            crashlog::record(kind, regions as u32);
+
+            for (index, region) in cx.local.icm_regions.iter().enumerate() {
+                if regions & 1 << index != 0 {
+                    write!(uart0_tx, "ICM {:?}: {}\r\n", kind, region.name).unwrap();
+                }
+            }
+        }
+
+        let dropped = events::dropped();
+        if dropped > 0 {
+            write!(uart0_tx, "ICM: {} events dropped\r\n", dropped).unwrap();
+        }
+
+        if failed && RESET_ON_MISMATCH {
+            cortex_m::peripheral::SCB::sys_reset();
+        }
+    }
+
+    #[task(binds = TRNG, shared = [rng], priority = 2)]
+    fn trng_interrupt(cx: trng_interrupt::Context) {
+        let mut rng = cx.shared.rng;
+        rng.lock(|r| r.entropy_mut().on_interrupt());
+    }
+
     #[task(binds = SERCOM0_2, shared = [uart0_rx], priority = 2)]
     fn uart_interrupt(cx: uart_interrupt::Context) {
         let mut rx = cx.shared.uart0_rx;
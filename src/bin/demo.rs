@@ -3,113 +3,432 @@
 #![no_main]
 
 #[panic_handler]
-fn panic(_info: &core::panic::PanicInfo) -> ! {
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    unsafe {
+        if let Some(u) = UART0_TX.as_mut() {
+            write!(u as &mut dyn Write<_, Error = _>, "{}\r\n", info).unwrap()
+        }
+    }
     loop {
         cortex_m::asm::nop();
     }
 }
-use atsamd_hal::{
-    clock::v2::{
-        dpll::Dpll, gclk, gclk::Gclk1Div, gclkio::GclkOut, por_state, xosc::*, xosc32k::*, Source,
+
+use atsamd_demo::{
+    board::{
+        hal::{
+            dsu::Dsu,
+            nvm::{smart_eeprom::SmartEepromMode, Nvm},
+            prelude::*,
+        },
+        pac::MCLK,
+        Board,
     },
-    gpio::Pins,
-    time::U32Ext,
+    board_parts, clear_line, clear_screen,
+    clock::{
+        failure::{self, Failure},
+        freqm::{self, Freqm},
+        profile, switch,
+        tree::{Source, Tree},
+    },
+    monotonic::{RtcClock, RtcDuration, RtcMonotonic, RTC_HZ},
+    uart::*,
 };
 
-use dwt_systick_monotonic::fugit::TimerDurationU32;
+use core::fmt::Write as _;
 
-const SCHEDULE_FREQ: u32 = 100_000_000;
+use nb::block;
 
 use rtic::app;
 
-#[app(device = atsamd_hal::pac, peripherals = true, dispatchers = [TCC1_MC1]
+static mut UART0_TX: Option<Uart0Tx> = None;
+
+#[app(device = atsamd_demo::board::pac, peripherals = true, dispatchers = [TCC1_MC1]
  )]
 mod app {
     use super::*;
-    use dwt_systick_monotonic::*;
     use rtic::Monotonic;
 
-    #[monotonic(binds = SysTick, default = true)]
-    type MyMono = DwtSystick<SCHEDULE_FREQ>;
+    // The RTC keeps time across clock profile switches and sleep
+    #[monotonic(binds = RTC, default = true)]
+    type MyMono = RtcMonotonic;
 
     #[shared]
-    struct SharedResources {}
+    struct SharedResources {
+        uart0_rx: Uart0Rx,
+        nvm: Nvm,
+        dsu: Dsu,
+        buffer: String,
+        freqm: Option<Freqm>,
+    }
 
     #[local]
     struct LocalResources {}
 
     #[init]
-    fn init(mut cx: init::Context) -> (SharedResources, LocalResources, init::Monotonics()) {
-        let mut device = cx.device;
-
-        // Get the clock power-on-reset state
-        let (_buses, clocks, tokens) = por_state(
-            device.OSCCTRL,
-            device.OSC32KCTRL,
-            device.GCLK,
-            device.MCLK,
-            &mut device.NVMCTRL,
-        );
-
-        // Get the pins
-        let pins = Pins::new(device.PORT);
-
-        // Enable pin PA14 and PA15 as an external source for XOSC0 at 8 MHz
-        let xosc0 = Xosc::from_crystal(
-            tokens.xosc0,
-            pins.pa14,
-            pins.pa15,
-            8.mhz(),
-            CrystalCurrent::Medium,
-        )
-        .enable();
+    fn init(cx: init::Context) -> (SharedResources, LocalResources, init::Monotonics()) {
+        let device = cx.device;
 
-        // Take DFLL 48 MHz, divide down to 2 MHz for Gclk1
-        let (gclk1, dfll) = gclk::Gclk::new(tokens.gclks.gclk1, clocks.dfll);
-        let _gclk1 = gclk1.div(Gclk1Div::Div(24)).enable();
+        let Board {
+            console: mut uart0,
+            nvm,
+            dsu,
+            ..
+        } = Board::new(board_parts!(device));
 
-        // Configure DPLL0 to 100 MHz fed from Xosc0 with a predivider of 1
-        let (dpll0, _xosc0) = Dpll::from_xosc0(tokens.dpll0, xosc0, 1);
+        // Initialize the monotonic on the 32 kHz crystal the board enabled
+        let mono = RtcMonotonic::new(device.RTC, RtcClock::Xosc32k);
 
-        // Use 4 as source predivider, 8 MHz / (2 * ( 1 + prediv) * 50 = 100 MHz,
-        // where prediv = 1
-        let dpll0 = dpll0.set_loop_div(50, 0).enable().ok().unwrap();
+        write!(
+            &mut uart0 as &mut dyn Write<_, Error = _>,
+            "RTIC booted!\r\n"
+        )
+        .unwrap();
 
-        // Change Gclk0 from Dfll to Dpll0, MCLK = 100 MHz
-        let (gclk0, _dfll, _dpll0) = clocks.gclk0.swap(dfll, dpll0);
+        // Only the FREQM bus clock is enabled through it, MCLK itself went to
+        // the clock tree
+        let mclk = unsafe { &*MCLK::ptr() };
+        let mut meter = match Freqm::new(device.FREQM, mclk, Source::Xosc32k) {
+            Ok(meter) => Some(meter),
+            Err(e) => {
+                write!(
+                    &mut uart0 as &mut dyn Write<_, Error = _>,
+                    "warning: no frequency meter, {:?}\r\n",
+                    e
+                )
+                .unwrap();
+                None
+            }
+        };
 
-        // Output Gclk0 on pin PB14
-        let (_gclk_out0, gclk0) =
-            GclkOut::enable(tokens.gclk_io.gclk_out0, pins.pb14, gclk0, false);
+        // Watch the crystals; a DPLL0 that did not lock has already left the
+        // core on the DFLL
+        failure::enable();
+        if !failure::status().is_ok() {
+            write!(
+                &mut uart0 as &mut dyn Write<_, Error = _>,
+                "warning: clocks {}\r\n",
+                failure::status()
+            )
+            .unwrap();
+        }
 
-        // Enable external 32k-oscillator
-        let token = tokens.xosc32k.base;
-        let base = XoscBase::from_crystal(token, pins.pa00, pins.pa01).enable();
-        let (_xosc32k, _base) = Xosc32k::enable(tokens.xosc32k.xosc32k, base);
+        // Clock self-check
+        if let Some(meter) = meter.as_mut() {
+            for &source in freqm::SELF_CHECK.iter() {
+                match meter.check(source) {
+                    Ok(measurement) if measurement.within(freqm::TOLERANCE_PPM) => {}
+                    Ok(measurement) => write!(
+                        &mut uart0 as &mut dyn Write<_, Error = _>,
+                        "warning: {} out of tolerance, {}\r\n",
+                        source.name(),
+                        measurement
+                    )
+                    .unwrap(),
+                    Err(e) => write!(
+                        &mut uart0 as &mut dyn Write<_, Error = _>,
+                        "warning: {} not checked, {:?}\r\n",
+                        source.name(),
+                        e
+                    )
+                    .unwrap(),
+                }
+            }
+        }
 
-        // Initialize the monotonic
-        let mono = DwtSystick::new(&mut cx.core.DCB, cx.core.DWT, cx.core.SYST, gclk0.freq().0);
+        let (uart0_rx, uart0_tx) = uart0.split();
+
+        unsafe {
+            UART0_TX.replace(uart0_tx);
+        }
 
         let _ = periodic::spawn();
 
         (
-            SharedResources {},
+            SharedResources {
+                uart0_rx,
+                nvm,
+                dsu,
+                buffer: heapless::String::new(),
+                freqm: meter,
+            },
             LocalResources {},
             init::Monotonics(mono),
         )
     }
 
-    #[idle]
+    #[derive(Debug)]
+    pub enum Action {
+        Read,
+        Write,
+    }
+
+    #[task(shared = [buffer, nvm, freqm], capacity = 10)]
+    fn uart_handle(cx: uart_handle::Context, uart_data: UartCommand) {
+        let mut buffer = cx.shared.buffer;
+        let mut nvm = cx.shared.nvm;
+        let mut freqm = cx.shared.freqm;
+        let uart0_tx = unsafe { UART0_TX.as_mut().unwrap() as &mut dyn Write<_, Error = _> };
+        match uart_data {
+            UartCommand::Return => {
+                buffer.lock(|b| {
+                    uart0_tx.write_str("\r\n").unwrap();
+                    // custom action start
+
+                    let mut iterator = b.split_whitespace();
+                    let command = iterator.next();
+
+                    if command == Some("clocks") {
+                        write!(
+                            uart0_tx as &mut dyn Write<_, Error = _>,
+                            "{}Failures: {}\r\n",
+                            Tree::read(),
+                            failure::status()
+                        )
+                        .unwrap();
+                        b.clear();
+                        return;
+                    }
+
+                    if command == Some("profile") {
+                        let profile = iterator
+                            .next()
+                            .and_then(|arg| arg.parse::<usize>().ok())
+                            .and_then(|index| profile::PROFILES.get(index));
+                        match profile {
+                            // SERCOM0 runs from GCLK0, which runs at the CPU
+                            // frequency; a profile too slow for the console
+                            // would leave it unreachable
+                            Some(profile)
+                                if profile
+                                    .solve()
+                                    .map_or(true, |plan| console_baud(plan.cpu_hz).is_none()) =>
+                            {
+                                write!(
+                                    uart0_tx as &mut dyn Write<_, Error = _>,
+                                    "{} is too slow for the console\r\n",
+                                    profile.name
+                                )
+                                .unwrap();
+                            }
+                            Some(profile) => {
+                                write!(
+                                    uart0_tx as &mut dyn Write<_, Error = _>,
+                                    "switching to {}\r\n",
+                                    profile.name
+                                )
+                                .unwrap();
+                                // Let the message out before the baud rate changes
+                                block!(uart0_tx.flush()).ok();
+
+                                let result = switch::apply(profile);
+                                // SERCOM0 runs from GCLK0, wherever it ended up
+                                if Tree::read().generator_hz(0).map_or(false, set_console_baud) {
+                                    match result {
+                                        Ok(cpu_hz) => write!(
+                                            uart0_tx as &mut dyn Write<_, Error = _>,
+                                            "CPU at {} Hz\r\n",
+                                            cpu_hz
+                                        ),
+                                        Err(e) => write!(
+                                            uart0_tx as &mut dyn Write<_, Error = _>,
+                                            "error: {:?}\r\n",
+                                            e
+                                        ),
+                                    }
+                                    .unwrap();
+                                }
+                            }
+                            None => {
+                                for (index, profile) in profile::PROFILES.iter().enumerate() {
+                                    write!(
+                                        uart0_tx as &mut dyn Write<_, Error = _>,
+                                        "{}: {}\r\n",
+                                        index, profile.name
+                                    )
+                                    .unwrap();
+                                }
+                                uart0_tx.write_str("usage: profile <index>\r\n").unwrap();
+                            }
+                        }
+                        b.clear();
+                        return;
+                    }
+
+                    if command == Some("freqm") {
+                        let generator = iterator.next().and_then(|arg| arg.parse().ok());
+                        freqm.lock(|meter| match (meter.as_mut(), generator) {
+                            (Some(meter), Some(generator)) => {
+                                match meter.check_generator(generator) {
+                                    Ok(measurement) => write!(
+                                        uart0_tx as &mut dyn Write<_, Error = _>,
+                                        "GCLK{}: {}\r\n",
+                                        generator, measurement
+                                    ),
+                                    Err(e) => write!(
+                                        uart0_tx as &mut dyn Write<_, Error = _>,
+                                        "error: {:?}\r\n",
+                                        e
+                                    ),
+                                }
+                                .unwrap()
+                            }
+                            (None, _) => {
+                                uart0_tx.write_str("error: no frequency meter\r\n").unwrap()
+                            }
+                            (_, None) => uart0_tx.write_str("usage: freqm <gclk>\r\n").unwrap(),
+                        });
+                        b.clear();
+                        return;
+                    }
+
+                    let (action, arg1, arg2) = match command
+                        .and_then(|v| match v {
+                            "r" => Some(Action::Read),
+                            "w" => Some(Action::Write),
+                            _ => None,
+                        })
+                        .and_then(|action| {
+                            iterator
+                                .next()
+                                .and_then(|arg1| arg1.parse().ok())
+                                .and_then(|arg1| {
+                                    iterator
+                                        .next()
+                                        .and_then(|arg2| arg2.parse().ok())
+                                        .map(|arg2| (action, arg1, arg2))
+                                })
+                        }) {
+                        Some(v) => v,
+                        None => {
+                            uart0_tx.write_str("argument parsing failure\r\n").unwrap();
+                            b.clear();
+                            return;
+                        }
+                    };
+
+                    nvm.lock(|n| {
+                        let mut se = match n.smart_eeprom().unwrap() {
+                            SmartEepromMode::Unlocked(se) => se,
+                            SmartEepromMode::Locked(se) => se.unlock(),
+                        };
+                        match action {
+                            Action::Read => {
+                                se.iter::<u8>().enumerate().skip(arg1).take(arg2).for_each(
+                                    |(i, v)| {
+                                        write!(
+                                            uart0_tx as &mut dyn Write<_, Error = _>,
+                                            "{:0x}: {:0x}\r\n",
+                                            i, v
+                                        )
+                                        .unwrap()
+                                    },
+                                )
+                            }
+                            Action::Write => {
+                                use core::convert::TryInto;
+                                uart0_tx.write_str("writing to eeprom..\r\n").unwrap();
+                                se.iter_mut::<u8>()
+                                    .skip(arg1)
+                                    .take(arg2)
+                                    .for_each(|v| *v = (arg2 & 0xff_usize).try_into().unwrap());
+                                uart0_tx.write_str("done\r\n").unwrap();
+                            }
+                        }
+                    });
+
+                    b.clear();
+                })
+            }
+            UartCommand::DataChar(character) => buffer.lock(|b| match b.push(character) {
+                Ok(_) => {
+                    clear_line!(uart0_tx);
+                    uart0_tx.write_str(b.as_str()).unwrap();
+                }
+                Err(_) => uart_handle::spawn(UartCommand::BufferFull).unwrap(),
+            }),
+            UartCommand::Backspace => buffer.lock(|b| {
+                if b.pop().is_some() {
+                    clear_line!(uart0_tx);
+                    uart0_tx.write_str(b.as_str()).unwrap();
+                }
+            }),
+            UartCommand::CtrlC => buffer.lock(|b| {
+                b.clear();
+                clear_screen!(uart0_tx);
+                uart0_tx.write_str(b.as_str()).unwrap();
+            }),
+            // failure / not supported commands
+            other => write!(
+                uart0_tx as &mut dyn Write<_, Error = _>,
+                "error: {:?}\r\n",
+                other
+            )
+            .unwrap(),
+        }
+    }
+
+    #[task(binds = SERCOM0_2, shared = [uart0_rx], priority = 2)]
+    fn uart_interrupt(cx: uart_interrupt::Context) {
+        let mut rx = cx.shared.uart0_rx;
+        match rx.lock(|rx| block!(rx.read())) {
+            Ok(byte) => match byte as char {
+                '\u{7f}' => uart_handle::spawn(UartCommand::Backspace),
+                '\u{3}' => uart_handle::spawn(UartCommand::CtrlC),
+                '\r' => uart_handle::spawn(UartCommand::Return),
+                byte => uart_handle::spawn(UartCommand::DataChar(byte)),
+            },
+            Err(e) => uart_handle::spawn(UartCommand::ReadError(e)),
+        }
+        .unwrap();
+    }
+
+    #[task(binds = OSCCTRL_0, priority = 3)]
+    fn xosc_failure(_: xosc_failure::Context) {
+        if let Some(failure) = failure::on_oscctrl_interrupt() {
+            clock_failure::spawn(failure).ok();
+        }
+    }
+
+    #[task(binds = OSC32KCTRL, priority = 3)]
+    fn xosc32k_failure(_: xosc32k_failure::Context) {
+        if let Some(failure) = failure::on_osc32kctrl_interrupt() {
+            clock_failure::spawn(failure).ok();
+        }
+    }
+
+    #[task(capacity = 2)]
+    fn clock_failure(_: clock_failure::Context, failure: Failure) {
+        let status = failure::status();
+        // SERCOM0 runs from GCLK0, which may have moved to the DFLL
+        if status.fallback() {
+            if let Some(hz) = Tree::read().generator_hz(0) {
+                set_console_baud(hz);
+            }
+        }
+        let uart0_tx = unsafe { UART0_TX.as_mut().unwrap() as &mut dyn Write<_, Error = _> };
+        write!(
+            uart0_tx as &mut dyn Write<_, Error = _>,
+            "clock failure: {}, clocks {}\r\n",
+            failure.name(),
+            status
+        )
+        .unwrap();
+    }
+
+    // Only assign dsu to silence an unused warning
+    #[idle(shared = [dsu])]
     fn idle(_cx: idle::Context) -> ! {
+        // Sleep between interrupts, the RTC keeps the schedule
         loop {
-            cortex_m::asm::nop();
+            cortex_m::asm::wfi();
         }
     }
 
     #[task]
     fn periodic(_: periodic::Context) {
-        // Should be 1 second
+        // 1 second of the RTC
         let _now = monotonics::now();
-        let _ = periodic::spawn_after(TimerDurationU32::from_ticks(SCHEDULE_FREQ));
+        let _ = periodic::spawn_after(RtcDuration::from_ticks(RTC_HZ));
     }
 }
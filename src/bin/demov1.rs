@@ -14,26 +14,40 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
     }
 }
 
-use atsamd_demo::{clear_line, clear_screen, uart::*};
+use atsamd_demo::{
+    board::{
+        hal::{
+            dsu::Dsu,
+            nvm::{smart_eeprom::SmartEepromMode, Nvm},
+            prelude::*,
+        },
+        Board,
+    },
+    board_parts,
+    calendar::{datetime, Calendar},
+    clear_line, clear_screen,
+    monotonic::RtcClock,
+    uart::*,
+};
 
 use core::fmt::Write as _;
 
-use atsamd_hal_clockv1::{
-    clock::GenericClockController,
-    dsu::Dsu,
-    gpio::Pins,
-    ehal::serial::Write,
-    nvm::{smart_eeprom::SmartEepromMode, Nvm},
-    prelude::*,
-    time::U32Ext,
-};
 use nb::block;
 
 use rtic::app;
 
 static mut UART0_TX: Option<Uart0Tx> = None;
 
-#[app(device = atsamd_hal_clockv1::pac, peripherals = true, dispatchers = [FREQM])]
+/// Print the calendar date-time, or why there is none
+fn write_now<W: core::fmt::Write + ?Sized>(tx: &mut W, calendar: &Calendar) {
+    match calendar.now() {
+        Ok(now) => write!(tx, "{}", now),
+        Err(e) => write!(tx, "no date, {:?}", e),
+    }
+    .unwrap();
+}
+
+#[app(device = atsamd_demo::board::pac, peripherals = true, dispatchers = [FREQM])]
 mod app {
     use super::*;
 
@@ -43,6 +57,7 @@ mod app {
         nvm: Nvm,
         dsu: Dsu,
         buffer: String,
+        calendar: Calendar,
     }
 
     #[local]
@@ -50,39 +65,22 @@ mod app {
 
     #[init]
     fn init(cx: init::Context) -> (SharedResources, LocalResources, init::Monotonics()) {
-        let mut device = cx.device;
+        let device = cx.device;
 
-        let pins = Pins::new(device.PORT);
+        let Board {
+            console: mut uart0,
+            nvm,
+            dsu,
+            ..
+        } = Board::new(board_parts!(device));
 
-        let mclk = &mut device.MCLK;
+        // Wall clock on the 32 kHz crystal the board enabled
+        let calendar = Calendar::new(device.RTC, RtcClock::Xosc32k);
 
-        let mut clocks = GenericClockController::with_external_32kosc(
-            device.GCLK,
-            mclk,
-            &mut device.OSC32KCTRL,
-            &mut device.OSCCTRL,
-            &mut device.NVMCTRL,
-        );
-
-        let gclk0 = clocks.gclk0();
-
-        let mut uart0 = Config::new(
-            mclk,
-            device.SERCOM0,
-            Pads::default().rx(pins.pa05).tx(pins.pa04),
-            clocks.sercom0_core(&gclk0).unwrap().freq(),
-        )
-        .baud(115_200.hz(), BaudMode::Arithmetic(Oversampling::Bits16))
-        .enable();
-        uart0.enable_interrupts(Flags::RXC);
-
-        let nvm = Nvm::new(device.NVMCTRL);
-        let dsu = Dsu::new(device.DSU, &device.PAC).unwrap();
-        write!(
-            &mut uart0 as &mut dyn Write<_, Error = _>,
-            "RTIC booted!\r\n"
-        )
-        .unwrap();
+        let tx = &mut uart0 as &mut dyn Write<_, Error = _>;
+        tx.write_str("RTIC booted! ").unwrap();
+        write_now(tx, &calendar);
+        tx.write_str("\r\n").unwrap();
 
         let (uart0_rx, uart0_tx) = uart0.split();
 
@@ -96,6 +94,7 @@ mod app {
                 nvm,
                 dsu,
                 buffer: heapless::String::new(),
+                calendar,
             },
             LocalResources {},
             init::Monotonics(),
@@ -108,10 +107,11 @@ mod app {
         Write,
     }
 
-    #[task(shared = [buffer, nvm], capacity = 10)]
+    #[task(shared = [buffer, nvm, calendar], capacity = 10)]
     fn uart_handle(cx: uart_handle::Context, uart_data: UartCommand) {
         let mut buffer = cx.shared.buffer;
         let mut nvm = cx.shared.nvm;
+        let mut calendar = cx.shared.calendar;
         let uart0_tx = unsafe { UART0_TX.as_mut().unwrap() as &mut dyn Write<_, Error = _> };
         match uart_data {
             UartCommand::Return => {
@@ -120,8 +120,39 @@ mod app {
                     // custom action start
 
                     let mut iterator = b.split_whitespace();
-                    let (action, arg1, arg2) = match iterator
-                        .next()
+                    let command = iterator.next();
+
+                    if command == Some("date") {
+                        calendar.lock(|calendar| {
+                            let result = match (iterator.next(), iterator.next()) {
+                                (None, _) => Ok(()),
+                                (Some("alarm"), Some(arg)) => {
+                                    arg.parse().and_then(|at| calendar.set_alarm(&at))
+                                }
+                                (Some(arg), None) => arg.parse().and_then(|now| calendar.set(&now)),
+                                _ => Err(datetime::Error::Format),
+                            };
+                            match result {
+                                Ok(()) => {
+                                    write_now(uart0_tx, calendar);
+                                    if calendar.alarm_pending() {
+                                        uart0_tx.write_str(", alarm set").unwrap();
+                                    }
+                                    uart0_tx.write_str("\r\n").unwrap();
+                                }
+                                Err(e) => write!(
+                                    uart0_tx as &mut dyn Write<_, Error = _>,
+                                    "error: {:?}\r\nusage: date [[alarm] YYYY-MM-DDTHH:MM:SS]\r\n",
+                                    e
+                                )
+                                .unwrap(),
+                            }
+                        });
+                        b.clear();
+                        return;
+                    }
+
+                    let (action, arg1, arg2) = match command
                         .and_then(|v| match v {
                             "r" => Some(Action::Read),
                             "w" => Some(Action::Write),
@@ -222,6 +253,22 @@ mod app {
         .unwrap();
     }
 
+    #[task(binds = RTC, shared = [calendar], priority = 2)]
+    fn rtc(cx: rtc::Context) {
+        let mut calendar = cx.shared.calendar;
+        if calendar.lock(|calendar| calendar.on_interrupt()) {
+            alarm::spawn().ok();
+        }
+    }
+
+    #[task(shared = [calendar])]
+    fn alarm(cx: alarm::Context) {
+        let mut calendar = cx.shared.calendar;
+        let uart0_tx = unsafe { UART0_TX.as_mut().unwrap() as &mut dyn Write<_, Error = _> };
+        calendar.lock(|calendar| write_now(uart0_tx, calendar));
+        uart0_tx.write_str(" alarm\r\n").unwrap();
+    }
+
     // Only assign dsu to silence an unused warning
     #[idle(shared = [dsu])]
     fn idle(_cx: idle::Context) -> ! {
@@ -14,19 +14,29 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
     }
 }
 
-use atsamd_demo::{clear_line, clear_screen, uart::*};
+use atsamd_demo::{
+    board::{
+        hal::{
+            nvm::{smart_eeprom::SmartEepromMode, Nvm},
+            prelude::*,
+        },
+        pac::ICM,
+        Board,
+    },
+    board_parts, clear_line, clear_screen,
+    compare::Diff,
+    icm::{
+        config::{Configuration, IcmConfig, Interrupt, Memory},
+        console,
+        events::{self, Consumer, Event, Producer, Queue},
+        Sha1, Sha224, Sha256,
+    },
+    sha::{self, Algorithm, PaddedMessage},
+    uart::*,
+};
 
 use core::fmt::Write as _;
 
-use atsamd_hal_clockv1::{
-    clock::GenericClockController,
-    gpio::Pins,
-    hal::serial::Write,
-    icm::*,
-    nvm::{smart_eeprom::SmartEepromMode, Nvm},
-    prelude::*,
-    time::U32Ext,
-};
 use nb::block;
 
 use rtic::app;
@@ -37,10 +47,12 @@ static MESSAGE_REF0: [u32; 16] = [
     0x99999999, 0xaaaaaaaa, 0xbbbbbbbb, 0xcccccccc, 0xdddddddd, 0xeeeeeeee, 0xffffffff, 0x00000000,
 ];
 
-static MESSAGE_REF1: [u32; 16] = [
-    0x80636261, 0x00000000, 0x00000000, 0x00000000, 0x00000000, 0x00000000, 0x00000000, 0x00000000,
-    0x00000000, 0x00000000, 0x00000000, 0x00000000, 0x00000000, 0x00000000, 0x00000000, 0x18000000,
-];
+// Separate block hashed after MESSAGE_REF0 into the same region, through the
+// region's secondary list
+static MESSAGE_REF0_CHUNK: [u32; 16] = [0x5a5a5a5a; 16];
+
+// "abc" with its SHA padding, a single block
+static MESSAGE_REF1: PaddedMessage<1> = PaddedMessage::new(b"abc");
 
 // Expected SHA1 sum result
 static MESSAGE_SHA1_RES: [u32; 8] = [
@@ -54,12 +66,30 @@ static MESSAGE_SHA256_RES: [u32; 8] = [
     0xbf1678ba, 0xeacf018f, 0xde404141, 0x2322ae5d, 0xa36103b0, 0x9c7a1796, 0x61ff10b4, 0xad1500f2,
 ];
 
-static mut HASH: HashArea = HashArea::default();
-static mut ICM_REGION_DESC: Regions = Regions::default();
+/// Copies of the test messages, monitored once every region has been hashed
+pub struct Copies {
+    region0_sha1: [u32; 16],
+    region0_chunk: [u32; 16],
+    region1_sha1: [u32; 16],
+    region2_sha224: [u32; 16],
+    region3_sha256: [u32; 16],
+}
+
+impl Copies {
+    const fn new() -> Self {
+        Self {
+            region0_sha1: [0; 16],
+            region0_chunk: [0; 16],
+            region1_sha1: [0; 16],
+            region2_sha224: [0; 16],
+            region3_sha256: [0; 16],
+        }
+    }
+}
 
 static mut UART0_TX: Option<Uart0Tx> = None;
 
-#[app(device = atsamd_hal_clockv1::pac, peripherals = true, dispatchers = [FREQM])]
+#[app(device = atsamd_demo::board::pac, peripherals = true, dispatchers = [FREQM])]
 mod app {
     use super::*;
 
@@ -68,216 +98,86 @@ mod app {
         uart0_rx: Uart0Rx,
         nvm: Nvm,
         buffer: String,
-        icm: Icm,
+        icm: ICM,
+        icm_config: Option<Configuration>,
+        // Interrupt flags seen last, ISR clears them when read
+        icm_flags: u32,
     }
 
     #[local]
     struct LocalResources {
-        icm_region0: Region<Region0>,
-        icm_region1: Region<Region1>,
-        icm_region2: Region<Region2>,
-        icm_region3: Region<Region3>,
-        message_region0_sha1: [u32; 16],
-        message_region1_sha1: [u32; 16],
-        message_region2_sha224: [u32; 16],
-        message_region3_sha256: [u32; 16],
+        icm_producer: Producer<'static>,
+        icm_consumer: Consumer<'static>,
+        // Modified, then handed to the ICM for good
+        copies: Option<&'static mut Copies>,
+        // The copies the ICM is monitoring
+        monitored: Option<&'static Copies>,
     }
 
-    #[init]
+    #[init(local = [icm_queue: Queue = Queue::new(), copies: Copies = Copies::new()])]
     fn init(cx: init::Context) -> (SharedResources, LocalResources, init::Monotonics()) {
-        let mut device = cx.device;
-
-        let pins = Pins::new(device.PORT);
-
-        let mclk = &mut device.MCLK;
-
-        let mut clocks = GenericClockController::with_external_32kosc(
-            device.GCLK,
-            mclk,
-            &mut device.OSC32KCTRL,
-            &mut device.OSCCTRL,
-            &mut device.NVMCTRL,
-        );
+        let device = cx.device;
 
-        let gclk0 = clocks.gclk0();
+        let Board {
+            console: mut uart0,
+            nvm,
+            mut mclk,
+            ..
+        } = Board::new(board_parts!(device));
 
-        let mut uart0 = Config::new(
-            mclk,
-            device.SERCOM0,
-            Pads::default().rx(pins.pa05).tx(pins.pa04),
-            clocks.sercom0_core(&gclk0).unwrap().freq(),
-        )
-        .baud(115_200.hz(), BaudMode::Arithmetic(Oversampling::Bits16))
-        .enable();
-        uart0.enable_interrupts(Flags::RXC);
-
-        let nvm = Nvm::new(device.NVMCTRL);
         write!(
             &mut uart0 as &mut dyn Write<_, Error = _>,
             "RTIC booted!\r\n"
         )
         .unwrap();
 
+        // Run the hash self-test on the ICM SHA engine before the ICM is
+        // handed to the region monitoring demo below
+        atsamd_demo::icm::install(device.ICM, &mut mclk);
+        sha::selftest::run::<Sha1, Sha224, Sha256, _>(&mut uart0 as &mut dyn Write<_, Error = _>)
+            .ok();
+        if let Err(e) = atsamd_demo::icm::check() {
+            write!(
+                &mut uart0 as &mut dyn Write<_, Error = _>,
+                "warning: ICM SHA engine {:?} error\r\n",
+                e
+            )
+            .unwrap();
+        }
+        let icm = atsamd_demo::icm::release().unwrap();
+
         let (uart0_rx, uart0_tx) = uart0.split();
 
         unsafe {
             UART0_TX.replace(uart0_tx);
         }
 
-        // Enable ICM apb clock
-        // Clock v1
-        mclk.apbcmask.modify(|_, w| w.icm_().set_bit());
-        // Clock v2
-        //tokens.apbs.icm.enable();
-
-        // Create new ICM
-        let mut icm = Icm::new(device.ICM);
-
-        // Reset the ICM, clearing past error states
-        icm.swrst();
-
-        // End of Monitoring is permitted
-        icm.set_eomdis(false);
-        // Write Back is permitted
-        icm.set_wbdis(false);
-        // Secondary List branching is forbidden
-        icm.set_slbdis(false);
-        // Automatic Switch to Compare is disabled
-        icm.set_ascd(false);
-
-        // Test setting user initial hash value
-        icm.set_user_initial_hash_value(&MESSAGE_SHA1_RES);
-
-        // Region Descriptor create a new one with intention of
-        // replacing ICM_REGION_DESC
-        let mut icm_region_desc = Regions::default();
-
-        // Get the interface for Region0 and enable monitoring
-        let icm_region0: Region<Region0> = icm.get_region_handle();
-        icm_region0.enable_monitoring();
-
-        // Setup desired interrupts
-        //
-        // Region Hash Completed
-        icm_region0.set_rhc_int();
-
-        // Region0 raddr
-        icm_region_desc
-            .region0
-            .set_region_address(MESSAGE_REF0.as_ptr());
-
-        // Configure the RCFG
-
-        // Some are default values, just as an example
-
-        // Activate Write back (should be true when comparing memory)
-        icm_region_desc.region0.rcfg.set_cdwbn(false);
-        // Should the ICM controller loop back to DSCR after this region?
-        icm_region_desc.region0.rcfg.set_wrap(false);
-        // Set this as the end of descriptor linked list
-        icm_region_desc.region0.rcfg.set_eom(false);
-        // The RHC flag is set when the field NEXT = 0
-        // in a descriptor of the main or second list
-        icm_region_desc.region0.rcfg.set_rhien(false);
-        // Set Algorithm to SHA1
-        icm_region_desc.region0.rcfg.set_algo(icm_algorithm::SHA1);
-
-        // Get the interface for region1
-        let icm_region1 = icm.get_region1_handle();
-
-        // Enable region monitoring
-        icm_region1.enable_monitoring();
-
-        // Setup desired interrupts
-        //
-        // Region Hash Completed
-        icm_region1.set_rhc_int();
-
-        // Region1 raddr
-        icm_region_desc
-            .region1
-            .set_region_address(MESSAGE_REF1.as_ptr());
-
-        // Configure the RCFG
-        // The RHC flag is set when the field NEXT = 0
-        // in a descriptor of the main or second list
-        icm_region_desc.region1.rcfg.set_rhien(false);
-        // Set Algorithm to SHA1
-        icm_region_desc.region1.rcfg.set_algo(icm_algorithm::SHA1);
-
-        // Get the interface for region2
-        let icm_region2 = icm.get_region2_handle();
-
-        // Enable region monitoring
-        icm_region2.enable_monitoring();
-
-        // Setup desired interrupts
-        //
-        // Region Hash Completed
-        icm_region2.set_rhc_int();
-
-        // Region2 raddr
-        icm_region_desc
-            .region2
-            .set_region_address(MESSAGE_REF1.as_ptr());
-
-        // Configure the RCFG
-        // The RHC flag is set when the field NEXT = 0
-        // in a descriptor of the main or second list
-        icm_region_desc.region2.rcfg.set_rhien(false);
-        // Set Algorithm to SHA224
-        icm_region_desc.region2.rcfg.set_algo(icm_algorithm::SHA224);
-
-        // Get the interface for region3
-        let icm_region3 = icm.get_region3_handle();
-
-        // Enable region monitoring
-        icm_region3.enable_monitoring();
-
-        // Setup desired interrupts
-        //
-        // Region Hash Completed
-        icm_region3.set_rhc_int();
-
-        // Region3 raddr
-        icm_region_desc
-            .region3
-            .set_region_address(MESSAGE_REF1.as_ptr());
-
-        // Configure the RCFG
-        //
-        // Set this as the end of descriptor linked list
-        icm_region_desc.region3.rcfg.set_eom(true);
-        // The RHC flag is set when the field NEXT = 0
-        // in a descriptor of the main or second list
-        icm_region_desc.region3.rcfg.set_rhien(false);
-        // Set Algorithm to SHA256
-        icm_region_desc.region3.rcfg.set_algo(icm_algorithm::SHA256);
-
-        // Safe because Interrupts are disabled in RTIC Init
-        unsafe {
-            // Hash Area
-            // Set HASH addr to the beginning of the Hash area
-            icm.set_hash_addr(&HASH);
-        }
-
-        unsafe {
-            // Move the icm_region_desc into static
-            ICM_REGION_DESC = icm_region_desc;
-            // Set DSCR to the beginning of the region descriptor
-            icm.set_dscr_addr(&ICM_REGION_DESC.region0);
-            // the same but via helper function
-            //ICM_REGION_DESC.region0.set_dscr_addr(&icm);
-        }
+        // Hash the test messages once, writing the digests back, with a
+        // Region Hash Completed interrupt for each of them
+        let icm_config = IcmConfig::new()
+            .region(&MESSAGE_REF0, Algorithm::Sha1)
+            .chunk(&MESSAGE_REF0_CHUNK)
+            .interrupt_on_hash_complete()
+            .region(MESSAGE_REF1.words(), Algorithm::Sha1)
+            .interrupt_on_hash_complete()
+            .region(MESSAGE_REF1.words(), Algorithm::Sha224)
+            .interrupt_on_hash_complete()
+            .region(MESSAGE_REF1.words(), Algorithm::Sha256)
+            .interrupt_on_hash_complete()
+            .build(Memory::take().unwrap())
+            .unwrap();
 
-        // Start the ICM calculation
-        icm.enable();
+        // Start the ICM calculation, the interrupt only queues its status
+        let (icm_producer, icm_consumer) = cx.local.icm_queue.split();
+        icm_config.start(&icm);
 
         // Setup the compare regions
-        let message_region0_sha1 = MESSAGE_REF0;
-        let message_region1_sha1 = MESSAGE_REF1;
-        let message_region2_sha224 = MESSAGE_REF1;
-        let message_region3_sha256 = MESSAGE_REF1;
+        let copies = cx.local.copies;
+        copies.region0_sha1 = MESSAGE_REF0;
+        copies.region0_chunk = MESSAGE_REF0_CHUNK;
+        copies.region1_sha1.copy_from_slice(MESSAGE_REF1.words());
+        copies.region2_sha224 = copies.region1_sha1;
+        copies.region3_sha256 = copies.region1_sha1;
 
         //cortex_m::asm::bkpt();
         (
@@ -286,16 +186,14 @@ mod app {
                 nvm,
                 buffer: heapless::String::new(),
                 icm,
+                icm_config: Some(icm_config),
+                icm_flags: 0,
             },
             LocalResources {
-                icm_region0,
-                icm_region1,
-                icm_region2,
-                icm_region3,
-                message_region0_sha1,
-                message_region1_sha1,
-                message_region2_sha224,
-                message_region3_sha256,
+                icm_producer,
+                icm_consumer,
+                copies: Some(copies),
+                monitored: None,
             },
             init::Monotonics(),
         )
@@ -307,131 +205,121 @@ mod app {
         Write,
     }
 
-    #[task(priority= 3, binds = ICM,
-        shared = [buffer, icm],
-        local = [icm_region0, icm_region1, icm_region2, icm_region3,
-        message_region0_sha1, message_region1_sha1, message_region2_sha224,
-        message_region3_sha256
-        ])]
+    /// Queue the ICM interrupt status for `icm_report`
+    #[task(priority = 3, binds = ICM, shared = [icm, icm_flags], local = [icm_producer])]
     fn icm(cx: icm::Context) {
-        let uart0_tx = unsafe { UART0_TX.as_mut().unwrap() as &mut dyn Write<_, Error = _> };
         let mut icm = cx.shared.icm;
-        let icm_region0 = cx.local.icm_region0;
-        let icm_region1 = cx.local.icm_region1;
-        let icm_region2 = cx.local.icm_region2;
-        let icm_region3 = cx.local.icm_region3;
+        let mut icm_flags = cx.shared.icm_flags;
 
-        uart0_tx.write_str("ICM Interrupt!\r\n").unwrap();
+        // Read, and so clear, the interrupt status
+        let status = icm.lock(|i| events::capture(i, cx.local.icm_producer));
+        icm_flags.lock(|f| *f = status);
 
-        // Get a parseable copy of the interrupt status vector
-        let icminterrupt = icm.lock(|i| i.get_interrupt_status());
-        //cortex_m::asm::bkpt();
-
-        // Check that all hashes has been computed
-        if icminterrupt.get_rhc_int().is_all() {
-            // Use the RHC-mask to toggle between Write Back
-            // and Digest Compare modes
-            if icm_region0.get_rhc_int_mask() {
-                // Disable RHC interrupts
-                icm_region0.disable_rhc_int();
-                icm_region1.disable_rhc_int();
-                icm_region2.disable_rhc_int();
-                icm_region3.disable_rhc_int();
-            }
+        // Fails while a report is pending, which drains the queue anyway
+        icm_report::spawn().ok();
+    }
 
-            uart0_tx
-                .write_str("Region 0: Expected,  Actual - SHA1 (should mismatch)\r\n")
-                .unwrap();
+    /// Report queued ICM events, switch to region monitoring once all regions
+    /// have been hashed
+    #[task(shared = [icm, icm_config],
+        local = [icm_consumer, hashed: u8 = 0, copies, monitored])]
+    fn icm_report(cx: icm_report::Context) {
+        let uart0_tx = unsafe { UART0_TX.as_mut().unwrap() as &mut dyn Write<_, Error = _> };
+        let mut icm = cx.shared.icm;
+        let mut icm_config = cx.shared.icm_config;
 
-            for (index, val) in MESSAGE_SHA1_RES.iter().enumerate() {
-                unsafe {
-                    let cmp = HASH.region0[index];
-                    if *val == cmp {
-                        write!(
-                            uart0_tx as &mut dyn Write<_, Error = _>,
-                            "   Match! {:#010x} {:#010x}\r\n",
-                            *val, cmp
-                        )
-                        .unwrap();
-                    } else {
-                        write!(
-                            uart0_tx as &mut dyn Write<_, Error = _>,
-                            "mismatch! {:#010x} {:#010x}\r\n",
-                            *val, cmp
-                        )
-                        .unwrap();
-                    }
-                }
-            }
-            uart0_tx
-                .write_str("Region 1: Expected,  Actual - SHA1 (should match)\r\n")
-                .unwrap();
-            for (index, val) in MESSAGE_SHA1_RES.iter().enumerate() {
-                unsafe {
-                    let cmp = HASH.region1[index];
-                    if *val == cmp {
-                        write!(
-                            uart0_tx as &mut dyn Write<_, Error = _>,
-                            "   Match! {:#010x} {:#010x}\r\n",
-                            *val, cmp
-                        )
-                        .unwrap();
-                    } else {
-                        write!(
-                            uart0_tx as &mut dyn Write<_, Error = _>,
-                            "mismatch! {:#010x} {:#010x}\r\n",
-                            *val, cmp
-                        )
-                        .unwrap();
-                    }
-                }
-            }
-            uart0_tx
-                .write_str("Region 2: Expected,  Actual - SHA224 (should match)\r\n")
-                .unwrap();
-            for (index, val) in MESSAGE_SHA224_RES.iter().enumerate() {
-                unsafe {
-                    let cmp = HASH.region2[index];
-                    if *val == cmp {
-                        write!(
-                            uart0_tx as &mut dyn Write<_, Error = _>,
-                            "   Match! {:#010x} {:#010x}\r\n",
-                            *val, cmp
-                        )
-                        .unwrap();
-                    } else {
-                        write!(
-                            uart0_tx as &mut dyn Write<_, Error = _>,
-                            "mismatch! {:#010x} {:#010x}\r\n",
-                            *val, cmp
-                        )
-                        .unwrap();
+        while let Some(event) = cx.local.icm_consumer.dequeue() {
+            write!(
+                uart0_tx as &mut dyn Write<_, Error = _>,
+                "ICM: {}\r\n",
+                event
+            )
+            .unwrap();
+            match event {
+                Event::Region(Interrupt::HashCompleted, regions) => *cx.local.hashed |= regions,
+                Event::Region(Interrupt::DigestMismatch, regions) => {
+                    // Only the monitored copies are compared
+                    let copies = match *cx.local.monitored {
+                        Some(copies) => copies,
+                        None => continue,
+                    };
+                    let modified: [(u8, &str, &[u32], &[u32]); 5] = [
+                        (0, "Region 0", &MESSAGE_REF0, &copies.region0_sha1[..]),
+                        (
+                            0,
+                            "Region 0 chunk",
+                            &MESSAGE_REF0_CHUNK,
+                            &copies.region0_chunk[..],
+                        ),
+                        (
+                            1,
+                            "Region 1",
+                            MESSAGE_REF1.words(),
+                            &copies.region1_sha1[..],
+                        ),
+                        (
+                            2,
+                            "Region 2",
+                            MESSAGE_REF1.words(),
+                            &copies.region2_sha224[..],
+                        ),
+                        (
+                            3,
+                            "Region 3",
+                            MESSAGE_REF1.words(),
+                            &copies.region3_sha256[..],
+                        ),
+                    ];
+                    for (region, name, expected, actual) in modified.iter() {
+                        if regions & 1 << region != 0 {
+                            write!(
+                                uart0_tx as &mut dyn Write<_, Error = _>,
+                                "{}: {}",
+                                name,
+                                Diff::new(expected, actual)
+                            )
+                            .unwrap();
+                        }
                     }
                 }
+                _ => {}
             }
+        }
 
-            uart0_tx
-                .write_str("Region 3: Expected,  Actual - SHA256 (should match)\r\n")
+        let dropped = events::dropped();
+        if dropped > 0 {
+            write!(
+                uart0_tx as &mut dyn Write<_, Error = _>,
+                "ICM: {} events dropped\r\n",
+                dropped
+            )
+            .unwrap();
+        }
+
+        // Check that all hashes has been computed
+        if *cx.local.hashed == 0b1111 {
+            *cx.local.hashed = 0;
+
+            let digests = icm_config.lock(|c| {
+                let c = c.as_ref().unwrap();
+                [0, 1, 2, 3].map(|region| c.digest(region).unwrap())
+            });
+
+            let known_answers: [(&str, &[u32]); 4] = [
+                ("SHA1 (should mismatch)", &MESSAGE_SHA1_RES),
+                ("SHA1 (should match)", &MESSAGE_SHA1_RES),
+                ("SHA224 (should match)", &MESSAGE_SHA224_RES),
+                ("SHA256 (should match)", &MESSAGE_SHA256_RES),
+            ];
+            for (region, (name, expected)) in known_answers.iter().enumerate() {
+                write!(
+                    uart0_tx as &mut dyn Write<_, Error = _>,
+                    "Region {} - {}: {}",
+                    region,
+                    name,
+                    Diff::new(expected, &digests[region])
+                )
                 .unwrap();
-            for (index, val) in MESSAGE_SHA256_RES.iter().enumerate() {
-                unsafe {
-                    let cmp = HASH.region3[index];
-                    if *val == cmp {
-                        write!(
-                            uart0_tx as &mut dyn Write<_, Error = _>,
-                            "   Match! {:#010x} {:#010x}\r\n",
-                            *val, cmp
-                        )
-                        .unwrap();
-                    } else {
-                        write!(
-                            uart0_tx as &mut dyn Write<_, Error = _>,
-                            "mismatch! {:#010x} {:#010x}\r\n",
-                            *val, cmp
-                        )
-                        .unwrap();
-                    }
-                }
             }
 
             // Reconfigure ICM to watch and compare memory instead
@@ -440,229 +328,56 @@ mod app {
             uart0_tx
                 .write_str(" Switch to region monitoring mode")
                 .unwrap();
-            icm.lock(|i| i.swrst());
-
-            // Create temporary Region
-            let mut icm_region_desc = Regions::default();
-
-            // Setup region 0 to monitor memory
-            icm_region_desc
-                .region0
-                .set_region_address(cx.local.message_region0_sha1);
-            icm_region_desc
-                .region0
-                .rcfg
-                .reset_region_configuration_to_default();
-            icm_region_desc.region0.rcfg.set_algo(icm_algorithm::SHA1);
-            // Activate Compare Digest (should be true when comparing memory)
-            icm_region_desc.region0.rcfg.set_cdwbn(true);
-            // Digest Mismatch Interrupt Disable (enabled)
-            icm_region_desc.region0.rcfg.set_dmien(false);
-
-            // Set Region Mismatch Interrupt
-            icm_region0.set_rdm_int();
-
-            // Setup region 1 to monitor memory
-            icm_region_desc
-                .region1
-                .set_region_address(cx.local.message_region1_sha1);
-            icm_region_desc
-                .region1
-                .rcfg
-                .reset_region_configuration_to_default();
-            icm_region_desc.region1.rcfg.set_algo(icm_algorithm::SHA1);
-            // Activate Compare Digest (should be true when comparing memory)
-            icm_region_desc.region1.rcfg.set_cdwbn(true);
-            // Digest Mismatch Interrupt Disable (enabled)
-            icm_region_desc.region1.rcfg.set_dmien(false);
-
-            // Set Region Mismatch Interrupt
-            icm_region1.set_rdm_int();
-
-            // Setup region 2 to monitor memory
-            icm_region_desc
-                .region2
-                .set_region_address(cx.local.message_region2_sha224);
-            icm_region_desc
-                .region2
-                .rcfg
-                .reset_region_configuration_to_default();
-            icm_region_desc.region2.rcfg.set_algo(icm_algorithm::SHA224);
-            // Activate Compare Digest (should be true when comparing memory)
-            icm_region_desc.region2.rcfg.set_cdwbn(true);
-            // Digest Mismatch Interrupt Disable (enabled)
-            icm_region_desc.region2.rcfg.set_dmien(false);
-
-            // Set Region Mismatch Interrupt
-            icm_region2.set_rdm_int();
-
-            // Setup region 3 to monitor memory
-            icm_region_desc
-                .region3
-                .set_region_address(cx.local.message_region3_sha256);
-            icm_region_desc
-                .region3
-                .rcfg
-                .reset_region_configuration_to_default();
-            icm_region_desc.region3.rcfg.set_algo(icm_algorithm::SHA256);
-            // Activate Compare Digest (should be true when comparing memory)
-            icm_region_desc.region3.rcfg.set_cdwbn(true);
-            // Digest Mismatch Interrupt Disable (enabled)
-            icm_region_desc.region3.rcfg.set_dmien(false);
-            // Wrap
-            icm_region_desc.region3.rcfg.set_wrap(true);
-
-            // Set Region Mismatch Interrupt
-            icm_region3.set_rdm_int();
-
-            // Modify regions to trigger interrupts
-            uart0_tx.write_str("Manually modify region0\r\n").unwrap();
-            cx.local.message_region0_sha1[3] = 0xDEAD_BEEF;
+            icm.lock(|i| i.ctrl.write(|w| w.swrst().set_bit()));
+
+            // Modify the copies to trigger interrupts
+            let copies = cx.local.copies.take().unwrap();
+            uart0_tx
+                .write_str("Manually modify region0, in its secondary list chunk\r\n")
+                .unwrap();
+            copies.region0_chunk[3] = 0xDEAD_BEEF;
             uart0_tx.write_str("Manually modify region1\r\n").unwrap();
-            cx.local.message_region1_sha1[4] = 0xDEAD_BEEF;
+            copies.region1_sha1[4] = 0xDEAD_BEEF;
             uart0_tx.write_str("Manually modify region2\r\n").unwrap();
-            cx.local.message_region2_sha224[5] = 0xDEAD_BEEF;
+            copies.region2_sha224[5] = 0xDEAD_BEEF;
             uart0_tx.write_str("Manually modify region3\r\n").unwrap();
-            cx.local.message_region3_sha256[6] = 0xDEAD_BEEF;
-
-            // Copy the configured Regions into the static mut ICM is reading
-            unsafe {
-                ICM_REGION_DESC = icm_region_desc;
-            }
-
-            icm.lock(|i| i.enable());
-        } else if icminterrupt.get_rdm_int().is_all() {
-            if icminterrupt
-                .get_rdm_int()
-                .intersects(RegionDigestMismatch::R0)
-            {
-                uart0_tx.write_str("Region0 digest mismatch!\r\n").unwrap();
-                // Disable the interrupt
-                icm_region0.disable_rdm_int();
-
-                uart0_tx.write_str("Region 0: Expected,  Actual\r\n").unwrap();
-                for (index, val) in MESSAGE_REF0.iter().enumerate() {
-                    let cmp = cx.local.message_region0_sha1[index];
-                    if *val == cmp {
-                        write!(
-                            uart0_tx as &mut dyn Write<_, Error = _>,
-                            "   Match! {:#010x} {:#010x}\r\n",
-                            *val, cmp
-                        )
-                        .unwrap();
-                    } else {
-                        write!(
-                            uart0_tx as &mut dyn Write<_, Error = _>,
-                            "mismatch! {:#010x} {:#010x}\r\n",
-                            *val, cmp
-                        )
-                        .unwrap();
-                    }
-                }
-            }
-            if icminterrupt
-                .get_rdm_int()
-                .intersects(RegionDigestMismatch::R1)
-            {
-                uart0_tx.write_str("Region1 digest mismatch!\r\n").unwrap();
-                // Disable the interrupt
-                icm_region1.disable_rdm_int();
-
-                uart0_tx.write_str("Region 1: Expected,  Actual\r\n").unwrap();
-                for (index, val) in MESSAGE_REF1.iter().enumerate() {
-                    let cmp = cx.local.message_region1_sha1[index];
-                    if *val == cmp {
-                        write!(
-                            uart0_tx as &mut dyn Write<_, Error = _>,
-                            "   Match! {:#010x} {:#010x}\r\n",
-                            *val, cmp
-                        )
-                        .unwrap();
-                    } else {
-                        write!(
-                            uart0_tx as &mut dyn Write<_, Error = _>,
-                            "mismatch! {:#010x} {:#010x}\r\n",
-                            *val, cmp
-                        )
-                        .unwrap();
-                    }
-                }
-            }
-            if icminterrupt
-                .get_rdm_int()
-                .intersects(RegionDigestMismatch::R2)
-            {
-                uart0_tx.write_str("Region2 digest mismatch!\r\n").unwrap();
-                // Disable the interrupt
-                icm_region2.disable_rdm_int();
-
-                uart0_tx.write_str("Region 2: Expected,  Actual\r\n").unwrap();
-                for (index, val) in MESSAGE_REF1.iter().enumerate() {
-                    let cmp = cx.local.message_region2_sha224[index];
-                    if *val == cmp {
-                        write!(
-                            uart0_tx as &mut dyn Write<_, Error = _>,
-                            "   Match! {:#010x} {:#010x}\r\n",
-                            *val, cmp
-                        )
-                        .unwrap();
-                    } else {
-                        write!(
-                            uart0_tx as &mut dyn Write<_, Error = _>,
-                            "mismatch! {:#010x} {:#010x}\r\n",
-                            *val, cmp
-                        )
-                        .unwrap();
-                    }
-                }
-            }
-            if icminterrupt
-                .get_rdm_int()
-                .intersects(RegionDigestMismatch::R3)
-            {
-                uart0_tx.write_str("Region3 digest mismatch!\r\n").unwrap();
-                // Disable the interrupt
-                icm_region3.disable_rdm_int();
-
-                uart0_tx.write_str("Region 3: Expected,  Actual\r\n").unwrap();
-                for (index, val) in MESSAGE_REF1.iter().enumerate() {
-                    let cmp = cx.local.message_region3_sha256[index];
-                    //print_compare(uart0_tx, val, cmp);
-                    if *val == cmp {
-                        write!(
-                            uart0_tx as &mut dyn Write<_, Error = _>,
-                            "   Match! {:#010x} {:#010x}\r\n",
-                            *val, cmp
-                        )
-                        .unwrap();
-                    } else {
-                        write!(
-                            uart0_tx as &mut dyn Write<_, Error = _>,
-                            "mismatch! {:#010x} {:#010x}\r\n",
-                            *val, cmp
-                        )
-                        .unwrap();
-                    }
-                }
-            }
-
-            // Get and clear
-            let icminterrupt = icm.lock(|i| i.get_interrupt_status());
+            copies.region3_sha256[6] = 0xDEAD_BEEF;
+
+            // Monitor the copies, comparing against the digests of the
+            // messages just written back, which stay in the hash area
+            let copies: &'static Copies = copies;
+            let memory = icm_config.lock(|c| c.take()).unwrap().into_memory();
+            let config = IcmConfig::new()
+                .region(&copies.region0_sha1, Algorithm::Sha1)
+                .chunk(&copies.region0_chunk)
+                .compare()
+                .interrupt_on_mismatch()
+                .region(&copies.region1_sha1, Algorithm::Sha1)
+                .compare()
+                .interrupt_on_mismatch()
+                .region(&copies.region2_sha224, Algorithm::Sha224)
+                .compare()
+                .interrupt_on_mismatch()
+                .region(&copies.region3_sha256, Algorithm::Sha256)
+                .compare()
+                .interrupt_on_mismatch()
+                .wrap()
+                .build(memory)
+                .unwrap();
+            *cx.local.monitored = Some(copies);
 
-            let rdm_ints = icminterrupt.get_rdm_int();
-            write!(
-                uart0_tx as &mut dyn Write<_, Error = _>,
-                "RDM interrupt vector {:04b}\r\n",
-                rdm_ints
-            )
-            .unwrap();
+            icm.lock(|i| config.start(i));
+            icm_config.lock(|c| *c = Some(config));
         }
     }
 
-    #[task(shared = [buffer, nvm], capacity = 10)]
+    #[task(shared = [buffer, nvm, icm, icm_config, icm_flags], capacity = 10)]
     fn uart_handle(cx: uart_handle::Context, uart_data: UartCommand) {
         let mut buffer = cx.shared.buffer;
         let mut nvm = cx.shared.nvm;
+        let mut icm = cx.shared.icm;
+        let mut icm_config = cx.shared.icm_config;
+        let mut icm_flags = cx.shared.icm_flags;
         let uart0_tx = unsafe { UART0_TX.as_mut().unwrap() as &mut dyn Write<_, Error = _> };
         match uart_data {
             UartCommand::Return => {
@@ -671,8 +386,27 @@ mod app {
                     // custom action start
 
                     let mut iterator = b.split_whitespace();
-                    let (action, arg1, arg2) = match iterator
-                        .next()
+                    let command = iterator.next();
+
+                    if command == Some("icm") {
+                        (&mut icm, &mut icm_config, &mut icm_flags).lock(|icm, config, flags| {
+                            if let Err(e) =
+                                console::execute(iterator, icm, config.as_ref(), *flags, uart0_tx)
+                            {
+                                write!(
+                                    uart0_tx as &mut dyn Write<_, Error = _>,
+                                    "error: {:?}\r\n{}",
+                                    e,
+                                    console::USAGE
+                                )
+                                .unwrap();
+                            }
+                        });
+                        b.clear();
+                        return;
+                    }
+
+                    let (action, arg1, arg2) = match command
                         .and_then(|v| match v {
                             "r" => Some(Action::Read),
                             "w" => Some(Action::Write),
@@ -780,7 +514,3 @@ mod app {
         }
     }
 }
-
-//fn print_compare(val: &u32, cmp: u32) -> Result<(), _> {
-    //Ok(())
-//}
@@ -0,0 +1,71 @@
+//! Board bring-up for every HAL flavor
+//!
+//! [`hal`] and [`pac`] are the HAL selected by the crate features and its
+//! PAC, for the rest of the crate to name them through. [`Board::new`]
+//! brings up the clock tree, the SERCOM0 console (PA04 TX, PA05 RX), NVM and
+//! the DSU with whatever clocking API the HAL has, so the binaries do not
+//! repeat it:
+//!
+//! ```ignore
+//! let mut device = cx.device;
+//! let board = Board::new(board_parts!(device));
+//! ```
+//!
+//! A new HAL variant is one more arm in the `cfg_if!` below.
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "clockv1")] {
+        pub use atsamd_hal_clockv1 as hal;
+        mod clockv1;
+        pub use clockv1::Board;
+    } else if #[cfg(feature = "hal-aes")] {
+        pub use atsamd_hal_aes as hal;
+        mod clockv1;
+        pub use clockv1::Board;
+    } else {
+        pub use atsamd_hal as hal;
+        mod clockv2;
+        pub use clockv2::{Board, BOOT_PLAN, BOOT_PROFILE};
+    }
+}
+
+pub use hal::pac;
+
+/// Console baud rate
+pub const CONSOLE_BAUD: u32 = 115_200;
+
+/// XOSC0 crystal on PA14/PA15
+pub const XOSC0_HZ: u32 = 8_000_000;
+/// XOSC32K crystal on PA00/PA01
+pub const XOSC32K_HZ: u32 = 32_768;
+
+/// Peripherals taken over by [`Board::new`], see [`board_parts!`]
+pub struct Parts {
+    pub port: pac::PORT,
+    pub gclk: pac::GCLK,
+    pub mclk: pac::MCLK,
+    pub osc32kctrl: pac::OSC32KCTRL,
+    pub oscctrl: pac::OSCCTRL,
+    pub nvmctrl: pac::NVMCTRL,
+    pub sercom0: pac::SERCOM0,
+    pub dsu: pac::DSU,
+    pub pac: pac::PAC,
+}
+
+/// Move the [`Parts`] out of `pac::Peripherals`, leaving the rest usable
+#[macro_export]
+macro_rules! board_parts {
+    ($device:ident) => {
+        $crate::board::Parts {
+            port: $device.PORT,
+            gclk: $device.GCLK,
+            mclk: $device.MCLK,
+            osc32kctrl: $device.OSC32KCTRL,
+            oscctrl: $device.OSCCTRL,
+            nvmctrl: $device.NVMCTRL,
+            sercom0: $device.SERCOM0,
+            dsu: $device.DSU,
+            pac: $device.PAC,
+        }
+    };
+}
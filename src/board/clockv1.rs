@@ -0,0 +1,84 @@
+//! Bring-up with the `GenericClockController` clocking of the clockv1 and
+//! hal-aes HALs
+
+use super::{
+    hal::{
+        clock::GenericClockController, dsu::Dsu, gpio::Pins, nvm::Nvm, prelude::*, time::U32Ext,
+    },
+    pac::{MCLK, OSC32KCTRL, OSCCTRL},
+    Parts, CONSOLE_BAUD,
+};
+use crate::uart::{BaudMode, Config, Flags, Oversampling, Pads, Uart0};
+
+pub struct Board {
+    /// SERCOM0 console at [`CONSOLE_BAUD`], with the RXC interrupt enabled
+    pub console: Uart0,
+    pub nvm: Nvm,
+    pub dsu: Dsu,
+    /// Core clock, GCLK0, in Hz
+    pub sysclk_hz: u32,
+    /// Clock generators, GCLK0 runs the core
+    pub clocks: GenericClockController,
+    /// Bus clock gates, for the drivers of other peripherals
+    pub mclk: MCLK,
+    pub oscctrl: OSCCTRL,
+    pub osc32kctrl: OSC32KCTRL,
+}
+
+impl Board {
+    /// Run the core from DPLL0 locked to the external 32 kHz crystal and
+    /// bring up the console, NVM and DSU
+    pub fn new(parts: Parts) -> Self {
+        let Parts {
+            port,
+            gclk,
+            mut mclk,
+            mut osc32kctrl,
+            mut oscctrl,
+            mut nvmctrl,
+            sercom0,
+            dsu,
+            pac,
+        } = parts;
+
+        let pins = Pins::new(port);
+
+        let mut clocks = GenericClockController::with_external_32kosc(
+            gclk,
+            &mut mclk,
+            &mut osc32kctrl,
+            &mut oscctrl,
+            &mut nvmctrl,
+        );
+
+        let gclk0 = clocks.gclk0();
+        let sysclk_hz = gclk0.hz().0;
+
+        let mut console = Config::new(
+            &mclk,
+            sercom0,
+            Pads::default().rx(pins.pa05).tx(pins.pa04),
+            clocks.sercom0_core(&gclk0).unwrap().freq(),
+        )
+        .baud(
+            CONSOLE_BAUD.hz(),
+            BaudMode::Arithmetic(Oversampling::Bits16),
+        )
+        .enable();
+        console.enable_interrupts(Flags::RXC);
+
+        let nvm = Nvm::new(nvmctrl);
+        let dsu = Dsu::new(dsu, &pac).unwrap();
+
+        Self {
+            console,
+            nvm,
+            dsu,
+            sysclk_hz,
+            clocks,
+            mclk,
+            oscctrl,
+            osc32kctrl,
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! Bring-up with the clocking API v2 HAL
+//!
+//! XOSC0 runs from the 8 MHz crystal on PA14/PA15 and feeds DPLL0, which runs
+//! GCLK0 and so the core. GCLK0 is output on PB14. GCLK1 runs from the DFLL,
+//! and the 32 kHz crystal on PA00/PA01 is enabled for the RTC. SERCOM0 is
+//! clocked from GCLK0 for the console.
+//!
+//! The frequencies come from [`BOOT_PROFILE`], solved at compile time. The
+//! bring-up builds that one tree, so a boot profile running the core from
+//! anything but DPLL0 and XOSC0, undivided, or without GCLK1 fails the build.
+//! Should DPLL0 not lock, the core stays on the DFLL48M instead, which
+//! [`failure::status`] records.
+
+use super::{
+    hal::{
+        clock::v2::{
+            dpll::Dpll, gclk, gclk::Gclk1Div, gclkio::GclkOut, pclk::Pclk, por_state, xosc::*,
+            xosc32k::*, Source,
+        },
+        dsu::Dsu,
+        gpio::Pins,
+        nvm::Nvm,
+        time::U32Ext,
+    },
+    pac::MCLK,
+    Parts, CONSOLE_BAUD, XOSC0_HZ,
+};
+use crate::clock::{
+    failure,
+    profile::{self, DpllSetting, Plan, Profile},
+    tree::Reference,
+};
+use crate::uart::{BaudMode, Config, Flags, Oversampling, Pads, Uart0};
+
+/// Clock profile brought up by [`Board::new`]
+pub const BOOT_PROFILE: Profile = profile::CPU_100MHZ;
+
+/// Register values of [`BOOT_PROFILE`]
+pub const BOOT_PLAN: Plan = match BOOT_PROFILE.solve() {
+    Ok(plan) => plan,
+    Err(_) => panic!("boot clock profile without a solution"),
+};
+
+const DPLL0: DpllSetting = match BOOT_PLAN.dpll0 {
+    Some(dpll) if matches!(dpll.reference, Reference::Xosc0) && BOOT_PLAN.gclk0_div == 1 => dpll,
+    _ => panic!("boot clock profile does not run the core from DPLL0 and XOSC0"),
+};
+
+const GCLK1_DIV: u16 = match BOOT_PLAN.gclk1_div {
+    Some(div) => div,
+    None => panic!("boot clock profile without GCLK1"),
+};
+
+pub struct Board {
+    /// SERCOM0 console at [`CONSOLE_BAUD`], with the RXC interrupt enabled
+    pub console: Uart0,
+    pub nvm: Nvm,
+    pub dsu: Dsu,
+    /// Core clock, GCLK0, in Hz
+    pub sysclk_hz: u32,
+}
+
+impl Board {
+    /// Run the core at [`BOOT_PROFILE`] from the 8 MHz crystal and bring up
+    /// the console, NVM and DSU
+    pub fn new(parts: Parts) -> Self {
+        let Parts {
+            port,
+            gclk,
+            mclk,
+            osc32kctrl,
+            oscctrl,
+            mut nvmctrl,
+            sercom0,
+            dsu,
+            pac,
+        } = parts;
+
+        // Get the clock power-on-reset state
+        let (_buses, clocks, tokens) = por_state(oscctrl, osc32kctrl, gclk, mclk, &mut nvmctrl);
+
+        // Get the pins
+        let pins = Pins::new(port);
+
+        // Enable pin PA14 and PA15 as an external source for XOSC0 at 8 MHz
+        let xosc0 = Xosc::from_crystal(
+            tokens.xosc0,
+            pins.pa14,
+            pins.pa15,
+            XOSC0_HZ.hz(),
+            CrystalCurrent::Medium,
+        )
+        .enable();
+
+        // Take DFLL 48 MHz, divide down for Gclk1
+        let (gclk1, dfll) = gclk::Gclk::new(tokens.gclks.gclk1, clocks.dfll);
+        let _gclk1 = gclk1.div(Gclk1Div::Div(GCLK1_DIV)).enable();
+
+        // Configure DPLL0 fed from Xosc0, 8 MHz / (2 * (1 + prediv))
+        let (dpll0, _xosc0) = Dpll::from_xosc0(tokens.dpll0, xosc0, DPLL0.div);
+
+        // Enable external 32k-oscillator. Its handles are dropped, it keeps
+        // running for FREQM and the RTC, see `crate::monotonic`
+        let token = tokens.xosc32k.base;
+        let base = XoscBase::from_crystal(token, pins.pa00, pins.pa01).enable();
+        let (_xosc32k, _base) = Xosc32k::enable(tokens.xosc32k.xosc32k, base);
+
+        // Multiply the reference by LDR + 1 + LDRFRAC / 32
+        let dpll0 = dpll0
+            .set_loop_div(DPLL0.ldr + 1, DPLL0.ldrfrac)
+            .enable()
+            .ok();
+
+        // Change Gclk0 from Dfll to Dpll0 once it locks, MCLK = DPLL0. If it
+        // does not, the core stays on the DFLL, see `failure::status`
+        let (sysclk_hz, pclk_sercom0) = match dpll0 {
+            Some(dpll0) if failure::wait_for_lock() => {
+                let (gclk0, _dfll, _dpll0) = clocks.gclk0.swap(dfll, dpll0);
+
+                // Output Gclk0 on pin PB14
+                let (_gclk_out0, gclk0) =
+                    GclkOut::enable(tokens.gclk_io.gclk_out0, pins.pb14, gclk0, false);
+
+                // SERCOM0 core clock from Gclk0
+                let sysclk_hz = gclk0.freq().0;
+                let (pclk_sercom0, _gclk0) = Pclk::enable(tokens.pclks.sercom0, gclk0);
+                (sysclk_hz, pclk_sercom0)
+            }
+            _ => {
+                failure::fall_back();
+                let gclk0 = clocks.gclk0;
+
+                let (_gclk_out0, gclk0) =
+                    GclkOut::enable(tokens.gclk_io.gclk_out0, pins.pb14, gclk0, false);
+
+                let sysclk_hz = gclk0.freq().0;
+                let (pclk_sercom0, _gclk0) = Pclk::enable(tokens.pclks.sercom0, gclk0);
+                (sysclk_hz, pclk_sercom0)
+            }
+        };
+
+        // SERCOM0 bus clock through its APB token
+        let _apb_sercom0 = tokens.apbs.sercom0.enable();
+
+        // The UART only takes MCLK to enable the bus clock, which the token
+        // did already; MCLK itself went to the clock tree
+        let mclk = unsafe { &*MCLK::ptr() };
+        let mut console = Config::new(
+            mclk,
+            sercom0,
+            Pads::default().rx(pins.pa05).tx(pins.pa04),
+            pclk_sercom0.freq(),
+        )
+        .baud(
+            CONSOLE_BAUD.hz(),
+            BaudMode::Arithmetic(Oversampling::Bits16),
+        )
+        .enable();
+        console.enable_interrupts(Flags::RXC);
+
+        let nvm = Nvm::new(nvmctrl);
+        let dsu = Dsu::new(dsu, &pac).unwrap();
+
+        Self {
+            console,
+            nvm,
+            dsu,
+            sysclk_hz,
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! Wall-clock time on the RTC in clock/calendar mode
+//!
+//! [`Calendar`] runs the RTC in mode 2, counting seconds, minutes, hours,
+//! days, months and years from its 1.024 kHz clock. The date-time type and its
+//! Unix time conversion are in [`datetime`], apart from the hardware so they
+//! can be tested on the host.
+//!
+//! ```ignore
+//! let mut calendar = Calendar::new(device.RTC, RtcClock::Xosc32k);
+//! calendar.set(&"2024-02-29T12:00:00".parse()?)?;
+//! calendar.set_alarm(&"2024-02-29T12:30:00".parse()?)?;
+//!
+//! // RTC interrupt
+//! if calendar.on_interrupt() {
+//!     // ALARM0 matched
+//! }
+//! ```
+//!
+//! The RTC counts on through system resets, only a power-on reset clears it.
+//! There is one RTC, so the calendar and [`crate::monotonic::RtcMonotonic`] do
+//! not go together in one application.
+
+pub mod datetime;
+
+use self::datetime::{DateTime, Error};
+use crate::board::pac::RTC;
+use crate::monotonic::RtcClock;
+
+/// Year of the YEAR field value 0; it has 6 bits, so the RTC runs to 2063
+pub const BASE_YEAR: u16 = 2000;
+/// Last year the RTC can count
+pub const LAST_YEAR: u16 = BASE_YEAR + 63;
+
+const CTRLA_MODE: u16 = 3 << 2;
+const CTRLA_MODE2: u16 = 2 << 2;
+/// CTRLA, 1 Hz from 1.024 kHz, 24 hour, with CLOCK read synchronization
+const CTRLA_SETUP: u16 = 1 << 15 | 0xb << 8 | CTRLA_MODE2;
+const CTRLA_ENABLE: u16 = 1 << 1;
+const SYNCBUSY_CLOCK: u32 = 1 << 3;
+const SYNCBUSY_ALARM0: u32 = 1 << 5;
+const SYNCBUSY_MASK0: u32 = 1 << 11;
+/// INTFLAG, INTENSET and INTENCLR bit of ALARM0
+const INT_ALARM0: u16 = 1 << 8;
+/// MASK0.SEL, match every field from the seconds to the year
+const MASK_YYMMDDHHMMSS: u8 = 6;
+
+pub struct Calendar {
+    rtc: RTC,
+}
+
+impl Calendar {
+    /// Start the RTC as a calendar clocked from `clock`
+    ///
+    /// An RTC already running as a calendar, from before a system reset, keeps
+    /// its time; otherwise it is reset to [`BASE_YEAR`]-01-01T00:00:00. Either
+    /// way the RTC is switched to `clock`, with its 1.024 kHz output turned on.
+    pub fn new(rtc: RTC, clock: RtcClock) -> Self {
+        clock.select(true);
+        let mode2 = rtc.mode2();
+        if mode2.ctrla.read().bits() & (CTRLA_MODE | CTRLA_ENABLE) == CTRLA_MODE2 | CTRLA_ENABLE {
+            return Self { rtc };
+        }
+
+        mode2.ctrla.write(|w| w.swrst().set_bit());
+        while mode2.syncbusy.read().swrst().bit_is_set() {}
+        // Safe, mode 2 with a valid prescaler
+        unsafe {
+            mode2.ctrla.write(|w| w.bits(CTRLA_SETUP));
+            mode2.ctrla.write(|w| w.bits(CTRLA_SETUP | CTRLA_ENABLE));
+        }
+        while mode2.syncbusy.read().enable().bit_is_set() {}
+
+        let start = DateTime::new(BASE_YEAR, 1, 1, 0, 0, 0).unwrap();
+        // Safe, the base date is in range
+        unsafe { mode2.clock.write(|w| w.bits(to_bits(&start))) };
+        while mode2.syncbusy.read().bits() & SYNCBUSY_CLOCK != 0 {}
+        Self { rtc }
+    }
+
+    /// Current date-time
+    ///
+    /// Fails with [`Error::Range`] only if something else wrote the RTC.
+    pub fn now(&self) -> Result<DateTime, Error> {
+        let mode2 = self.rtc.mode2();
+        while mode2.syncbusy.read().bits() & SYNCBUSY_CLOCK != 0 {}
+        from_bits(mode2.clock.read().bits())
+    }
+
+    /// Set the date-time, from [`BASE_YEAR`] to [`LAST_YEAR`]
+    pub fn set(&mut self, now: &DateTime) -> Result<(), Error> {
+        let bits = checked_bits(now)?;
+        let mode2 = self.rtc.mode2();
+        // Safe, the fields are in range
+        unsafe { mode2.clock.write(|w| w.bits(bits)) };
+        while mode2.syncbusy.read().bits() & SYNCBUSY_CLOCK != 0 {}
+        Ok(())
+    }
+
+    /// Raise the RTC interrupt once at `at`, replacing an alarm already set
+    ///
+    /// All fields have to match, an alarm in the past never goes off.
+    pub fn set_alarm(&mut self, at: &DateTime) -> Result<(), Error> {
+        let bits = checked_bits(at)?;
+        let mode2 = self.rtc.mode2();
+        let alarm0 = &mode2.mode2_alarm[0];
+        // Safe, the fields and the mask selection are in range
+        unsafe {
+            alarm0.alarm.write(|w| w.bits(bits));
+            alarm0.mask.write(|w| w.bits(MASK_YYMMDDHHMMSS));
+        }
+        while mode2.syncbusy.read().bits() & (SYNCBUSY_ALARM0 | SYNCBUSY_MASK0) != 0 {}
+        // Safe, only the ALARM0 interrupt and flag
+        unsafe {
+            mode2.intflag.write(|w| w.bits(INT_ALARM0));
+            mode2.intenset.write(|w| w.bits(INT_ALARM0));
+        }
+        Ok(())
+    }
+
+    /// Cancel the alarm
+    pub fn clear_alarm(&mut self) {
+        // Safe, only clears the ALARM0 interrupt
+        unsafe { self.rtc.mode2().intenclr.write(|w| w.bits(INT_ALARM0)) };
+    }
+
+    /// Whether an alarm is set and yet to go off
+    pub fn alarm_pending(&self) -> bool {
+        self.rtc.mode2().intenset.read().bits() & INT_ALARM0 != 0
+    }
+
+    /// Handle the RTC interrupt, true if the alarm went off
+    ///
+    /// The alarm is disabled once it went off.
+    pub fn on_interrupt(&mut self) -> bool {
+        let mode2 = self.rtc.mode2();
+        if mode2.intflag.read().bits() & INT_ALARM0 == 0 {
+            return false;
+        }
+        // Safe, only clears the ALARM0 interrupt and flag
+        unsafe {
+            mode2.intenclr.write(|w| w.bits(INT_ALARM0));
+            mode2.intflag.write(|w| w.bits(INT_ALARM0));
+        }
+        true
+    }
+}
+
+/// CLOCK and ALARM layout: second, minute, hour, day, month, year from bit 0
+fn to_bits(date: &DateTime) -> u32 {
+    date.second() as u32
+        | (date.minute() as u32) << 6
+        | (date.hour() as u32) << 12
+        | (date.day() as u32) << 17
+        | (date.month() as u32) << 22
+        | ((date.year() - BASE_YEAR) as u32) << 26
+}
+
+fn checked_bits(date: &DateTime) -> Result<u32, Error> {
+    if (BASE_YEAR..=LAST_YEAR).contains(&date.year()) {
+        Ok(to_bits(date))
+    } else {
+        Err(Error::Range)
+    }
+}
+
+fn from_bits(bits: u32) -> Result<DateTime, Error> {
+    DateTime::new(
+        BASE_YEAR + (bits >> 26) as u16,
+        (bits >> 22 & 0xf) as u8,
+        (bits >> 17 & 0x1f) as u8,
+        (bits >> 12 & 0x1f) as u8,
+        (bits >> 6 & 0x3f) as u8,
+        (bits & 0x3f) as u8,
+    )
+}
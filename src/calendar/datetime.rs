@@ -0,0 +1,276 @@
+//! Calendar date-time and its conversion to Unix time
+//!
+//! Proleptic Gregorian calendar, UTC, no leap seconds. Dates run from the Unix
+//! epoch, 1970-01-01, to the end of 2105, so Unix time fits a `u32`. Nothing
+//! here touches the hardware; the RTC driver is in [`crate::calendar`].
+
+use core::fmt;
+use core::str::FromStr;
+
+/// First year of a [`DateTime`], the Unix epoch
+pub const MIN_YEAR: u16 = 1970;
+/// Last year of a [`DateTime`]
+pub const MAX_YEAR: u16 = 2105;
+
+const SECONDS_PER_DAY: u32 = 86_400;
+/// Days from 0000-03-01 to 1970-01-01
+const EPOCH_DAYS: u32 = 719_468;
+/// Days in 400 years
+const ERA_DAYS: u32 = 146_097;
+
+/// Layout parsed by [`DateTime::from_str`], `0` stands for a digit
+const FORMAT: &[u8] = b"0000-00-00T00:00:00";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// Not `YYYY-MM-DDTHH:MM:SS`
+    Format,
+    /// A field out of its range, such as February 30th
+    Range,
+}
+
+/// Date and time of day, checked on construction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    year: u16,
+    month: u8,
+    day: u8,
+    hour: u8,
+    minute: u8,
+    second: u8,
+}
+
+pub fn is_leap_year(year: u16) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+/// Days in `month` of `year`, 0 for months outside 1 to 12
+pub fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        _ => 0,
+    }
+}
+
+impl DateTime {
+    pub fn new(
+        year: u16,
+        month: u8,
+        day: u8,
+        hour: u8,
+        minute: u8,
+        second: u8,
+    ) -> Result<Self, Error> {
+        if !(MIN_YEAR..=MAX_YEAR).contains(&year)
+            || day == 0
+            || day > days_in_month(year, month)
+            || hour > 23
+            || minute > 59
+            || second > 59
+        {
+            return Err(Error::Range);
+        }
+        Ok(Self {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        })
+    }
+
+    /// Date-time of `seconds` since the Unix epoch
+    ///
+    /// Times past [`MAX_YEAR`], up to 2106-02-07, are not valid date-times.
+    pub fn from_unix(seconds: u32) -> Result<Self, Error> {
+        // Days since 0000-03-01, in 400 year eras; March first puts the leap
+        // day at the end of the year
+        let days = seconds / SECONDS_PER_DAY + EPOCH_DAYS;
+        let era = days / ERA_DAYS;
+        let day_of_era = days % ERA_DAYS;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_from_march = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+        let month = if month_from_march < 10 {
+            month_from_march + 3
+        } else {
+            month_from_march - 9
+        };
+        let year = era * 400 + year_of_era + (month <= 2) as u32;
+
+        let time = seconds % SECONDS_PER_DAY;
+        if year > MAX_YEAR as u32 {
+            return Err(Error::Range);
+        }
+        Self::new(
+            year as u16,
+            month as u8,
+            day as u8,
+            (time / 3600) as u8,
+            (time / 60 % 60) as u8,
+            (time % 60) as u8,
+        )
+    }
+
+    /// Seconds since the Unix epoch
+    pub fn to_unix(&self) -> u32 {
+        // The inverse of `from_unix`, years start on March first
+        let year = self.year as u32 - (self.month <= 2) as u32;
+        let era = year / 400;
+        let year_of_era = year % 400;
+        let month_from_march = (self.month as u32 + 9) % 12;
+        let day_of_year = (153 * month_from_march + 2) / 5 + self.day as u32 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * ERA_DAYS + day_of_era - EPOCH_DAYS;
+
+        days * SECONDS_PER_DAY
+            + self.hour as u32 * 3600
+            + self.minute as u32 * 60
+            + self.second as u32
+    }
+
+    pub fn year(&self) -> u16 {
+        self.year
+    }
+
+    /// Month, 1 to 12
+    pub fn month(&self) -> u8 {
+        self.month
+    }
+
+    /// Day of the month, from 1
+    pub fn day(&self) -> u8 {
+        self.day
+    }
+
+    pub fn hour(&self) -> u8 {
+        self.hour
+    }
+
+    pub fn minute(&self) -> u8 {
+        self.minute
+    }
+
+    pub fn second(&self) -> u8 {
+        self.second
+    }
+}
+
+/// ISO 8601, `YYYY-MM-DDTHH:MM:SS`
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// Parse the [`Display`](fmt::Display) format back
+impl FromStr for DateTime {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let bytes = s.as_bytes();
+        let matches = bytes.len() == FORMAT.len()
+            && bytes.iter().zip(FORMAT).all(|(&b, &f)| match f {
+                b'0' => b.is_ascii_digit(),
+                _ => b == f,
+            });
+        if !matches {
+            return Err(Error::Format);
+        }
+
+        let number = |start: usize, len: usize| {
+            bytes[start..start + len]
+                .iter()
+                .fold(0, |n, &b| n * 10 + (b - b'0') as u16)
+        };
+        Self::new(
+            number(0, 4),
+            number(5, 2) as u8,
+            number(8, 2) as u8,
+            number(11, 2) as u8,
+            number(14, 2) as u8,
+            number(17, 2) as u8,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leap_years() {
+        assert!(is_leap_year(2024));
+        assert!(is_leap_year(2000));
+        assert!(!is_leap_year(2100));
+        assert!(!is_leap_year(2023));
+        assert_eq!(days_in_month(2024, 2), 29);
+        assert_eq!(days_in_month(2100, 2), 28);
+        assert_eq!(days_in_month(2023, 13), 0);
+    }
+
+    #[test]
+    fn unix_time() {
+        let epoch = DateTime::new(1970, 1, 1, 0, 0, 0).unwrap();
+        assert_eq!(epoch.to_unix(), 0);
+        assert_eq!(DateTime::from_unix(0), Ok(epoch));
+
+        let leap_day = DateTime::new(2024, 2, 29, 12, 34, 56).unwrap();
+        assert_eq!(leap_day.to_unix(), 1_709_210_096);
+        assert_eq!(DateTime::from_unix(1_709_210_096), Ok(leap_day));
+
+        let last = DateTime::new(MAX_YEAR, 12, 31, 23, 59, 59).unwrap();
+        assert_eq!(DateTime::from_unix(last.to_unix()), Ok(last));
+        assert_eq!(DateTime::from_unix(last.to_unix() + 1), Err(Error::Range));
+    }
+
+    #[test]
+    fn every_day_round_trips() {
+        let mut previous = None;
+        for days in 0..DateTime::new(MAX_YEAR, 12, 31, 0, 0, 0).unwrap().to_unix() / 86_400 {
+            let date = DateTime::from_unix(days * 86_400 + 86_399).unwrap();
+            assert_eq!(date.to_unix(), days * 86_400 + 86_399);
+            assert!(previous < Some(date));
+            previous = Some(date);
+        }
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(DateTime::new(2023, 2, 29, 0, 0, 0), Err(Error::Range));
+        assert_eq!(DateTime::new(2023, 0, 1, 0, 0, 0), Err(Error::Range));
+        assert_eq!(DateTime::new(2023, 1, 1, 24, 0, 0), Err(Error::Range));
+        assert_eq!(DateTime::new(1969, 12, 31, 0, 0, 0), Err(Error::Range));
+        assert_eq!(
+            DateTime::new(MAX_YEAR + 1, 1, 1, 0, 0, 0),
+            Err(Error::Range)
+        );
+    }
+
+    #[test]
+    fn parse_and_display() {
+        extern crate std;
+        use std::string::ToString;
+
+        let date: DateTime = "2024-02-29T12:34:56".parse().unwrap();
+        assert_eq!(date, DateTime::new(2024, 2, 29, 12, 34, 56).unwrap());
+        assert_eq!(date.to_string(), "2024-02-29T12:34:56");
+
+        assert_eq!(
+            "2024-02-29 12:34:56".parse::<DateTime>(),
+            Err(Error::Format)
+        );
+        assert_eq!("2024-2-29T12:34:56".parse::<DateTime>(), Err(Error::Format));
+        assert_eq!("2024-02-30T12:34:56".parse::<DateTime>(), Err(Error::Range));
+    }
+}
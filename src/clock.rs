@@ -0,0 +1,14 @@
+//! Clock tree inspection
+//!
+//! [`tree`] reads the clock configuration back from the OSCCTRL, OSC32KCTRL,
+//! GCLK and MCLK registers, whichever HAL flavor set it up. [`freqm`] measures
+//! generators and sources with the FREQM peripheral against that. [`profile`]
+//! describes clock trees by their target frequencies and solves them for the
+//! register values. [`failure`] watches the crystals and moves the core to the
+//! DFLL48M when one fails, and [`switch`] changes profiles at runtime.
+
+pub mod failure;
+pub mod freqm;
+pub mod profile;
+pub mod switch;
+pub mod tree;
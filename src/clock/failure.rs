@@ -0,0 +1,215 @@
+//! Clock failure detection and fallback
+//!
+//! [`enable`] turns on the clock failure detectors (CFD) of XOSC0 and XOSC32K.
+//! The hardware replaces a failed crystal with a safe clock, the DFLL48M
+//! divided down for XOSC0 and OSCULP32K for XOSC32K, but DPLL0 locked to it
+//! does not run at its frequency any more. The interrupt handlers move GCLK0 to
+//! the DFLL48M when it depends on the failed oscillator, see [`fall_back`],
+//! and record the failure in [`status`], so the application can carry on at a
+//! lower speed instead of hanging.
+//!
+//! ```ignore
+//! // OSCCTRL_0 interrupt
+//! if let Some(failure) = failure::on_oscctrl_interrupt() {
+//!     report::spawn(failure).ok();
+//! }
+//! ```
+//!
+//! GCLK0 also clocks the peripherals on it, the console included; see
+//! [`crate::uart::set_console_baud`].
+
+use super::{
+    profile::CPU_MAX_HZ,
+    switch::{self, oscctrl, LOCK_TIMEOUT},
+    tree::{Source, Tree, DFLL_HZ},
+};
+use crate::board::pac::OSC32KCTRL;
+use core::fmt;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// XOSC0 CFD safe clock prescaler, DFLL48M / 2^3 = 6 MHz, below XOSC0
+const CFDPRESC: u32 = 3;
+
+const XOSCCTRL_CFDEN: u32 = 1 << 16;
+
+/// Status bit of the GCLK0 fallback, next to the [`Failure`] bits
+const FALLBACK: u8 = 1 << 7;
+
+/// Failures seen and fallback, see [`Status`]
+static STATUS: AtomicU8 = AtomicU8::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Failure {
+    /// XOSC0 clock failure detected
+    Xosc0,
+    /// XOSC32K clock failure detected
+    Xosc32k,
+    /// DPLL0 did not lock at boot
+    Dpll0Lock,
+}
+
+impl Failure {
+    const fn bit(self) -> u8 {
+        match self {
+            Failure::Xosc0 => 1,
+            Failure::Xosc32k => 1 << 1,
+            Failure::Dpll0Lock => 1 << 2,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Failure::Xosc0 => "XOSC0 failed",
+            Failure::Xosc32k => "XOSC32K failed",
+            Failure::Dpll0Lock => "DPLL0 not locked",
+        }
+    }
+}
+
+/// Clock failures seen since boot
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Status(u8);
+
+impl Status {
+    pub fn failed(self, failure: Failure) -> bool {
+        self.0 & failure.bit() != 0
+    }
+
+    /// GCLK0 was moved to the DFLL48M
+    pub fn fallback(self) -> bool {
+        self.0 & FALLBACK != 0
+    }
+
+    /// No failure seen
+    pub fn is_ok(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_ok() {
+            return f.write_str("ok");
+        }
+        let mut separator = "";
+        for &failure in [Failure::Xosc0, Failure::Xosc32k, Failure::Dpll0Lock].iter() {
+            if self.failed(failure) {
+                write!(f, "{}{}", separator, failure.name())?;
+                separator = ", ";
+            }
+        }
+        if self.fallback() {
+            write!(f, "{}GCLK0 on DFLL", separator)?;
+        }
+        Ok(())
+    }
+}
+
+/// Clock failures seen since boot
+pub fn status() -> Status {
+    Status(STATUS.load(Ordering::Relaxed))
+}
+
+// The failure detectors, their interrupts and the fallback change registers
+// the HAL set up and does not touch again, so they go through the pointers,
+// OSCCTRL through `switch::oscctrl`
+
+fn osc32kctrl() -> &'static crate::board::pac::osc32kctrl::RegisterBlock {
+    unsafe { &*OSC32KCTRL::ptr() }
+}
+
+/// Enable the failure detectors of the running crystals and their interrupts
+///
+/// XOSC0 failures raise `OSCCTRL_0`, XOSC32K failures `OSC32KCTRL`.
+pub fn enable() {
+    let tree = Tree::read();
+    if tree.xosc_ready(0) {
+        // Safe, only adds the CFD enable and prescaler to the running setup
+        oscctrl().xoscctrl[0].modify(|r, w| unsafe {
+            w.bits(r.bits() & !(0xf << 24) | CFDPRESC << 24 | XOSCCTRL_CFDEN)
+        });
+        oscctrl().intflag.write(|w| w.xoscfail0().set_bit());
+        oscctrl().intenset.write(|w| w.xoscfail0().set_bit());
+    }
+    if tree.xosc32k_ready() {
+        osc32kctrl().cfdctrl.modify(|_, w| w.cfden().set_bit());
+        osc32kctrl().intflag.write(|w| w.xosc32kfail().set_bit());
+        osc32kctrl().intenset.write(|w| w.xosc32kfail().set_bit());
+    }
+}
+
+/// Handle an XOSC0 failure, call from the `OSCCTRL_0` interrupt
+///
+/// The interrupt is disabled after a failure, a failed crystal keeps failing.
+pub fn on_oscctrl_interrupt() -> Option<Failure> {
+    if oscctrl().intflag.read().xoscfail0().bit_is_clear() {
+        return None;
+    }
+    oscctrl().intenclr.write(|w| w.xoscfail0().set_bit());
+    oscctrl().intflag.write(|w| w.xoscfail0().set_bit());
+    Some(fail(Failure::Xosc0, Source::Xosc0))
+}
+
+/// Handle an XOSC32K failure, call from the `OSC32KCTRL` interrupt
+///
+/// The interrupt is disabled after a failure, a failed crystal keeps failing.
+pub fn on_osc32kctrl_interrupt() -> Option<Failure> {
+    if osc32kctrl().intflag.read().xosc32kfail().bit_is_clear() {
+        return None;
+    }
+    osc32kctrl().intenclr.write(|w| w.xosc32kfail().set_bit());
+    osc32kctrl().intflag.write(|w| w.xosc32kfail().set_bit());
+    Some(fail(Failure::Xosc32k, Source::Xosc32k))
+}
+
+/// `source` failed, whether or not its failure interrupt has run yet
+pub(crate) fn source_failed(source: Source) -> bool {
+    match source {
+        Source::Xosc0 => {
+            status().failed(Failure::Xosc0) || oscctrl().intflag.read().xoscfail0().bit_is_set()
+        }
+        Source::Xosc32k => {
+            status().failed(Failure::Xosc32k)
+                || osc32kctrl().intflag.read().xosc32kfail().bit_is_set()
+        }
+        _ => false,
+    }
+}
+
+/// Wait for DPLL0 to lock, a failure is recorded if it does not in time
+pub fn wait_for_lock() -> bool {
+    let dpll0 = &oscctrl().dpll[0];
+    for _ in 0..LOCK_TIMEOUT {
+        let status = dpll0.dpllstatus.read();
+        if status.lock().bit_is_set() && status.clkrdy().bit_is_set() {
+            return true;
+        }
+    }
+    record(Failure::Dpll0Lock.bit());
+    false
+}
+
+/// Run GCLK0 from the DFLL48M, undivided
+///
+/// The DFLL is enabled if it is not running, and the flash wait states set for
+/// 48 MHz whatever the core ran at before.
+pub fn fall_back() {
+    switch::set_wait_states(CPU_MAX_HZ);
+    switch::enable_dfll();
+    switch::set_generator(0, Source::Dfll, 1);
+    switch::set_wait_states(DFLL_HZ);
+    record(FALLBACK);
+}
+
+fn record(bits: u8) {
+    STATUS.fetch_or(bits, Ordering::Relaxed);
+}
+
+/// Record `failure` of `source`, and move GCLK0 off it if it depends on it
+fn fail(failure: Failure, source: Source) -> Failure {
+    record(failure.bit());
+    if Tree::read().generator_uses(0, source) {
+        fall_back();
+    }
+    failure
+}
@@ -0,0 +1,226 @@
+//! Frequency meter
+//!
+//! [`Freqm`] counts the cycles of a generator during a number of reference
+//! clock cycles. The reference is a clock source, usually XOSC32K, which the
+//! meter runs through [`REFERENCE_GCLK`]; sources without a generator of their
+//! own, such as XOSC0 or DPLL0 when they only feed a DPLL, are measured
+//! through [`SOURCE_GCLK`]. The boards leave both generators free.
+//!
+//! What a measurement is compared against is read back from the registers
+//! with [`Tree`], so a check does not depend on what the HAL was asked for.
+//!
+//! ```ignore
+//! let mut freqm = Freqm::new(device.FREQM, &mclk, Source::Xosc32k)?;
+//! let measurement = freqm.check(Source::Dpll0)?;
+//! if !measurement.within(TOLERANCE_PPM) {
+//!     write!(tx, "warning: DPLL0 {}\r\n", measurement)?;
+//! }
+//! ```
+
+use super::tree::{Source, Tree, GENERATORS};
+use crate::board::pac::{FREQM, GCLK, MCLK};
+use core::fmt;
+
+/// Generator running the reference clock
+pub const REFERENCE_GCLK: usize = 10;
+/// Generator routing a source to the meter
+pub const SOURCE_GCLK: usize = 11;
+/// Reference cycles of a measurement, the most CFGA.REFNUM takes
+pub const REFNUM: u8 = 255;
+/// Tolerance of the boot self-check, in ppm
+pub const TOLERANCE_PPM: u32 = 1000;
+/// Sources checked at boot
+pub const SELF_CHECK: [Source; 2] = [Source::Dpll0, Source::Xosc0];
+
+/// Peripheral channel of the measured clock
+const CHANNEL_MSR: usize = 5;
+/// Peripheral channel of the reference clock
+const CHANNEL_REF: usize = 6;
+/// Polls of STATUS.BUSY before giving up, far above a measurement of
+/// [`REFNUM`] cycles of a 32 kHz reference at the core clock
+const TIMEOUT: u32 = 10_000_000;
+
+const GENCTRL_GENEN: u32 = 1 << 8;
+const PCHCTRL_CHEN: u32 = 1 << 6;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// Generator number out of range
+    NoGenerator,
+    /// The generator or source is off, or its frequency is unknown
+    Off,
+    /// More than 2^24 cycles counted, the measured clock is too fast
+    Overflow,
+    /// The measurement did not finish, the reference clock stopped
+    Timeout,
+}
+
+/// Measured against configured frequency
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Measurement {
+    /// Frequency worked out from the registers, in Hz
+    pub expected: u32,
+    /// Frequency measured, in Hz
+    pub measured: u32,
+}
+
+impl Measurement {
+    /// Deviation from the expected frequency, in ppm
+    pub fn ppm(&self) -> i32 {
+        if self.expected == 0 {
+            return 0;
+        }
+        let deviation = self.measured as i64 - self.expected as i64;
+        (deviation * 1_000_000 / self.expected as i64) as i32
+    }
+
+    /// Deviation within `tolerance` ppm
+    pub fn within(&self, tolerance: u32) -> bool {
+        self.ppm().unsigned_abs() <= tolerance
+    }
+}
+
+impl fmt::Display for Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} Hz, configured {} Hz, {:+} ppm",
+            self.measured,
+            self.expected,
+            self.ppm()
+        )
+    }
+}
+
+pub struct Freqm {
+    freqm: FREQM,
+    /// Reference frequency in Hz
+    reference_hz: u32,
+}
+
+impl Freqm {
+    /// Enable FREQM, with `reference` as the reference clock
+    ///
+    /// Takes over [`REFERENCE_GCLK`] and [`SOURCE_GCLK`] and the two FREQM
+    /// peripheral channels. Fails if `reference` is not running.
+    pub fn new(freqm: FREQM, mclk: &MCLK, reference: Source) -> Result<Self, Error> {
+        let reference_hz = Tree::read().source_hz(reference).ok_or(Error::Off)?;
+
+        mclk.apbamask.modify(|_, w| w.freqm_().set_bit());
+        freqm.ctrla.write(|w| w.swrst().set_bit());
+        while freqm.syncbusy.read().swrst().bit_is_set() {}
+
+        route_source(REFERENCE_GCLK, reference);
+        route_channel(CHANNEL_REF, REFERENCE_GCLK);
+
+        // Safe, REFNUM takes any value but 0
+        unsafe {
+            freqm.cfga.write(|w| w.bits(REFNUM as u16));
+        }
+
+        Ok(Self {
+            freqm,
+            reference_hz,
+        })
+    }
+
+    /// Measure generator `generator`, in Hz
+    pub fn measure(&mut self, generator: usize) -> Result<u32, Error> {
+        if generator >= GENERATORS {
+            return Err(Error::NoGenerator);
+        }
+        if !Tree::read().generator(generator).enabled {
+            return Err(Error::Off);
+        }
+
+        // The channel must not change while the meter is enabled
+        self.freqm.ctrla.write(|w| w.enable().clear_bit());
+        while self.freqm.syncbusy.read().enable().bit_is_set() {}
+        route_channel(CHANNEL_MSR, generator);
+        self.freqm.ctrla.write(|w| w.enable().set_bit());
+        while self.freqm.syncbusy.read().enable().bit_is_set() {}
+
+        self.freqm.intflag.write(|w| w.done().set_bit());
+        self.freqm.ctrlb.write(|w| w.start().set_bit());
+        let mut polls = 0;
+        while self.freqm.status.read().busy().bit_is_set() {
+            polls += 1;
+            if polls == TIMEOUT {
+                return Err(Error::Timeout);
+            }
+        }
+        if self.freqm.status.read().ovf().bit_is_set() {
+            self.freqm.status.write(|w| w.ovf().set_bit());
+            return Err(Error::Overflow);
+        }
+
+        let cycles = self.freqm.value.read().value().bits() as u64;
+        Ok((cycles * self.reference_hz as u64 / REFNUM as u64) as u32)
+    }
+
+    /// Measure `source` through [`SOURCE_GCLK`], in Hz
+    pub fn measure_source(&mut self, source: Source) -> Result<u32, Error> {
+        route_source(SOURCE_GCLK, source);
+        let result = self.measure(SOURCE_GCLK);
+        disable_generator(SOURCE_GCLK);
+        result
+    }
+
+    /// Measure generator `generator` against its configured frequency
+    pub fn check_generator(&mut self, generator: usize) -> Result<Measurement, Error> {
+        if generator >= GENERATORS {
+            return Err(Error::NoGenerator);
+        }
+        let expected = Tree::read().generator_hz(generator).ok_or(Error::Off)?;
+        Ok(Measurement {
+            expected,
+            measured: self.measure(generator)?,
+        })
+    }
+
+    /// Measure `source` against its configured frequency
+    pub fn check(&mut self, source: Source) -> Result<Measurement, Error> {
+        let expected = Tree::read().source_hz(source).ok_or(Error::Off)?;
+        Ok(Measurement {
+            expected,
+            measured: self.measure_source(source)?,
+        })
+    }
+}
+
+// The generators and channels below are the meter's own, see `Freqm::new`;
+// the HAL does not touch them, so writing through the pointer is fine
+
+fn gclk() -> &'static crate::board::pac::gclk::RegisterBlock {
+    unsafe { &*GCLK::ptr() }
+}
+
+/// Run `generator` from `source`, undivided
+fn route_source(generator: usize, source: Source) {
+    let src = source.bits() as u32;
+    // Safe, DIV 1 and the source number are valid for any generator
+    unsafe {
+        gclk().genctrl[generator].write(|w| w.bits(src | GENCTRL_GENEN | 1 << 16));
+    }
+    while gclk().syncbusy.read().bits() & 1 << (2 + generator) != 0 {}
+}
+
+fn disable_generator(generator: usize) {
+    // Safe, clearing GENEN is always valid
+    unsafe {
+        gclk().genctrl[generator].write(|w| w.bits(0));
+    }
+    while gclk().syncbusy.read().bits() & 1 << (2 + generator) != 0 {}
+}
+
+/// Feed peripheral channel `channel` from `generator`
+fn route_channel(channel: usize, generator: usize) {
+    let pchctrl = &gclk().pchctrl[channel];
+    // Safe, the channel is disabled before its generator changes
+    unsafe {
+        pchctrl.write(|w| w.bits(0));
+        while pchctrl.read().bits() & PCHCTRL_CHEN != 0 {}
+        pchctrl.write(|w| w.bits(generator as u32 | PCHCTRL_CHEN));
+    }
+    while pchctrl.read().bits() & PCHCTRL_CHEN == 0 {}
+}
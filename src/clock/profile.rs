@@ -0,0 +1,370 @@
+//! Clock profiles
+//!
+//! A [`Profile`] says what should run the CPU and at what frequency, and what
+//! GCLK1 should run at. [`Profile::solve`] works out the register values for
+//! it: the XOSC divider and LDR/LDRFRAC loop divider of DPLL0 and the
+//! generator dividers. Only exact solutions are accepted, and since `solve`
+//! is a `const fn`, a profile evaluated in a constant that has none fails the
+//! build:
+//!
+//! ```ignore
+//! const PLAN: Plan = match CPU_120MHZ.solve() {
+//!     Ok(plan) => plan,
+//!     Err(_) => panic!("no clock plan for 120 MHz"),
+//! };
+//! ```
+//!
+//! The shipped profiles are checked that way below.
+
+use super::tree::{Reference, Source, DFLL_HZ};
+use crate::board::{XOSC0_HZ, XOSC32K_HZ};
+
+/// Highest CPU frequency
+pub const CPU_MAX_HZ: u32 = 120_000_000;
+/// DPLL reference frequency range, after the XOSC divider
+pub const DPLL_REF_MIN_HZ: u32 = 32_000;
+pub const DPLL_REF_MAX_HZ: u32 = 3_200_000;
+/// DPLL output frequency range
+pub const DPLL_OUT_MIN_HZ: u32 = 96_000_000;
+pub const DPLL_OUT_MAX_HZ: u32 = 200_000_000;
+
+/// Largest GENCTRL.DIV of GCLK0 and of GCLK1, which has a 16 bit field
+const GCLK0_DIV_MAX: u32 = 255;
+const GCLK1_DIV_MAX: u32 = 65_535;
+/// Largest DPLLCTRLB.DIV
+const XOSC_DIV_MAX: u32 = 2047;
+/// Largest DPLLRATIO.LDR
+const LDR_MAX: u32 = 8191;
+
+/// What runs the CPU, through GCLK0
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cpu {
+    /// DPLL0 locked to `reference`, XOSC0 or XOSC32K, divided down to `hz`
+    Dpll0 { reference: Reference, hz: u32 },
+    /// DFLL48M in open loop, divided down to `hz`
+    Dfll { hz: u32 },
+    /// XOSC32K, divided down to `hz`
+    Xosc32k { hz: u32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Profile {
+    pub name: &'static str,
+    pub cpu: Cpu,
+    /// GCLK1 frequency, from the DFLL; `None` leaves GCLK1 off
+    pub gclk1_hz: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// CPU frequency zero or above [`CPU_MAX_HZ`]
+    CpuFrequency,
+    /// DPLL0 reference other than XOSC0 or XOSC32K
+    Reference,
+    /// No XOSC divider, loop divider and GCLK0 divider give the CPU frequency
+    /// exactly, with DPLL0 in its range
+    Dpll,
+    /// The CPU frequency is not the source divided by a GCLK0 divider
+    Gclk0Divider,
+    /// The GCLK1 frequency is not the DFLL divided by a GCLK1 divider
+    Gclk1Divider,
+}
+
+/// DPLL setup
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DpllSetting {
+    pub reference: Reference,
+    /// XOSC reference divider, DPLLCTRLB.DIV, the reference is divided by
+    /// 2 * (DIV + 1)
+    pub div: u16,
+    /// Loop divider ratio integer part, DPLLRATIO.LDR, the reference is
+    /// multiplied by LDR + 1 + LDRFRAC / 32
+    pub ldr: u16,
+    /// Loop divider ratio fractional part in 32ths, DPLLRATIO.LDRFRAC
+    pub ldrfrac: u8,
+    /// Output frequency in Hz
+    pub hz: u32,
+}
+
+/// Register values of a profile
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Plan {
+    /// DPLL0 setup, if the CPU runs from it
+    pub dpll0: Option<DpllSetting>,
+    /// GCLK0 source and divider
+    pub gclk0_source: Source,
+    pub gclk0_div: u16,
+    /// GCLK1 divider, from the DFLL
+    pub gclk1_div: Option<u16>,
+    /// CPU frequency in Hz
+    pub cpu_hz: u32,
+}
+
+pub const CPU_120MHZ: Profile = Profile {
+    name: "120 MHz",
+    cpu: Cpu::Dpll0 {
+        reference: Reference::Xosc0,
+        hz: 120_000_000,
+    },
+    gclk1_hz: Some(2_000_000),
+};
+
+pub const CPU_100MHZ: Profile = Profile {
+    name: "100 MHz",
+    cpu: Cpu::Dpll0 {
+        reference: Reference::Xosc0,
+        hz: 100_000_000,
+    },
+    gclk1_hz: Some(2_000_000),
+};
+
+pub const CPU_48MHZ: Profile = Profile {
+    name: "48 MHz",
+    cpu: Cpu::Dfll { hz: 48_000_000 },
+    gclk1_hz: Some(2_000_000),
+};
+
+/// Core from the 32 kHz crystal, DFLL and DPLLs unused
+pub const LOW_POWER_32KHZ: Profile = Profile {
+    name: "32 kHz low power",
+    cpu: Cpu::Xosc32k { hz: XOSC32K_HZ },
+    gclk1_hz: None,
+};
+
+pub const PROFILES: [Profile; 4] = [CPU_120MHZ, CPU_100MHZ, CPU_48MHZ, LOW_POWER_32KHZ];
+
+// Every shipped profile has a solution
+const _: () = {
+    let mut index = 0;
+    while index < PROFILES.len() {
+        if PROFILES[index].solve().is_err() {
+            panic!("shipped clock profile without a solution");
+        }
+        index += 1;
+    }
+};
+
+impl Profile {
+    /// Register values for this profile
+    pub const fn solve(&self) -> Result<Plan, Error> {
+        let cpu_hz = match self.cpu {
+            Cpu::Dpll0 { hz, .. } | Cpu::Dfll { hz } | Cpu::Xosc32k { hz } => hz,
+        };
+        if cpu_hz == 0 || cpu_hz > CPU_MAX_HZ {
+            return Err(Error::CpuFrequency);
+        }
+
+        let (dpll0, gclk0_source, gclk0_div) = match self.cpu {
+            Cpu::Dpll0 { reference, hz } => match solve_dpll(reference, hz) {
+                Ok((dpll, div)) => (Some(dpll), Source::Dpll0, div),
+                Err(e) => return Err(e),
+            },
+            Cpu::Dfll { hz } => match divider(DFLL_HZ, hz, GCLK0_DIV_MAX) {
+                Some(div) => (None, Source::Dfll, div),
+                None => return Err(Error::Gclk0Divider),
+            },
+            Cpu::Xosc32k { hz } => match divider(XOSC32K_HZ, hz, GCLK0_DIV_MAX) {
+                Some(div) => (None, Source::Xosc32k, div),
+                None => return Err(Error::Gclk0Divider),
+            },
+        };
+
+        let gclk1_div = match self.gclk1_hz {
+            Some(hz) => match divider(DFLL_HZ, hz, GCLK1_DIV_MAX) {
+                Some(div) => Some(div),
+                None => return Err(Error::Gclk1Divider),
+            },
+            None => None,
+        };
+
+        Ok(Plan {
+            dpll0,
+            gclk0_source,
+            gclk0_div,
+            gclk1_div,
+            cpu_hz,
+        })
+    }
+}
+
+/// Divider from `source_hz` down to exactly `hz`, at most `max`
+const fn divider(source_hz: u32, hz: u32, max: u32) -> Option<u16> {
+    if hz == 0 || source_hz % hz != 0 || source_hz / hz > max {
+        None
+    } else {
+        Some((source_hz / hz) as u16)
+    }
+}
+
+/// DPLL0 setup and GCLK0 divider for a CPU frequency of `hz`
+///
+/// The smallest GCLK0 divider that brings the DPLL into its range wins, then
+/// the highest reference frequency.
+const fn solve_dpll(reference: Reference, hz: u32) -> Result<(DpllSetting, u16), Error> {
+    let (input_hz, xosc) = match reference {
+        Reference::Xosc0 => (XOSC0_HZ, true),
+        Reference::Xosc32k => (XOSC32K_HZ, false),
+        _ => return Err(Error::Reference),
+    };
+
+    let mut gclk0_div = 1;
+    while gclk0_div <= GCLK0_DIV_MAX {
+        let out_hz = hz as u64 * gclk0_div as u64;
+        if out_hz > DPLL_OUT_MAX_HZ as u64 {
+            break;
+        }
+        if out_hz >= DPLL_OUT_MIN_HZ as u64 {
+            if let Some(dpll) = solve_ratio(reference, input_hz, xosc, out_hz as u32) {
+                return Ok((dpll, gclk0_div as u16));
+            }
+        }
+        gclk0_div += 1;
+    }
+    Err(Error::Dpll)
+}
+
+/// DPLL setup giving exactly `out_hz` from `input_hz`
+///
+/// XOSC references go through the DPLL's XOSC divider, XOSC32K is used as is.
+const fn solve_ratio(
+    reference: Reference,
+    input_hz: u32,
+    xosc: bool,
+    out_hz: u32,
+) -> Option<DpllSetting> {
+    // XOSC32K goes to the DPLL undivided, so takes a single pass
+    let last = if xosc { XOSC_DIV_MAX } else { 0 };
+    let mut div = 0;
+    while div <= last {
+        let factor = if xosc { 2 * (div + 1) } else { 1 };
+        let ref_hz = input_hz / factor;
+        if ref_hz < DPLL_REF_MIN_HZ {
+            break;
+        }
+
+        // Ratio in 32ths, LDR + 1 + LDRFRAC / 32
+        let ratio_32 = out_hz as u64 * 32;
+        if input_hz % factor == 0 && ref_hz <= DPLL_REF_MAX_HZ && ratio_32 % ref_hz as u64 == 0 {
+            let ratio_32 = ratio_32 / ref_hz as u64;
+            let ldr = ratio_32 / 32 - 1;
+            if ldr <= LDR_MAX as u64 {
+                return Some(DpllSetting {
+                    reference,
+                    div: div as u16,
+                    ldr: ldr as u16,
+                    ldrfrac: (ratio_32 % 32) as u8,
+                    hz: out_hz,
+                });
+            }
+        }
+        div += 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_profiles() {
+        // 8 MHz / 4 = 2 MHz reference
+        let plan = CPU_120MHZ.solve().unwrap();
+        let dpll = plan.dpll0.unwrap();
+        assert_eq!((dpll.div, dpll.ldr, dpll.ldrfrac), (1, 59, 0));
+        assert_eq!((plan.gclk0_source, plan.gclk0_div), (Source::Dpll0, 1));
+        assert_eq!(plan.gclk1_div, Some(24));
+
+        let dpll = CPU_100MHZ.solve().unwrap().dpll0.unwrap();
+        assert_eq!(
+            (dpll.div, dpll.ldr, dpll.ldrfrac, dpll.hz),
+            (1, 49, 0, 100_000_000)
+        );
+
+        let plan = CPU_48MHZ.solve().unwrap();
+        assert_eq!(
+            (plan.dpll0, plan.gclk0_source, plan.gclk0_div),
+            (None, Source::Dfll, 1)
+        );
+
+        let plan = LOW_POWER_32KHZ.solve().unwrap();
+        assert_eq!((plan.gclk0_source, plan.gclk0_div), (Source::Xosc32k, 1));
+        assert_eq!((plan.gclk1_div, plan.cpu_hz), (None, 32_768));
+    }
+
+    #[test]
+    fn dpll_below_its_range() {
+        // 60 MHz needs the DPLL at 120 MHz and GCLK0 / 2
+        let profile = Profile {
+            cpu: Cpu::Dpll0 {
+                reference: Reference::Xosc0,
+                hz: 60_000_000,
+            },
+            ..CPU_120MHZ
+        };
+        let plan = profile.solve().unwrap();
+        assert_eq!(plan.gclk0_div, 2);
+        assert_eq!(plan.dpll0.unwrap().hz, 120_000_000);
+    }
+
+    #[test]
+    fn fractional_ratio() {
+        // 2 MHz * (50 + 8/32) = 100.5 MHz
+        let profile = Profile {
+            cpu: Cpu::Dpll0 {
+                reference: Reference::Xosc0,
+                hz: 100_500_000,
+            },
+            ..CPU_100MHZ
+        };
+        let dpll = profile.solve().unwrap().dpll0.unwrap();
+        assert_eq!((dpll.div, dpll.ldr, dpll.ldrfrac), (1, 49, 8));
+    }
+
+    #[test]
+    fn xosc32k_reference() {
+        // 32768 Hz * 3000
+        let profile = Profile {
+            cpu: Cpu::Dpll0 {
+                reference: Reference::Xosc32k,
+                hz: 98_304_000,
+            },
+            ..CPU_100MHZ
+        };
+        let dpll = profile.solve().unwrap().dpll0.unwrap();
+        assert_eq!((dpll.div, dpll.ldr, dpll.ldrfrac), (0, 2999, 0));
+    }
+
+    #[test]
+    fn impossible() {
+        let dpll = |reference, hz| Profile {
+            cpu: Cpu::Dpll0 { reference, hz },
+            ..CPU_100MHZ
+        };
+        assert_eq!(
+            dpll(Reference::Xosc0, 150_000_000).solve(),
+            Err(Error::CpuFrequency)
+        );
+        assert_eq!(
+            dpll(Reference::Gclk, 100_000_000).solve(),
+            Err(Error::Reference)
+        );
+        // Not a multiple of 32768 / 32
+        assert_eq!(
+            dpll(Reference::Xosc32k, 100_000_000).solve(),
+            Err(Error::Dpll)
+        );
+
+        let dfll = Profile {
+            cpu: Cpu::Dfll { hz: 7_000_000 },
+            ..CPU_48MHZ
+        };
+        assert_eq!(dfll.solve(), Err(Error::Gclk0Divider));
+
+        let gclk1 = Profile {
+            gclk1_hz: Some(5_000_000),
+            ..CPU_48MHZ
+        };
+        assert_eq!(gclk1.solve(), Err(Error::Gclk1Divider));
+    }
+}
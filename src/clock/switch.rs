@@ -0,0 +1,230 @@
+//! Runtime clock profile switching
+//!
+//! [`apply`] moves the running clock tree to another [`Profile`]: GCLK0 goes
+//! to the DFLL48M while DPLL0 is set up again, then to the profile's source,
+//! with the flash wait states raised before and lowered after. GCLK1 follows
+//! the profile, and DPLL0 and the DFLL are turned off once nothing uses them.
+//!
+//! The switch runs with interrupts masked, so a [`super::failure`] fallback
+//! cannot land halfway through it and be undone. A crystal failure that
+//! happens meanwhile is seen before GCLK0 moves onto it, and handled by the
+//! failure interrupt once the switch returns.
+//!
+//! This goes around the HAL, whose clock types keep describing the boot tree,
+//! and their `freq()` values with them. Anything timed from GCLK0 has to be set
+//! up again, such as the console with [`crate::uart::set_console_baud`] and a
+//! [`crate::monotonic::SysTickMonotonic`] with its `rescale`; the
+//! [`crate::monotonic::RtcMonotonic`] does not depend on the core clock.
+//!
+//! ```ignore
+//! let cpu_hz = switch::apply(&profile::CPU_48MHZ)?;
+//! uart::set_console_baud(cpu_hz);
+//! SysTickMonotonic::rescale(cpu_hz);
+//! ```
+
+use super::{
+    failure,
+    profile::{self, DpllSetting, Profile},
+    tree::{Reference, Source, Tree, DFLL_HZ, GENERATORS},
+};
+use crate::board::pac::{GCLK, NVMCTRL, OSCCTRL};
+use cortex_m::interrupt;
+
+/// Polls of the DPLL0 lock before giving up
+pub(crate) const LOCK_TIMEOUT: u32 = 1_000_000;
+
+/// Highest CPU frequency of each flash wait state count, NVMCTRL.CTRLA.RWS
+const WAIT_STATES: [u32; 5] = [24_000_000, 51_000_000, 77_000_000, 101_000_000, 119_000_000];
+
+const GENCTRL_SRC: u32 = 0xf;
+const GENCTRL_GENEN: u32 = 1 << 8;
+const GENCTRL_DIVSEL: u32 = 1 << 12;
+const GENCTRL_DIV: u32 = 0xffff << 16;
+const DPLLSYNCBUSY_ENABLE: u32 = 1 << 1;
+const DPLLSYNCBUSY_RATIO: u32 = 1 << 2;
+const NVMCTRL_RWS: u16 = 0xf << 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// The profile has no solution
+    Profile(profile::Error),
+    /// A source the profile needs is not running
+    SourceOff(Source),
+    /// A source the profile needs has failed, the core was left on the
+    /// DFLL48M if the switch had started
+    Failed(Source),
+    /// DPLL0 also runs other generators
+    Dpll0InUse,
+    /// DPLL0 did not lock, the core was left on the DFLL48M
+    Lock,
+}
+
+/// Switch the clock tree to `profile`, returns the new CPU frequency
+///
+/// The tree is left as it was on errors found before the switch starts; if
+/// DPLL0 does not lock or its source fails, the core stays on the DFLL48M.
+pub fn apply(profile: &Profile) -> Result<u32, Error> {
+    interrupt::free(|_| switch_to(profile))
+}
+
+fn switch_to(profile: &Profile) -> Result<u32, Error> {
+    let plan = profile.solve().map_err(Error::Profile)?;
+    let tree = Tree::read();
+
+    // Everything the new tree needs has to run already, but the DFLL
+    let needed = match plan.dpll0 {
+        Some(dpll) => match dpll.reference {
+            Reference::Xosc0 => Some(Source::Xosc0),
+            Reference::Xosc32k => Some(Source::Xosc32k),
+            _ => None,
+        },
+        None if plan.gclk0_source == Source::Xosc32k => Some(Source::Xosc32k),
+        None => None,
+    };
+    if let Some(source) = needed {
+        if tree.source_hz(source).is_none() {
+            return Err(Error::SourceOff(source));
+        }
+        if failure::source_failed(source) {
+            return Err(Error::Failed(source));
+        }
+    }
+    if plan.dpll0.is_some()
+        && (1..GENERATORS).any(|index| tree.generator_uses(index, Source::Dpll0))
+    {
+        return Err(Error::Dpll0InUse);
+    }
+
+    let current_hz = tree.cpu_hz().unwrap_or(profile::CPU_MAX_HZ);
+    set_wait_states(current_hz.max(plan.cpu_hz).max(DFLL_HZ));
+
+    enable_dfll();
+    set_generator(0, Source::Dfll, 1);
+
+    if let Some(dpll) = plan.dpll0 {
+        if !configure_dpll0(&dpll) {
+            set_wait_states(DFLL_HZ);
+            return Err(Error::Lock);
+        }
+    }
+    // The failure interrupts wait for the switch to finish
+    if let Some(source) = needed.filter(|&source| failure::source_failed(source)) {
+        set_wait_states(DFLL_HZ);
+        return Err(Error::Failed(source));
+    }
+    set_generator(0, plan.gclk0_source, plan.gclk0_div);
+
+    match plan.gclk1_div {
+        Some(div) => set_generator(1, Source::Dfll, div),
+        None => disable_generator(1),
+    }
+
+    // Stop what the new tree does not use
+    let tree = Tree::read();
+    let unused = |source| !(0..GENERATORS).any(|index| tree.generator_uses(index, source));
+    if unused(Source::Dpll0) {
+        disable_dpll0();
+    }
+    if unused(Source::Dfll) {
+        disable_dfll();
+    }
+
+    set_wait_states(plan.cpu_hz);
+    Ok(plan.cpu_hz)
+}
+
+// GCLK0, GCLK1, DPLL0, the DFLL and the flash wait states change under the
+// HAL, see the module documentation, so they go through the pointers
+
+fn gclk() -> &'static crate::board::pac::gclk::RegisterBlock {
+    unsafe { &*GCLK::ptr() }
+}
+
+pub(crate) fn oscctrl() -> &'static crate::board::pac::oscctrl::RegisterBlock {
+    unsafe { &*OSCCTRL::ptr() }
+}
+
+/// Run generator `index` from `source` divided by `div`
+pub(crate) fn set_generator(index: usize, source: Source, div: u16) {
+    // Safe, the solver only gives valid sources and dividers
+    gclk().genctrl[index].modify(|r, w| unsafe {
+        w.bits(
+            r.bits() & !(GENCTRL_SRC | GENCTRL_DIVSEL | GENCTRL_DIV)
+                | source.bits() as u32
+                | GENCTRL_GENEN
+                | (div as u32) << 16,
+        )
+    });
+    while gclk().syncbusy.read().bits() & 1 << (2 + index) != 0 {}
+}
+
+fn disable_generator(index: usize) {
+    // Safe, clearing GENEN is always valid
+    gclk().genctrl[index].modify(|r, w| unsafe { w.bits(r.bits() & !GENCTRL_GENEN) });
+    while gclk().syncbusy.read().bits() & 1 << (2 + index) != 0 {}
+}
+
+pub(crate) fn enable_dfll() {
+    let oscctrl = oscctrl();
+    if oscctrl.dfllctrla.read().enable().bit_is_clear() {
+        oscctrl.dfllctrla.modify(|_, w| w.enable().set_bit());
+        while oscctrl.status.read().dfllrdy().bit_is_clear() {}
+    }
+}
+
+fn disable_dfll() {
+    oscctrl().dfllctrla.modify(|_, w| w.enable().clear_bit());
+}
+
+fn disable_dpll0() {
+    let dpll0 = &oscctrl().dpll[0];
+    dpll0.dpllctrla.modify(|_, w| w.enable().clear_bit());
+    while dpll0.dpllsyncbusy.read().bits() & DPLLSYNCBUSY_ENABLE != 0 {}
+}
+
+/// Set DPLL0 up as `dpll` and wait for it to lock
+fn configure_dpll0(dpll: &DpllSetting) -> bool {
+    disable_dpll0();
+
+    let refclk = match dpll.reference {
+        Reference::Xosc32k => 1,
+        Reference::Xosc0 => 2,
+        Reference::Xosc1 => 3,
+        _ => 0,
+    };
+    let dpll0 = &oscctrl().dpll[0];
+    // Safe, the solver keeps the dividers in their fields
+    unsafe {
+        dpll0
+            .dpllctrlb
+            .write(|w| w.bits(refclk << 5 | (dpll.div as u32) << 16));
+        dpll0
+            .dpllratio
+            .write(|w| w.bits(dpll.ldr as u32 | (dpll.ldrfrac as u32) << 16));
+    }
+    while dpll0.dpllsyncbusy.read().bits() & DPLLSYNCBUSY_RATIO != 0 {}
+    dpll0.dpllctrla.modify(|_, w| w.enable().set_bit());
+    while dpll0.dpllsyncbusy.read().bits() & DPLLSYNCBUSY_ENABLE != 0 {}
+
+    for _ in 0..LOCK_TIMEOUT {
+        let status = dpll0.dpllstatus.read();
+        if status.lock().bit_is_set() && status.clkrdy().bit_is_set() {
+            return true;
+        }
+    }
+    false
+}
+
+/// Flash wait states for a CPU frequency of `cpu_hz`
+pub(crate) fn set_wait_states(cpu_hz: u32) {
+    let rws = WAIT_STATES
+        .iter()
+        .position(|&max_hz| cpu_hz <= max_hz)
+        .unwrap_or(WAIT_STATES.len()) as u16;
+    // Safe, RWS takes 0 to 15
+    unsafe {
+        (*NVMCTRL::ptr())
+            .ctrla
+            .modify(|r, w| w.bits(r.bits() & !NVMCTRL_RWS | rws << 8));
+    }
+}
@@ -0,0 +1,572 @@
+//! Clock tree read back from the registers
+//!
+//! [`Tree::read`] takes a snapshot of OSCCTRL, OSC32KCTRL, GCLK and MCLK, and
+//! everything else decodes that snapshot: which oscillators run, how the DPLLs
+//! are set up and whether they are locked, what feeds each generator and which
+//! generator each peripheral channel uses. Frequencies are worked out from the
+//! board crystals and the register values, not from what the HAL was asked
+//! for.
+
+use crate::board::{
+    pac::{GCLK, MCLK, OSC32KCTRL, OSCCTRL},
+    XOSC0_HZ, XOSC32K_HZ,
+};
+use core::fmt;
+
+/// Generic clock generators
+pub const GENERATORS: usize = 12;
+/// Peripheral channels
+pub const CHANNELS: usize = 48;
+/// DFLL48M frequency in open loop mode
+pub const DFLL_HZ: u32 = 48_000_000;
+
+/// Peripheral channel of the DFLL reference
+const CHANNEL_DFLL: usize = 0;
+/// Peripheral channel of the DPLL0 reference, DPLL1 is next
+const CHANNEL_DPLL0: usize = 1;
+
+/// Peripheral channel names, by index
+const CHANNEL_NAMES: [&str; CHANNELS] = [
+    "OSCCTRL_DFLL48",
+    "OSCCTRL_FDPLL0",
+    "OSCCTRL_FDPLL1",
+    "OSCCTRL_FDPLL0_32K",
+    "EIC",
+    "FREQM_MSR",
+    "FREQM_REF",
+    "SERCOM0_CORE",
+    "SERCOM1_CORE",
+    "TC0_TC1",
+    "USB",
+    "EVSYS0",
+    "EVSYS1",
+    "EVSYS2",
+    "EVSYS3",
+    "EVSYS4",
+    "EVSYS5",
+    "EVSYS6",
+    "EVSYS7",
+    "EVSYS8",
+    "EVSYS9",
+    "EVSYS10",
+    "EVSYS11",
+    "SERCOM2_CORE",
+    "SERCOM3_CORE",
+    "TCC0_TCC1",
+    "TC2_TC3",
+    "CAN0",
+    "CAN1",
+    "TCC2_TCC3",
+    "TC4_TC5",
+    "PDEC",
+    "AC",
+    "CCL",
+    "SERCOM4_CORE",
+    "SERCOM5_CORE",
+    "SERCOM6_CORE",
+    "SERCOM7_CORE",
+    "TCC4",
+    "TC6_TC7",
+    "ADC0",
+    "ADC1",
+    "DAC",
+    "I2S0",
+    "I2S1",
+    "SDHC0",
+    "SDHC1",
+    "CM4_TRACE",
+];
+
+/// Generator source, GENCTRL.SRC
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    Xosc0,
+    Xosc1,
+    GclkIn,
+    Gclk1,
+    OscUlp32k,
+    Xosc32k,
+    Dfll,
+    Dpll0,
+    Dpll1,
+    Reserved(u8),
+}
+
+impl Source {
+    pub fn from_bits(bits: u8) -> Self {
+        match bits {
+            0 => Source::Xosc0,
+            1 => Source::Xosc1,
+            2 => Source::GclkIn,
+            3 => Source::Gclk1,
+            4 => Source::OscUlp32k,
+            5 => Source::Xosc32k,
+            6 => Source::Dfll,
+            7 => Source::Dpll0,
+            8 => Source::Dpll1,
+            bits => Source::Reserved(bits),
+        }
+    }
+
+    /// GENCTRL.SRC value
+    pub fn bits(self) -> u8 {
+        match self {
+            Source::Xosc0 => 0,
+            Source::Xosc1 => 1,
+            Source::GclkIn => 2,
+            Source::Gclk1 => 3,
+            Source::OscUlp32k => 4,
+            Source::Xosc32k => 5,
+            Source::Dfll => 6,
+            Source::Dpll0 => 7,
+            Source::Dpll1 => 8,
+            Source::Reserved(bits) => bits,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Source::Xosc0 => "XOSC0",
+            Source::Xosc1 => "XOSC1",
+            Source::GclkIn => "GCLK_IN",
+            Source::Gclk1 => "GCLK1",
+            Source::OscUlp32k => "OSCULP32K",
+            Source::Xosc32k => "XOSC32K",
+            Source::Dfll => "DFLL",
+            Source::Dpll0 => "DPLL0",
+            Source::Dpll1 => "DPLL1",
+            Source::Reserved(_) => "reserved",
+        }
+    }
+}
+
+/// DPLL reference, DPLLCTRLB.REFCLK
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reference {
+    /// The DPLL's peripheral channel
+    Gclk,
+    Xosc32k,
+    /// XOSC0, divided by 2 * (DIV + 1)
+    Xosc0,
+    /// XOSC1, divided by 2 * (DIV + 1)
+    Xosc1,
+    Reserved(u8),
+}
+
+impl Reference {
+    pub fn from_bits(bits: u8) -> Self {
+        match bits {
+            0 => Reference::Gclk,
+            1 => Reference::Xosc32k,
+            2 => Reference::Xosc0,
+            3 => Reference::Xosc1,
+            bits => Reference::Reserved(bits),
+        }
+    }
+}
+
+/// Registers of one DPLL
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DpllRegisters {
+    pub ctrla: u8,
+    pub ratio: u32,
+    pub ctrlb: u32,
+    pub status: u32,
+}
+
+/// Decoded DPLL setup
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dpll {
+    pub enabled: bool,
+    pub locked: bool,
+    pub reference: Reference,
+    /// XOSC reference divider, DPLLCTRLB.DIV
+    pub div: u16,
+    /// Loop divider ratio integer part, DPLLRATIO.LDR
+    pub ldr: u16,
+    /// Loop divider ratio fractional part in 32ths, DPLLRATIO.LDRFRAC
+    pub ldrfrac: u8,
+}
+
+/// Decoded generator setup
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Generator {
+    pub enabled: bool,
+    pub source: Source,
+    /// Division factor, from GENCTRL.DIV and DIVSEL
+    pub divider: u32,
+    /// Output enabled on the GCLK_IO pin
+    pub output: bool,
+}
+
+/// Register snapshot
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tree {
+    pub oscctrl_status: u32,
+    pub xoscctrl: [u32; 2],
+    pub dfllctrla: u8,
+    pub dfllctrlb: u8,
+    pub dfllmul: u32,
+    pub dpll: [DpllRegisters; 2],
+    pub osc32kctrl_status: u32,
+    pub xosc32k: u16,
+    pub genctrl: [u32; GENERATORS],
+    pub pchctrl: [u32; CHANNELS],
+    pub cpudiv: u8,
+}
+
+impl Default for Tree {
+    fn default() -> Self {
+        Self {
+            oscctrl_status: 0,
+            xoscctrl: [0; 2],
+            dfllctrla: 0,
+            dfllctrlb: 0,
+            dfllmul: 0,
+            dpll: [DpllRegisters::default(); 2],
+            osc32kctrl_status: 0,
+            xosc32k: 0,
+            genctrl: [0; GENERATORS],
+            pchctrl: [0; CHANNELS],
+            cpudiv: 0,
+        }
+    }
+}
+
+impl Tree {
+    /// Read the clock registers
+    ///
+    /// Only reads, so it is fine while the HAL owns the peripherals.
+    pub fn read() -> Self {
+        // Safe, the registers are only read
+        let (oscctrl, osc32kctrl, gclk, mclk) = unsafe {
+            (
+                &*OSCCTRL::ptr(),
+                &*OSC32KCTRL::ptr(),
+                &*GCLK::ptr(),
+                &*MCLK::ptr(),
+            )
+        };
+
+        let mut tree = Self {
+            oscctrl_status: oscctrl.status.read().bits(),
+            dfllctrla: oscctrl.dfllctrla.read().bits(),
+            dfllctrlb: oscctrl.dfllctrlb.read().bits(),
+            dfllmul: oscctrl.dfllmul.read().bits(),
+            osc32kctrl_status: osc32kctrl.status.read().bits(),
+            xosc32k: osc32kctrl.xosc32k.read().bits(),
+            cpudiv: mclk.cpudiv.read().bits(),
+            ..Self::default()
+        };
+        for (value, register) in tree.xoscctrl.iter_mut().zip(oscctrl.xoscctrl.iter()) {
+            *value = register.read().bits();
+        }
+        for (value, dpll) in tree.dpll.iter_mut().zip(oscctrl.dpll.iter()) {
+            *value = DpllRegisters {
+                ctrla: dpll.dpllctrla.read().bits(),
+                ratio: dpll.dpllratio.read().bits(),
+                ctrlb: dpll.dpllctrlb.read().bits(),
+                status: dpll.dpllstatus.read().bits(),
+            };
+        }
+        for (value, register) in tree.genctrl.iter_mut().zip(gclk.genctrl.iter()) {
+            *value = register.read().bits();
+        }
+        for (value, register) in tree.pchctrl.iter_mut().zip(gclk.pchctrl.iter()) {
+            *value = register.read().bits();
+        }
+        tree
+    }
+
+    /// XOSC `index` is enabled and ready
+    pub fn xosc_ready(&self, index: usize) -> bool {
+        self.xoscctrl[index] & 1 << 1 != 0 && self.oscctrl_status & 1 << index != 0
+    }
+
+    /// XOSC32K is enabled and ready
+    pub fn xosc32k_ready(&self) -> bool {
+        self.xosc32k & 1 << 1 != 0 && self.osc32kctrl_status & 1 != 0
+    }
+
+    pub fn dfll_enabled(&self) -> bool {
+        self.dfllctrla & 1 << 1 != 0
+    }
+
+    /// DFLL in closed loop mode, multiplying its reference channel
+    pub fn dfll_closed_loop(&self) -> bool {
+        self.dfllctrlb & 1 != 0
+    }
+
+    pub fn dpll(&self, index: usize) -> Dpll {
+        let registers = &self.dpll[index];
+        Dpll {
+            enabled: registers.ctrla & 1 << 1 != 0,
+            locked: registers.status & 1 != 0,
+            reference: Reference::from_bits((registers.ctrlb >> 5 & 0b111) as u8),
+            div: (registers.ctrlb >> 16 & 0x7ff) as u16,
+            ldr: (registers.ratio & 0x1fff) as u16,
+            ldrfrac: (registers.ratio >> 16 & 0x1f) as u8,
+        }
+    }
+
+    pub fn generator(&self, index: usize) -> Generator {
+        let genctrl = self.genctrl[index];
+        let div = genctrl >> 16;
+        let divider = if genctrl & 1 << 12 != 0 {
+            // DIVSEL, 2^(DIV + 1)
+            1u32.checked_shl(div + 1).unwrap_or(0)
+        } else {
+            div.max(1)
+        };
+        Generator {
+            enabled: genctrl & 1 << 8 != 0,
+            source: Source::from_bits((genctrl & 0xf) as u8),
+            divider,
+            output: genctrl & 1 << 11 != 0,
+        }
+    }
+
+    /// Generator feeding peripheral channel `index`, if the channel is enabled
+    pub fn channel(&self, index: usize) -> Option<usize> {
+        let pchctrl = self.pchctrl[index];
+        if pchctrl & 1 << 6 != 0 {
+            Some((pchctrl & 0xf) as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Frequency of `source`, `None` if it is off or unknown
+    pub fn source_hz(&self, source: Source) -> Option<u32> {
+        self.source_hz_within(source, GENERATORS)
+    }
+
+    /// Frequency of generator `index`, `None` if it is off or unknown
+    pub fn generator_hz(&self, index: usize) -> Option<u32> {
+        self.generator_hz_within(index, GENERATORS)
+    }
+
+    /// Frequency of DPLL `index`, `None` if it is off, unlocked or unknown
+    pub fn dpll_hz(&self, index: usize) -> Option<u32> {
+        self.dpll_hz_within(index, GENERATORS)
+    }
+
+    /// CPU clock, GCLK0 divided by MCLK.CPUDIV
+    pub fn cpu_hz(&self) -> Option<u32> {
+        Some(self.generator_hz(0)? / self.cpudiv.max(1) as u32)
+    }
+
+    /// Generator `index` runs from `source`, directly, through GCLK1 or
+    /// through a DPLL or the DFLL referenced to it
+    pub fn generator_uses(&self, index: usize, source: Source) -> bool {
+        self.generator_uses_within(index, source, GENERATORS)
+    }
+
+    // Generators can feed DPLLs and GCLK1 which feed generators again, `depth`
+    // bounds the search in case of a loop
+    fn source_hz_within(&self, source: Source, depth: usize) -> Option<u32> {
+        match source {
+            Source::Xosc0 if self.xosc_ready(0) => Some(XOSC0_HZ),
+            Source::Xosc32k if self.xosc32k_ready() => Some(XOSC32K_HZ),
+            Source::OscUlp32k => Some(XOSC32K_HZ),
+            Source::Dfll if self.dfll_enabled() => {
+                if self.dfll_closed_loop() {
+                    let reference = self.channel_hz_within(CHANNEL_DFLL, depth)?;
+                    Some(reference * (self.dfllmul & 0xffff))
+                } else {
+                    Some(DFLL_HZ)
+                }
+            }
+            Source::Dpll0 => self.dpll_hz_within(0, depth),
+            Source::Dpll1 => self.dpll_hz_within(1, depth),
+            Source::Gclk1 => self.generator_hz_within(1, depth),
+            _ => None,
+        }
+    }
+
+    fn generator_hz_within(&self, index: usize, depth: usize) -> Option<u32> {
+        let generator = self.generator(index);
+        if !generator.enabled || generator.divider == 0 || depth == 0 {
+            return None;
+        }
+        Some(self.source_hz_within(generator.source, depth - 1)? / generator.divider)
+    }
+
+    fn generator_uses_within(&self, index: usize, source: Source, depth: usize) -> bool {
+        let generator = self.generator(index);
+        if !generator.enabled || depth == 0 {
+            return false;
+        }
+        let from = generator.source;
+        if from == source {
+            return true;
+        }
+        let channel_uses = |channel| match self.channel(channel) {
+            Some(generator) => self.generator_uses_within(generator, source, depth - 1),
+            None => false,
+        };
+        match from {
+            Source::Gclk1 => self.generator_uses_within(1, source, depth - 1),
+            Source::Dfll if self.dfll_closed_loop() => channel_uses(CHANNEL_DFLL),
+            Source::Dpll0 | Source::Dpll1 => {
+                let index = if from == Source::Dpll0 { 0 } else { 1 };
+                match self.dpll(index).reference {
+                    Reference::Gclk => channel_uses(CHANNEL_DPLL0 + index),
+                    Reference::Xosc32k => source == Source::Xosc32k,
+                    Reference::Xosc0 => source == Source::Xosc0,
+                    Reference::Xosc1 => source == Source::Xosc1,
+                    Reference::Reserved(_) => false,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    fn channel_hz_within(&self, index: usize, depth: usize) -> Option<u32> {
+        self.generator_hz_within(self.channel(index)?, depth)
+    }
+
+    fn dpll_hz_within(&self, index: usize, depth: usize) -> Option<u32> {
+        let dpll = self.dpll(index);
+        if !dpll.enabled || !dpll.locked {
+            return None;
+        }
+        let xosc_divider = 2 * (dpll.div as u32 + 1);
+        let reference = match dpll.reference {
+            Reference::Gclk => self.channel_hz_within(CHANNEL_DPLL0 + index, depth)?,
+            Reference::Xosc32k => self.source_hz_within(Source::Xosc32k, depth)?,
+            Reference::Xosc0 => self.source_hz_within(Source::Xosc0, depth)? / xosc_divider,
+            _ => return None,
+        };
+        // f = f_ref * (LDR + 1 + LDRFRAC / 32)
+        let ratio_32 = (dpll.ldr as u64 + 1) * 32 + dpll.ldrfrac as u64;
+        Some((reference as u64 * ratio_32 / 32) as u32)
+    }
+}
+
+/// Frequency in Hz, or `?` when unknown
+struct Hz(Option<u32>);
+
+impl fmt::Display for Hz {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(hz) => write!(f, "{} Hz", hz),
+            None => f.write_str("? Hz"),
+        }
+    }
+}
+
+fn on_off(on: bool) -> &'static str {
+    if on {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+impl fmt::Display for Tree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for index in 0..2 {
+            let xoscctrl = self.xoscctrl[index];
+            write!(f, "XOSC{}: {}", index, on_off(xoscctrl & 1 << 1 != 0))?;
+            if xoscctrl & 1 << 1 != 0 {
+                write!(
+                    f,
+                    ", {}, {}",
+                    if xoscctrl & 1 << 2 != 0 {
+                        "crystal"
+                    } else {
+                        "external clock"
+                    },
+                    if self.xosc_ready(index) {
+                        "ready"
+                    } else {
+                        "not ready"
+                    },
+                )?;
+                if self.oscctrl_status & 1 << (2 + index) != 0 {
+                    f.write_str(", failed")?;
+                }
+            }
+            f.write_str("\r\n")?;
+        }
+
+        write!(f, "XOSC32K: {}", on_off(self.xosc32k & 1 << 1 != 0))?;
+        if self.xosc32k & 1 << 1 != 0 {
+            write!(
+                f,
+                ", {}",
+                if self.xosc32k_ready() {
+                    "ready"
+                } else {
+                    "not ready"
+                }
+            )?;
+            if self.osc32kctrl_status & 1 << 2 != 0 {
+                f.write_str(", failed")?;
+            }
+        }
+        f.write_str("\r\n")?;
+
+        write!(f, "DFLL: {}", on_off(self.dfll_enabled()))?;
+        if self.dfll_enabled() {
+            if self.dfll_closed_loop() {
+                write!(f, ", closed loop x{}", self.dfllmul & 0xffff)?;
+            } else {
+                f.write_str(", open loop")?;
+            }
+            write!(f, ", {}", Hz(self.source_hz(Source::Dfll)))?;
+        }
+        f.write_str("\r\n")?;
+
+        for index in 0..2 {
+            let dpll = self.dpll(index);
+            write!(f, "DPLL{}: {}", index, on_off(dpll.enabled))?;
+            if dpll.enabled {
+                match dpll.reference {
+                    Reference::Gclk => match self.channel(CHANNEL_DPLL0 + index) {
+                        Some(generator) => write!(f, ", ref GCLK{}", generator)?,
+                        None => f.write_str(", ref GCLK (channel off)")?,
+                    },
+                    Reference::Xosc32k => f.write_str(", ref XOSC32K")?,
+                    Reference::Xosc0 => write!(f, ", ref XOSC0 / {}", 2 * (dpll.div + 1))?,
+                    Reference::Xosc1 => write!(f, ", ref XOSC1 / {}", 2 * (dpll.div + 1))?,
+                    Reference::Reserved(bits) => write!(f, ", ref reserved {}", bits)?,
+                }
+                write!(
+                    f,
+                    ", LDR {} LDRFRAC {}/32, {}, {}",
+                    dpll.ldr,
+                    dpll.ldrfrac,
+                    if dpll.locked { "locked" } else { "unlocked" },
+                    Hz(self.dpll_hz(index)),
+                )?;
+            }
+            f.write_str("\r\n")?;
+        }
+
+        for index in 0..GENERATORS {
+            let generator = self.generator(index);
+            if !generator.enabled {
+                continue;
+            }
+            write!(
+                f,
+                "GCLK{}: {} / {}, {}{}\r\n",
+                index,
+                generator.source.name(),
+                generator.divider,
+                Hz(self.generator_hz(index)),
+                if generator.output { ", output" } else { "" },
+            )?;
+        }
+        write!(f, "CPU: GCLK0 / {}, {}\r\n", self.cpudiv, Hz(self.cpu_hz()))?;
+
+        for (index, name) in CHANNEL_NAMES.iter().enumerate() {
+            if let Some(generator) = self.channel(index) {
+                write!(f, "{:>2} {:<18} GCLK{}\r\n", index, name, generator)?;
+            }
+        }
+        Ok(())
+    }
+}
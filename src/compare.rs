@@ -0,0 +1,175 @@
+//! Expected against actual data reports
+//!
+//! [`Diff`] compares two word or byte slices, such as a digest read from the
+//! ICM hash area against a known answer, and formats a compact report: a
+//! summary line with the number of differing elements and the first of them,
+//! then an `index: expected actual` row for each difference only.
+//!
+//! ```ignore
+//! write!(tx, "{}", Diff::new(&EXPECTED, &digest))?;
+//! ```
+
+use core::fmt;
+
+/// Difference rows printed, the rest are only counted
+pub const MAX_ROWS: usize = 8;
+
+/// Element of the compared slices
+pub trait Element: Copy + PartialEq + fmt::LowerHex {
+    /// Hex digits of one element
+    const DIGITS: usize;
+    /// Element name in the summary, plural
+    const NAME: &'static str;
+}
+
+impl Element for u8 {
+    const DIGITS: usize = 2;
+    const NAME: &'static str = "bytes";
+}
+
+impl Element for u32 {
+    const DIGITS: usize = 8;
+    const NAME: &'static str = "words";
+}
+
+/// Comparison of `expected` and `actual`
+///
+/// Slices of different length differ in every element past the shorter one.
+#[derive(Debug, Clone, Copy)]
+pub struct Diff<'a, T> {
+    expected: &'a [T],
+    actual: &'a [T],
+}
+
+impl<'a, T: Element> Diff<'a, T> {
+    pub fn new(expected: &'a [T], actual: &'a [T]) -> Self {
+        Self { expected, actual }
+    }
+
+    /// Elements compared, the length of the longer slice
+    pub fn len(&self) -> usize {
+        self.expected.len().max(self.actual.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Indexes of the differing elements, in order
+    pub fn mismatches(&self) -> impl Iterator<Item = usize> + 'a {
+        let (expected, actual) = (self.expected, self.actual);
+        (0..self.len()).filter(move |&index| expected.get(index) != actual.get(index))
+    }
+
+    pub fn first_mismatch(&self) -> Option<usize> {
+        self.mismatches().next()
+    }
+
+    /// Number of differing elements
+    pub fn count(&self) -> usize {
+        self.mismatches().count()
+    }
+
+    pub fn is_match(&self) -> bool {
+        self.first_mismatch().is_none()
+    }
+}
+
+impl<T: Element> fmt::Display for Diff<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let first = match self.first_mismatch() {
+            Some(first) => first,
+            None => return write!(f, "match, {} {}\r\n", self.len(), T::NAME),
+        };
+        let count = self.count();
+        write!(
+            f,
+            "{} of {} {} differ, first at {}\r\n",
+            count,
+            self.len(),
+            T::NAME,
+            first
+        )?;
+
+        for index in self.mismatches().take(MAX_ROWS) {
+            write!(f, "{:>5}: ", index)?;
+            cell(f, self.expected.get(index))?;
+            f.write_str(" ")?;
+            cell(f, self.actual.get(index))?;
+            f.write_str("\r\n")?;
+        }
+        if count > MAX_ROWS {
+            write!(f, "  ... {} more\r\n", count - MAX_ROWS)?;
+        }
+        Ok(())
+    }
+}
+
+/// One element in hex, dashes past the end of its slice
+fn cell<T: Element>(f: &mut fmt::Formatter, value: Option<&T>) -> fmt::Result {
+    match value {
+        Some(value) => write!(f, "{:01$x}", value, T::DIGITS),
+        None => write!(f, "{:-<1$}", "", T::DIGITS),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::string::ToString;
+
+    #[test]
+    fn matching() {
+        let diff = Diff::new(&[1u32, 2], &[1, 2]);
+        assert!(diff.is_match());
+        assert_eq!(diff.to_string(), "match, 2 words\r\n");
+        assert_eq!(Diff::<u8>::new(&[], &[]).to_string(), "match, 0 bytes\r\n");
+    }
+
+    #[test]
+    fn words() {
+        let diff = Diff::new(&[0x0123_4567u32, 2, 3], &[0x0123_4567, 0xdead_beef, 3]);
+        assert_eq!((diff.count(), diff.first_mismatch()), (1, Some(1)));
+        assert_eq!(
+            diff.to_string(),
+            "1 of 3 words differ, first at 1\r\n    1: 00000002 deadbeef\r\n"
+        );
+    }
+
+    #[test]
+    fn bytes() {
+        let diff = Diff::new(&[0x0au8, 0xb0, 0xff], &[0x0a, 0x0b, 0xfe]);
+        assert_eq!(
+            diff.to_string(),
+            "2 of 3 bytes differ, first at 1\r\n    1: b0 0b\r\n    2: ff fe\r\n"
+        );
+    }
+
+    #[test]
+    fn unequal_lengths() {
+        let diff = Diff::new(&[1u8, 2, 3], &[1, 5]);
+        assert_eq!(diff.len(), 3);
+        assert_eq!(
+            diff.to_string(),
+            "2 of 3 bytes differ, first at 1\r\n    1: 02 05\r\n    2: 03 --\r\n"
+        );
+        assert_eq!(
+            Diff::new(&[7u32], &[7, 8]).to_string(),
+            "1 of 2 words differ, first at 1\r\n    1: -------- 00000008\r\n"
+        );
+    }
+
+    #[test]
+    fn rows_limited() {
+        let diff = Diff::new(&[0u32; 10], &[1; 10]);
+        assert_eq!(diff.count(), 10);
+        let report = diff.to_string();
+        assert_eq!(report.lines().count(), 1 + MAX_ROWS + 1);
+        assert!(
+            report.starts_with("10 of 10 words differ, first at 0\r\n    0: 00000000 00000001\r\n")
+        );
+        assert!(report.ends_with("    7: 00000000 00000001\r\n  ... 2 more\r\n"));
+    }
+}
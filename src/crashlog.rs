@@ -0,0 +1,132 @@
+//! Crash log kept in RAM across resets
+//!
+//! The log lives in the `.uninit` section, which the runtime leaves alone at
+//! start-up, so entries recorded before a system reset can be read back
+//! afterwards. A magic word tells a valid log from power-on garbage. The last
+//! [`ENTRIES`] entries are kept.
+
+use core::mem::MaybeUninit;
+use core::ptr::{addr_of, addr_of_mut};
+use cortex_m::interrupt;
+use heapless::Vec;
+
+/// Entries kept, older ones are overwritten
+pub const ENTRIES: usize = 8;
+
+/// Marks an initialized log
+const MAGIC: u32 = 0x6c6f_6721;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    /// Panic, data is the source line
+    Panic,
+    /// ICM digest mismatch, data is the region mask
+    IcmMismatch,
+    /// ICM bus error, data is the region mask
+    IcmBusError,
+}
+
+impl Kind {
+    fn from_raw(raw: u32) -> Option<Self> {
+        match raw {
+            1 => Some(Kind::Panic),
+            2 => Some(Kind::IcmMismatch),
+            3 => Some(Kind::IcmBusError),
+            _ => None,
+        }
+    }
+
+    fn raw(self) -> u32 {
+        match self {
+            Kind::Panic => 1,
+            Kind::IcmMismatch => 2,
+            Kind::IcmBusError => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Entry {
+    pub kind: Kind,
+    pub data: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RawEntry {
+    kind: u32,
+    data: u32,
+}
+
+#[repr(C)]
+struct Log {
+    magic: u32,
+    /// Entries recorded since the log was cleared
+    total: u32,
+    entries: [RawEntry; ENTRIES],
+}
+
+#[link_section = ".uninit.CRASHLOG"]
+static mut LOG: MaybeUninit<Log> = MaybeUninit::uninit();
+
+/// The log, cleared first if it does not hold the magic word
+///
+/// # Safety
+///
+/// Must be called inside a critical section.
+unsafe fn log() -> &'static mut Log {
+    let log = LOG.as_mut_ptr();
+    if core::ptr::read_volatile(addr_of!((*log).magic)) != MAGIC {
+        addr_of_mut!(*log).write(Log {
+            magic: MAGIC,
+            total: 0,
+            entries: [RawEntry { kind: 0, data: 0 }; ENTRIES],
+        });
+    }
+    &mut *log
+}
+
+/// Append an entry
+pub fn record(kind: Kind, data: u32) {
+    interrupt::free(|_| {
+        // Safe inside the critical section
+        let log = unsafe { log() };
+        log.entries[log.total as usize % ENTRIES] = RawEntry {
+            kind: kind.raw(),
+            data,
+        };
+        log.total = log.total.wrapping_add(1);
+    })
+}
+
+/// Number of entries recorded since the log was cleared, including
+/// overwritten ones
+pub fn total() -> u32 {
+    interrupt::free(|_| unsafe { log() }.total)
+}
+
+/// Kept entries, oldest first
+pub fn entries() -> Vec<Entry, ENTRIES> {
+    interrupt::free(|_| {
+        // Safe inside the critical section
+        let log = unsafe { log() };
+        let kept = (log.total as usize).min(ENTRIES);
+        (log.total as usize - kept..log.total as usize)
+            .filter_map(|index| {
+                let raw = log.entries[index % ENTRIES];
+                Kind::from_raw(raw.kind).map(|kind| Entry {
+                    kind,
+                    data: raw.data,
+                })
+            })
+            .collect()
+    })
+}
+
+pub fn clear() {
+    interrupt::free(|_| {
+        // Safe inside the critical section
+        let log = unsafe { log() };
+        log.total = 0;
+    })
+}
@@ -0,0 +1,241 @@
+//! AES known-answer checks and helpers shared by the crypto demos
+//!
+//! Everything in here is generic over the RustCrypto `cipher` traits, so the
+//! same vectors can be run against the hardware backend and against the
+//! software `aes` crate.
+
+pub mod console;
+#[cfg(feature = "hal-aes")]
+pub mod dma;
+pub mod drbg;
+pub mod keystore;
+pub mod selftest;
+
+use cipher::{
+    consts::U16, generic_array::GenericArray, BlockCipher, BlockDecrypt, BlockEncrypt,
+    FromBlockCipher, NewBlockCipher, StreamCipher,
+};
+use cmac::{Cmac, Mac, NewMac};
+
+/// Block cipher usable with every helper in this module
+///
+/// Implemented for the AES-128/192/256 types of both the hardware backend
+/// and the `aes` crate.
+pub trait AesCipher:
+    NewBlockCipher + BlockCipher<BlockSize = U16> + BlockEncrypt + BlockDecrypt + Clone
+{
+}
+
+impl<C> AesCipher for C where
+    C: NewBlockCipher + BlockCipher<BlockSize = U16> + BlockEncrypt + BlockDecrypt + Clone
+{
+}
+
+/// Longest message a [`Vector`] may carry, in bytes
+pub const MAX_MESSAGE: usize = 64;
+
+/// Mode of operation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// Electronic codebook
+    Ecb,
+    /// Cipher block chaining
+    Cbc,
+    /// Counter mode with a 128-bit big endian counter
+    Ctr,
+    /// CMAC, only valid for [`cmac`] and MAC vectors
+    Cmac,
+    /// RFC 3394 key wrap, only valid for [`keystore::wrap`] and wrap vectors
+    KeyWrap,
+}
+
+impl Mode {
+    /// Whether the mode takes an IV or initial counter block
+    pub fn needs_iv(self) -> bool {
+        matches!(self, Mode::Cbc | Mode::Ctr)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Encrypt,
+    Decrypt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// Key is not 16, 24 or 32 bytes long
+    InvalidKey,
+    /// IV is not a single block
+    InvalidIv,
+    /// Data is not a whole number of blocks for a block mode
+    InvalidLength,
+    /// Mode cannot be used for the requested operation
+    InvalidMode,
+}
+
+/// Encrypt or decrypt `data` in place
+///
+/// `iv` is ignored for ECB. ECB and CBC require `data` to be a whole number
+/// of blocks, no padding is applied.
+pub fn apply<C: AesCipher>(
+    cipher: &C,
+    mode: Mode,
+    direction: Direction,
+    iv: &[u8],
+    data: &mut [u8],
+) -> Result<(), Error> {
+    if mode.needs_iv() && iv.len() != 16 {
+        return Err(Error::InvalidIv);
+    }
+
+    match mode {
+        Mode::Ecb | Mode::Cbc if data.len() % 16 != 0 => Err(Error::InvalidLength),
+        Mode::Ecb => {
+            for block in data.chunks_exact_mut(16) {
+                let block = GenericArray::from_mut_slice(block);
+                match direction {
+                    Direction::Encrypt => cipher.encrypt_block(block),
+                    Direction::Decrypt => cipher.decrypt_block(block),
+                }
+            }
+            Ok(())
+        }
+        Mode::Cbc => {
+            let mut chain = GenericArray::clone_from_slice(iv);
+            for block in data.chunks_exact_mut(16) {
+                let block = GenericArray::from_mut_slice(block);
+                match direction {
+                    Direction::Encrypt => {
+                        block
+                            .iter_mut()
+                            .zip(chain.iter())
+                            .for_each(|(b, c)| *b ^= c);
+                        cipher.encrypt_block(block);
+                        chain.copy_from_slice(block);
+                    }
+                    Direction::Decrypt => {
+                        let ciphertext = *block;
+                        cipher.decrypt_block(block);
+                        block
+                            .iter_mut()
+                            .zip(chain.iter())
+                            .for_each(|(b, c)| *b ^= c);
+                        chain = ciphertext;
+                    }
+                }
+            }
+            Ok(())
+        }
+        // Encryption and decryption are the same keystream operation
+        Mode::Ctr => {
+            let mut ctr =
+                ctr::Ctr128BE::from_block_cipher(cipher.clone(), GenericArray::from_slice(iv));
+            ctr.apply_keystream(data);
+            Ok(())
+        }
+        Mode::Cmac | Mode::KeyWrap => Err(Error::InvalidMode),
+    }
+}
+
+/// Compute the CMAC tag of `data`
+pub fn cmac<C: AesCipher>(key: &[u8], data: &[u8]) -> Result<[u8; 16], Error> {
+    let mut mac = Cmac::<C>::new_from_slice(key).map_err(|_| Error::InvalidKey)?;
+    mac.update(data);
+    let mut tag = [0; 16];
+    tag.copy_from_slice(&mac.finalize().into_bytes());
+    Ok(tag)
+}
+
+/// Known-answer vector
+#[derive(Debug)]
+pub struct Vector {
+    pub name: &'static str,
+    pub mode: Mode,
+    pub key: &'static [u8],
+    /// IV or initial counter block, empty for modes without one
+    pub iv: &'static [u8],
+    pub input: &'static [u8],
+    /// Ciphertext, the tag for CMAC or the wrapped key for key wrap
+    pub output: &'static [u8],
+}
+
+/// Run `vector` through cipher `C`
+///
+/// Encrypting modes are also decrypted back and compared against the input.
+/// Returns `false` if the key, IV or message length does not fit.
+pub fn check_vector<C: AesCipher>(vector: &Vector) -> bool {
+    let mut buffer = [0; MAX_MESSAGE];
+    if vector_output::<C>(vector, &mut buffer) != Some(vector.output) {
+        return false;
+    }
+
+    let cipher = match C::new_from_slice(vector.key) {
+        Ok(cipher) => cipher,
+        Err(_) => return false,
+    };
+    match vector.mode {
+        Mode::Cmac => true,
+        Mode::KeyWrap => {
+            let key = &mut buffer[..vector.input.len()];
+            keystore::unwrap(&cipher, vector.output, key).is_ok() && key == vector.input
+        }
+        _ => {
+            let data = &mut buffer[..vector.output.len()];
+            data.copy_from_slice(vector.output);
+            apply(&cipher, vector.mode, Direction::Decrypt, vector.iv, data).is_ok()
+                && data == vector.input
+        }
+    }
+}
+
+/// Output of `vector` through cipher `C`, in `buffer`
+///
+/// The ciphertext, tag or wrapped key, to compare against
+/// [`Vector::output`]. `None` if the key, IV or message length does not fit.
+pub fn vector_output<'a, C: AesCipher>(
+    vector: &Vector,
+    buffer: &'a mut [u8; MAX_MESSAGE],
+) -> Option<&'a [u8]> {
+    if vector.input.len() > MAX_MESSAGE {
+        return None;
+    }
+
+    if vector.mode == Mode::Cmac {
+        let tag = cmac::<C>(vector.key, vector.input).ok()?;
+        let output = &mut buffer[..tag.len()];
+        output.copy_from_slice(&tag);
+        return Some(output);
+    }
+
+    let cipher = C::new_from_slice(vector.key).ok()?;
+    if vector.mode == Mode::KeyWrap {
+        let wrapped = buffer.get_mut(..vector.input.len() + 8)?;
+        keystore::wrap(&cipher, vector.input, wrapped).ok()?;
+        return Some(wrapped);
+    }
+
+    let data = &mut buffer[..vector.input.len()];
+    data.copy_from_slice(vector.input);
+    apply(&cipher, vector.mode, Direction::Encrypt, vector.iv, data).ok()?;
+    Some(data)
+}
+
+/// Encrypt a buffer of words in place, one block (four words) at a time
+///
+/// Trailing words not making up a whole block are left untouched.
+pub fn encrypt_words<C>(cipher: &C, words: &mut [u32])
+where
+    C: BlockCipher<BlockSize = U16> + BlockEncrypt,
+{
+    for chunk in words.chunks_exact_mut(4) {
+        let mut block = GenericArray::default();
+        for (bytes, word) in block.chunks_exact_mut(4).zip(chunk.iter()) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+        cipher.encrypt_block(&mut block);
+        for (word, bytes) in chunk.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+    }
+}
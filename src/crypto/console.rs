@@ -0,0 +1,288 @@
+//! `aes`, `cmac` and `key` console commands
+//!
+//! Lets results be cross-checked against host tooling, e.g.
+//! `openssl enc -aes-128-cbc -nopad -K <key> -iv <iv>`, without reflashing.
+//! All arguments and results are hex encoded. Keys are given either in hex or
+//! as `#<slot>`, referring to a slot of the [`KeyStore`]. Stored keys are never
+//! printed. IVs, counter blocks and generated keys come from a [`CryptoRng`].
+
+use super::{
+    apply, cmac,
+    keystore::{self, KeyStore, Storage, MAX_KEY, SLOTS},
+    AesCipher, Direction, Mode, MAX_MESSAGE,
+};
+use crate::hex::{self, Hex};
+use rand_core::{CryptoRng, RngCore};
+use zeroize::Zeroizing;
+
+/// Command summary, printed on usage errors
+pub const USAGE: &str = "usage:\r\n\
+    \x20 aes enc|dec ecb <key> <data>\r\n\
+    \x20 aes enc|dec cbc|ctr <key> <iv> <data>\r\n\
+    \x20 cmac <key> <data>\r\n\
+    \x20 key list\r\n\
+    \x20 key set <slot> <key>\r\n\
+    \x20 key gen <slot> 128|192|256\r\n\
+    \x20 key erase <slot>\r\n\
+    \x20 random <count>\r\n\
+    <key> is hex or #<slot>, <iv> is hex or random when encrypting\r\n";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// Unknown command, or missing or extra arguments
+    Usage,
+    Hex(hex::Error),
+    Crypto(super::Error),
+    Key(keystore::Error),
+    /// The random number generator failed
+    Random,
+}
+
+impl From<hex::Error> for Error {
+    fn from(e: hex::Error) -> Self {
+        Error::Hex(e)
+    }
+}
+
+impl From<super::Error> for Error {
+    fn from(e: super::Error) -> Self {
+        Error::Crypto(e)
+    }
+}
+
+impl From<keystore::Error> for Error {
+    fn from(e: keystore::Error) -> Self {
+        Error::Key(e)
+    }
+}
+
+/// Run `command` with the remaining whitespace separated `args`, printing
+/// the hex encoded result to `tx`
+///
+/// The cipher is picked by key length.
+pub fn execute<'a, A128, A192, A256, S, K, R, I, W>(
+    command: &str,
+    mut args: I,
+    keys: &mut KeyStore<'_, S, K>,
+    rng: &mut R,
+    tx: &mut W,
+) -> Result<(), Error>
+where
+    A128: AesCipher,
+    A192: AesCipher,
+    A256: AesCipher,
+    S: Storage + ?Sized,
+    K: AesCipher,
+    R: RngCore + CryptoRng,
+    I: Iterator<Item = &'a str>,
+    W: core::fmt::Write + ?Sized,
+{
+    let mut key = Zeroizing::new([0; MAX_KEY]);
+    let mut iv = [0; 16];
+    let mut data = [0; MAX_MESSAGE];
+    let mut random_iv = false;
+    let tag;
+
+    let output = match command {
+        "aes" => {
+            let direction = match args.next() {
+                Some("enc") => Direction::Encrypt,
+                Some("dec") => Direction::Decrypt,
+                _ => return Err(Error::Usage),
+            };
+            let mode = match args.next() {
+                Some("ecb") => Mode::Ecb,
+                Some("cbc") => Mode::Cbc,
+                Some("ctr") => Mode::Ctr,
+                _ => return Err(Error::Usage),
+            };
+            let key_len = read_key(args.next().ok_or(Error::Usage)?, keys, &mut key)?;
+            let iv_len = if mode.needs_iv() {
+                match args.next().ok_or(Error::Usage)? {
+                    "random" if direction == Direction::Encrypt => {
+                        rng.try_fill_bytes(&mut iv).map_err(|_| Error::Random)?;
+                        random_iv = true;
+                        iv.len()
+                    }
+                    arg => hex::decode(arg, &mut iv)?,
+                }
+            } else {
+                0
+            };
+            let data_len = hex::decode(args.next().ok_or(Error::Usage)?, &mut data)?;
+            if args.next().is_some() {
+                return Err(Error::Usage);
+            }
+
+            let (key, iv, data) = (&key[..key_len], &iv[..iv_len], &mut data[..data_len]);
+            match key_len {
+                16 => run_cipher::<A128>(key, mode, direction, iv, data)?,
+                24 => run_cipher::<A192>(key, mode, direction, iv, data)?,
+                32 => run_cipher::<A256>(key, mode, direction, iv, data)?,
+                _ => return Err(super::Error::InvalidKey.into()),
+            }
+            &*data
+        }
+        "cmac" => {
+            let key_len = read_key(args.next().ok_or(Error::Usage)?, keys, &mut key)?;
+            let data_len = hex::decode(args.next().ok_or(Error::Usage)?, &mut data)?;
+            if args.next().is_some() {
+                return Err(Error::Usage);
+            }
+
+            let (key, message) = (&key[..key_len], &data[..data_len]);
+            tag = match key_len {
+                16 => cmac::<A128>(key, message)?,
+                24 => cmac::<A192>(key, message)?,
+                32 => cmac::<A256>(key, message)?,
+                _ => return Err(super::Error::InvalidKey.into()),
+            };
+            &tag[..]
+        }
+        "key" => return key_command(args, keys, rng, tx),
+        "random" => {
+            let count = args
+                .next()
+                .and_then(|count| count.parse().ok())
+                .filter(|count| *count <= MAX_MESSAGE)
+                .ok_or(Error::Usage)?;
+            if args.next().is_some() {
+                return Err(Error::Usage);
+            }
+
+            let data = &mut data[..count];
+            rng.try_fill_bytes(data).map_err(|_| Error::Random)?;
+            &*data
+        }
+        _ => return Err(Error::Usage),
+    };
+
+    if random_iv {
+        write!(tx, "iv: {}\r\n", Hex(&iv)).ok();
+    }
+    write!(tx, "{}\r\n", Hex(output)).ok();
+
+    Ok(())
+}
+
+/// Run the `key` command, which only ever reports key sizes, and where a slot
+/// failed to read back
+fn key_command<'a, S, K, R, I, W>(
+    mut args: I,
+    keys: &mut KeyStore<'_, S, K>,
+    rng: &mut R,
+    tx: &mut W,
+) -> Result<(), Error>
+where
+    S: Storage + ?Sized,
+    K: AesCipher,
+    R: RngCore + CryptoRng,
+    I: Iterator<Item = &'a str>,
+    W: core::fmt::Write + ?Sized,
+{
+    let action = args.next().ok_or(Error::Usage)?;
+    if action == "list" {
+        if args.next().is_some() {
+            return Err(Error::Usage);
+        }
+        for slot in 0..SLOTS {
+            match keys.key_len(slot) {
+                Ok(len) => write!(tx, "#{}: AES-{}\r\n", slot, len * 8),
+                Err(keystore::Error::Empty) => write!(tx, "#{}: empty\r\n", slot),
+                Err(e) => write!(tx, "#{}: {:?}\r\n", slot, e),
+            }
+            .ok();
+        }
+        return Ok(());
+    }
+
+    let slot = args
+        .next()
+        .and_then(|slot| slot.parse().ok())
+        .ok_or(Error::Usage)?;
+    match action {
+        "set" => {
+            let mut key = Zeroizing::new([0; MAX_KEY]);
+            let len = hex::decode(args.next().ok_or(Error::Usage)?, &mut key[..])?;
+            if args.next().is_some() {
+                return Err(Error::Usage);
+            }
+            store(keys, slot, &key[..len], tx)?;
+        }
+        "gen" => {
+            let len = match args.next() {
+                Some("128") => 16,
+                Some("192") => 24,
+                Some("256") => 32,
+                _ => return Err(Error::Usage),
+            };
+            if args.next().is_some() {
+                return Err(Error::Usage);
+            }
+            let mut key = Zeroizing::new([0; MAX_KEY]);
+            rng.try_fill_bytes(&mut key[..len])
+                .map_err(|_| Error::Random)?;
+            store(keys, slot, &key[..len], tx)?;
+        }
+        "erase" => {
+            if args.next().is_some() {
+                return Err(Error::Usage);
+            }
+            keys.erase(slot)?;
+        }
+        _ => return Err(Error::Usage),
+    }
+
+    write!(tx, "ok\r\n").ok();
+
+    Ok(())
+}
+
+/// Store `key` in `slot`, printing where it did not read back if storage fails
+fn store<S, K, W>(
+    keys: &mut KeyStore<'_, S, K>,
+    slot: usize,
+    key: &[u8],
+    tx: &mut W,
+) -> Result<(), Error>
+where
+    S: Storage + ?Sized,
+    K: AesCipher,
+    W: core::fmt::Write + ?Sized,
+{
+    let result = keys.store(slot, key);
+    if result == Err(keystore::Error::Storage) {
+        keys.report(slot, key, tx).ok();
+    }
+    Ok(result?)
+}
+
+/// Decode a hex key or load `#<slot>` from `keys`, returning the key length
+fn read_key<S, K>(
+    arg: &str,
+    keys: &mut KeyStore<'_, S, K>,
+    key: &mut Zeroizing<[u8; MAX_KEY]>,
+) -> Result<usize, Error>
+where
+    S: Storage + ?Sized,
+    K: AesCipher,
+{
+    match arg.strip_prefix('#') {
+        Some(slot) => {
+            let slot = slot.parse().map_err(|_| Error::Usage)?;
+            Ok(keys.load(slot, key)?)
+        }
+        None => Ok(hex::decode(arg, &mut key[..])?),
+    }
+}
+
+fn run_cipher<C: AesCipher>(
+    key: &[u8],
+    mode: Mode,
+    direction: Direction,
+    iv: &[u8],
+    data: &mut [u8],
+) -> Result<(), super::Error> {
+    let cipher = C::new_from_slice(key).map_err(|_| super::Error::InvalidKey)?;
+    apply(&cipher, mode, direction, iv, data)
+}
@@ -0,0 +1,381 @@
+//! DMA-driven bulk AES encryption
+//!
+//! Two DMAC channels are used, one feeding `INDATA` on the AES write trigger
+//! and one draining `INDATA` on the AES read trigger. Buffers are split into
+//! chunks of [`CHUNK_WORDS`], each described by its own DMAC descriptor, and
+//! the descriptors are linked together so the whole buffer is processed
+//! without CPU involvement.
+//!
+//! Completion is signalled through the `DMAC_1` interrupt, in which
+//! [`AesDma::complete`] should be called.
+
+use super::Direction;
+use atsamd_hal_aes::pac::{AES, DMAC, MCLK};
+
+/// DMAC channel writing plaintext into the AES peripheral
+const WRITE_CHANNEL: usize = 0;
+/// DMAC channel reading ciphertext out of the AES peripheral
+const READ_CHANNEL: usize = 1;
+/// Number of channels covered by the descriptor and write-back sections
+const CHANNELS: usize = 2;
+
+/// AES write trigger (DMAC TRIGSRC)
+const TRIGSRC_AES_WR: u8 = 0x51;
+/// AES read trigger (DMAC TRIGSRC)
+const TRIGSRC_AES_RD: u8 = 0x52;
+
+/// Words moved by a single descriptor, 4 KiB
+pub const CHUNK_WORDS: usize = 1024;
+/// Maximum number of descriptors in a chain
+pub const MAX_CHUNKS: usize = 16;
+/// Largest buffer accepted by [`AesDma::start`], in words
+pub const MAX_WORDS: usize = CHUNK_WORDS * MAX_CHUNKS;
+
+// BTCTRL bits
+const BTCTRL_VALID: u16 = 1 << 0;
+const BTCTRL_BLOCKACT_INT: u16 = 1 << 3;
+const BTCTRL_BEATSIZE_WORD: u16 = 2 << 8;
+const BTCTRL_SRCINC: u16 = 1 << 10;
+const BTCTRL_DSTINC: u16 = 1 << 11;
+
+/// DMAC transfer descriptor, as laid out in SRAM
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+struct Descriptor {
+    btctrl: u16,
+    btcnt: u16,
+    srcaddr: u32,
+    dstaddr: u32,
+    descaddr: u32,
+}
+
+impl Descriptor {
+    const fn empty() -> Self {
+        Self {
+            btctrl: 0,
+            btcnt: 0,
+            srcaddr: 0,
+            dstaddr: 0,
+            descaddr: 0,
+        }
+    }
+}
+
+/// First descriptor of each channel, pointed to by `BASEADDR`
+static mut DESCRIPTORS: [Descriptor; CHANNELS] = [Descriptor::empty(); CHANNELS];
+/// Write-back section, pointed to by `WRBADDR`
+static mut WRITEBACK: [Descriptor; CHANNELS] = [Descriptor::empty(); CHANNELS];
+/// Linked descriptors following the first one, per channel
+static mut WRITE_CHAIN: [Descriptor; MAX_CHUNKS - 1] = [Descriptor::empty(); MAX_CHUNKS - 1];
+static mut READ_CHAIN: [Descriptor; MAX_CHUNKS - 1] = [Descriptor::empty(); MAX_CHUNKS - 1];
+
+/// Key size, selected from the key length
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeySize {
+    Aes128,
+    Aes192,
+    Aes256,
+}
+
+impl KeySize {
+    pub fn from_key(key: &[u8]) -> Option<Self> {
+        match key.len() {
+            16 => Some(KeySize::Aes128),
+            24 => Some(KeySize::Aes192),
+            32 => Some(KeySize::Aes256),
+            _ => None,
+        }
+    }
+}
+
+/// Block cipher mode of operation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Ecb,
+    Cbc([u8; 16]),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// A transfer is already in progress
+    Busy,
+    /// Key is not 16, 24 or 32 bytes long
+    InvalidKey,
+    /// Buffers differ in length, are not a whole number of blocks,
+    /// or exceed [`MAX_WORDS`]
+    InvalidLength,
+    /// The DMAC reported a transfer error
+    Transfer,
+}
+
+/// Source and destination of a transfer
+///
+/// Ownership moves into [`AesDma`] for the duration of the transfer and is
+/// handed back on completion.
+pub struct Buffers {
+    pub source: &'static mut [u32],
+    pub destination: &'static mut [u32],
+}
+
+/// Bulk AES engine driven by the DMAC
+///
+/// The AES registers are accessed directly while a transfer is running, so
+/// no other user of the AES peripheral may run until [`AesDma::complete`] has
+/// returned the buffers.
+pub struct AesDma {
+    dmac: DMAC,
+    buffers: Option<Buffers>,
+}
+
+impl AesDma {
+    /// Take over the DMAC and prepare the descriptor sections
+    pub fn new(dmac: DMAC, mclk: &mut MCLK) -> Self {
+        mclk.ahbmask.modify(|_, w| w.dmac_().set_bit());
+
+        dmac.ctrl.modify(|_, w| w.dmaenable().clear_bit());
+        dmac.ctrl.modify(|_, w| w.swrst().set_bit());
+        while dmac.ctrl.read().swrst().bit_is_set() {}
+
+        // Safe because the DMAC is disabled and owned by us
+        unsafe {
+            dmac.baseaddr
+                .write(|w| w.baseaddr().bits(DESCRIPTORS.as_ptr() as u32));
+            dmac.wrbaddr
+                .write(|w| w.wrbaddr().bits(WRITEBACK.as_ptr() as u32));
+        }
+
+        for channel in [WRITE_CHANNEL, READ_CHANNEL] {
+            let ch = &dmac.channel[channel];
+            ch.chctrla.modify(|_, w| w.swrst().set_bit());
+            while ch.chctrla.read().swrst().bit_is_set() {}
+        }
+
+        // Only the read channel signals completion
+        dmac.channel[READ_CHANNEL]
+            .chintenset
+            .write(|w| w.tcmpl().set_bit().terr().set_bit());
+        dmac.channel[WRITE_CHANNEL]
+            .chintenset
+            .write(|w| w.terr().set_bit());
+
+        dmac.ctrl.modify(|_, w| {
+            w.lvlen0()
+                .set_bit()
+                .lvlen1()
+                .set_bit()
+                .lvlen2()
+                .set_bit()
+                .lvlen3()
+                .set_bit()
+                .dmaenable()
+                .set_bit()
+        });
+
+        Self {
+            dmac,
+            buffers: None,
+        }
+    }
+
+    /// Whether a transfer is currently in flight
+    pub fn is_busy(&self) -> bool {
+        self.buffers.is_some()
+    }
+
+    /// Start processing `buffers.source` into `buffers.destination`
+    ///
+    /// Returns immediately, the result is collected with
+    /// [`AesDma::complete`]. On error the buffers are handed back untouched.
+    pub fn start(
+        &mut self,
+        key: &[u8],
+        mode: Mode,
+        direction: Direction,
+        buffers: Buffers,
+    ) -> Result<(), (Error, Buffers)> {
+        if self.is_busy() {
+            return Err((Error::Busy, buffers));
+        }
+        let keysize = match KeySize::from_key(key) {
+            Some(keysize) => keysize,
+            None => return Err((Error::InvalidKey, buffers)),
+        };
+        let words = buffers.source.len();
+        if words == 0 || words % 4 != 0 || words > MAX_WORDS || buffers.destination.len() != words {
+            return Err((Error::InvalidLength, buffers));
+        }
+
+        configure_aes(key, keysize, mode, direction);
+
+        // Safe because no transfer is running, so the DMAC is not reading
+        // the descriptors
+        unsafe {
+            link_chain(
+                &mut DESCRIPTORS[WRITE_CHANNEL],
+                &mut WRITE_CHAIN,
+                words,
+                |offset, count| {
+                    (
+                        buffers.source.as_ptr().add(offset + count) as u32,
+                        indata_address(),
+                    )
+                },
+                BTCTRL_SRCINC,
+            );
+            link_chain(
+                &mut DESCRIPTORS[READ_CHANNEL],
+                &mut READ_CHAIN,
+                words,
+                |offset, count| {
+                    (
+                        indata_address(),
+                        buffers.destination.as_ptr().add(offset + count) as u32,
+                    )
+                },
+                BTCTRL_DSTINC,
+            );
+        }
+
+        // Each trigger moves one block, four words
+        for (channel, trigsrc) in [
+            (READ_CHANNEL, TRIGSRC_AES_RD),
+            (WRITE_CHANNEL, TRIGSRC_AES_WR),
+        ] {
+            let ch = &self.dmac.channel[channel];
+            ch.chintflag
+                .write(|w| w.tcmpl().set_bit().terr().set_bit().susp().set_bit());
+            ch.chctrla.write(|w| {
+                w.trigact().burst();
+                w.burstlen()._4beat();
+                unsafe { w.trigsrc().bits(trigsrc) }
+            });
+            // Enable the read channel first so no output is missed
+            ch.chctrla.modify(|_, w| w.enable().set_bit());
+        }
+
+        self.buffers = Some(buffers);
+
+        Ok(())
+    }
+
+    /// Collect the result of a finished transfer
+    ///
+    /// Intended to be called from the `DMAC_1` interrupt. Returns `None` if
+    /// no transfer has finished yet.
+    pub fn complete(&mut self) -> Option<(Result<(), Error>, Buffers)> {
+        let read = &self.dmac.channel[READ_CHANNEL];
+        let write = &self.dmac.channel[WRITE_CHANNEL];
+        let read_flags = read.chintflag.read();
+        let write_flags = write.chintflag.read();
+
+        let result = if read_flags.terr().bit_is_set() || write_flags.terr().bit_is_set() {
+            Err(Error::Transfer)
+        } else if read_flags.tcmpl().bit_is_set() {
+            Ok(())
+        } else {
+            return None;
+        };
+
+        for ch in [read, write] {
+            ch.chctrla.modify(|_, w| w.enable().clear_bit());
+            while ch.chctrla.read().enable().bit_is_set() {}
+            ch.chintflag
+                .write(|w| w.tcmpl().set_bit().terr().set_bit().susp().set_bit());
+        }
+
+        // Safe because the transfer is over, nobody else touches AES meanwhile
+        let aes = unsafe { &*AES::ptr() };
+        aes.ctrla.modify(|_, w| w.enable().clear_bit());
+
+        self.buffers.take().map(|buffers| (result, buffers))
+    }
+}
+
+fn indata_address() -> u32 {
+    // Safe because only the address is taken
+    unsafe { &(*AES::ptr()).indata as *const _ as u32 }
+}
+
+/// Fill `first` and as many entries of `chain` as needed to move `words`
+/// words, `addresses` returns the (source, destination) end addresses of the
+/// chunk starting at `offset` with `count` words
+fn link_chain(
+    first: &mut Descriptor,
+    chain: &mut [Descriptor],
+    words: usize,
+    addresses: impl Fn(usize, usize) -> (u32, u32),
+    increment: u16,
+) {
+    let chunks = (words + CHUNK_WORDS - 1) / CHUNK_WORDS;
+
+    for index in 0..chunks {
+        let offset = index * CHUNK_WORDS;
+        let count = core::cmp::min(CHUNK_WORDS, words - offset);
+        let (srcaddr, dstaddr) = addresses(offset, count);
+        let mut btctrl = BTCTRL_VALID | BTCTRL_BEATSIZE_WORD | increment;
+        // Only the last block of the chain raises an interrupt
+        if index + 1 == chunks {
+            btctrl |= BTCTRL_BLOCKACT_INT;
+        }
+        // Descriptor `index` is followed by `chain[index]`
+        let descaddr = if index + 1 < chunks {
+            &chain[index] as *const Descriptor as u32
+        } else {
+            0
+        };
+
+        let descriptor = Descriptor {
+            btctrl,
+            btcnt: count as u16,
+            srcaddr,
+            dstaddr,
+            descaddr,
+        };
+
+        if index == 0 {
+            *first = descriptor;
+        } else {
+            chain[index - 1] = descriptor;
+        }
+    }
+}
+
+/// Configure the AES peripheral for DMA operation and load key and IV
+fn configure_aes(key: &[u8], keysize: KeySize, mode: Mode, direction: Direction) {
+    // Safe because the caller guarantees exclusive use of AES while the
+    // transfer is running
+    let aes = unsafe { &*AES::ptr() };
+
+    aes.ctrla.modify(|_, w| w.enable().clear_bit());
+    aes.ctrla.write(|w| {
+        match mode {
+            Mode::Ecb => w.aesmode().ecb(),
+            Mode::Cbc(_) => w.aesmode().cbc(),
+        };
+        match keysize {
+            KeySize::Aes128 => w.keysize()._128bit(),
+            KeySize::Aes192 => w.keysize()._192bit(),
+            KeySize::Aes256 => w.keysize()._256bit(),
+        };
+        match direction {
+            Direction::Encrypt => w.cipher().enc(),
+            Direction::Decrypt => w.cipher().dec(),
+        };
+        // Processing starts as soon as the DMA has written a full block
+        w.startmode().auto()
+    });
+    aes.ctrla.modify(|_, w| w.enable().set_bit());
+
+    for (keyword, bytes) in aes.keyword.iter().zip(key.chunks_exact(4)) {
+        let word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        keyword.write(|w| unsafe { w.bits(word) });
+    }
+
+    if let Mode::Cbc(iv) = mode {
+        for (intvectv, bytes) in aes.intvectv.iter().zip(iv.chunks_exact(4)) {
+            let word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            intvectv.write(|w| unsafe { w.bits(word) });
+        }
+        aes.ctrlb.modify(|_, w| w.newmsg().set_bit());
+    }
+}
@@ -0,0 +1,298 @@
+//! CTR_DRBG from NIST SP 800-90A, AES-256 without derivation function
+//!
+//! [`CtrDrbg`] is the deterministic mechanism, fed explicit entropy input so
+//! it can be checked against known answers. [`DrbgRng`] wraps it with an
+//! entropy source, typically the TRNG, which seeds it on first use and
+//! reseeds it every [`RESEED_INTERVAL`] requests, and exposes it as a
+//! [`CryptoRng`].
+//!
+//! The cipher is generic, so the hardware backend and the `aes` crate can be
+//! used interchangeably.
+
+use super::AesCipher;
+use cipher::{consts::U32, generic_array::GenericArray};
+use core::num::NonZeroU32;
+use rand_core::{impls, CryptoRng, RngCore};
+use zeroize::{Zeroize, Zeroizing};
+
+/// Key length, AES-256
+const KEY_LEN: usize = 32;
+/// Seed length: key plus one block
+pub const SEED_LEN: usize = KEY_LEN + 16;
+/// Largest generate request, 2^19 bits
+pub const MAX_REQUEST: usize = 1 << 16;
+/// Generate requests allowed between reseeds
+pub const RESEED_INTERVAL: u64 = 1 << 16;
+
+/// First `rand_core` error code used by [`DrbgRng`]
+const ERROR_CODE: u32 = rand_core::Error::CUSTOM_START + 0x100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// Personalization string or additional input longer than [`SEED_LEN`]
+    InputTooLong,
+    /// More than [`MAX_REQUEST`] bytes requested at once
+    RequestTooLarge,
+    /// [`RESEED_INTERVAL`] requests served since the last reseed
+    ReseedRequired,
+    /// The entropy source failed
+    Entropy,
+}
+
+/// CTR_DRBG working state
+pub struct CtrDrbg<C: AesCipher<KeySize = U32>> {
+    cipher: C,
+    v: [u8; 16],
+    reseed_counter: u64,
+}
+
+impl<C: AesCipher<KeySize = U32>> CtrDrbg<C> {
+    /// Instantiate from `entropy` and an optional `personalization` string
+    pub fn new(entropy: &[u8; SEED_LEN], personalization: &[u8]) -> Result<Self, Error> {
+        let mut seed = pad(personalization)?;
+        xor(&mut seed[..], entropy);
+
+        let mut drbg = Self {
+            cipher: C::new(&GenericArray::default()),
+            v: [0; 16],
+            reseed_counter: 1,
+        };
+        drbg.update(&seed);
+        Ok(drbg)
+    }
+
+    /// Mix fresh `entropy` and optional `additional` input into the state
+    pub fn reseed(&mut self, entropy: &[u8; SEED_LEN], additional: &[u8]) -> Result<(), Error> {
+        let mut seed = pad(additional)?;
+        xor(&mut seed[..], entropy);
+        self.update(&seed);
+        self.reseed_counter = 1;
+        Ok(())
+    }
+
+    /// Fill `output`, mixing in optional `additional` input
+    pub fn generate(&mut self, output: &mut [u8], additional: &[u8]) -> Result<(), Error> {
+        if output.len() > MAX_REQUEST {
+            return Err(Error::RequestTooLarge);
+        }
+        if self.reseed_counter > RESEED_INTERVAL {
+            return Err(Error::ReseedRequired);
+        }
+
+        let provided = !additional.is_empty();
+        let additional = pad(additional)?;
+        if provided {
+            self.update(&additional);
+        }
+
+        let mut block = GenericArray::default();
+        for chunk in output.chunks_mut(16) {
+            increment(&mut self.v);
+            block.copy_from_slice(&self.v);
+            self.cipher.encrypt_block(&mut block);
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+        block.as_mut_slice().zeroize();
+
+        self.update(&additional);
+        self.reseed_counter += 1;
+        Ok(())
+    }
+
+    /// CTR_DRBG_Update, derives a new key and V from the current ones
+    fn update(&mut self, provided: &[u8; SEED_LEN]) {
+        let mut temp = Zeroizing::new([0; SEED_LEN]);
+        for block in temp.chunks_exact_mut(16) {
+            increment(&mut self.v);
+            block.copy_from_slice(&self.v);
+            self.cipher
+                .encrypt_block(GenericArray::from_mut_slice(block));
+        }
+        xor(&mut temp[..], provided);
+
+        self.cipher = C::new(GenericArray::from_slice(&temp[..KEY_LEN]));
+        self.v.copy_from_slice(&temp[KEY_LEN..]);
+    }
+}
+
+impl<C: AesCipher<KeySize = U32>> Drop for CtrDrbg<C> {
+    fn drop(&mut self) {
+        self.v.zeroize();
+        // The cipher does not clear its key schedule, so it is overwritten with
+        // the all-zero key's. The write is volatile, like `zeroize`, and the
+        // old cipher is forgotten rather than dropped.
+        unsafe { core::ptr::write_volatile(&mut self.cipher, C::new(&GenericArray::default())) };
+    }
+}
+
+/// Zero pad `input` to the seed length
+fn pad(input: &[u8]) -> Result<Zeroizing<[u8; SEED_LEN]>, Error> {
+    if input.len() > SEED_LEN {
+        return Err(Error::InputTooLong);
+    }
+    let mut padded = Zeroizing::new([0; SEED_LEN]);
+    padded[..input.len()].copy_from_slice(input);
+    Ok(padded)
+}
+
+fn xor(data: &mut [u8], other: &[u8]) {
+    data.iter_mut().zip(other).for_each(|(d, o)| *d ^= o);
+}
+
+/// Increment V as a 128-bit big endian counter
+fn increment(v: &mut [u8; 16]) {
+    let counter = u128::from_be_bytes(*v).wrapping_add(1);
+    *v = counter.to_be_bytes();
+}
+
+/// [`CtrDrbg`] seeded and reseeded from the entropy source `E`
+pub struct DrbgRng<C: AesCipher<KeySize = U32>, E> {
+    drbg: Option<CtrDrbg<C>>,
+    entropy: E,
+}
+
+impl<C: AesCipher<KeySize = U32>, E: RngCore + CryptoRng> DrbgRng<C, E> {
+    /// The DRBG is seeded on the first request
+    pub fn new(entropy: E) -> Self {
+        Self {
+            drbg: None,
+            entropy,
+        }
+    }
+
+    /// The entropy source, e.g. to service its interrupt
+    pub fn entropy_mut(&mut self) -> &mut E {
+        &mut self.entropy
+    }
+
+    /// Seed or reseed from the entropy source now
+    pub fn reseed(&mut self) -> Result<(), Error> {
+        let mut entropy = Zeroizing::new([0; SEED_LEN]);
+        self.entropy
+            .try_fill_bytes(&mut entropy[..])
+            .map_err(|_| Error::Entropy)?;
+        match self.drbg.as_mut() {
+            Some(drbg) => drbg.reseed(&entropy, &[]),
+            None => {
+                self.drbg = Some(CtrDrbg::new(&entropy, &[])?);
+                Ok(())
+            }
+        }
+    }
+
+    /// Fill `output`, seeding or reseeding first when needed
+    pub fn generate(&mut self, output: &mut [u8]) -> Result<(), Error> {
+        for chunk in output.chunks_mut(MAX_REQUEST) {
+            let seeded = matches!(
+                self.drbg,
+                Some(ref drbg) if drbg.reseed_counter <= RESEED_INTERVAL
+            );
+            if !seeded {
+                self.reseed()?;
+            }
+            if let Some(drbg) = self.drbg.as_mut() {
+                drbg.generate(chunk, &[])?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<C: AesCipher<KeySize = U32>, E: RngCore + CryptoRng> RngCore for DrbgRng<C, E> {
+    /// Panics if the entropy source fails
+    fn next_u32(&mut self) -> u32 {
+        impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        impls::next_u64_via_fill(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.try_fill_bytes(dest).expect("DRBG failed")
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.generate(dest)
+            .map_err(|e| NonZeroU32::new(ERROR_CODE + e as u32).unwrap().into())
+    }
+}
+
+impl<C: AesCipher<KeySize = U32>, E: RngCore + CryptoRng> CryptoRng for DrbgRng<C, E> {}
+
+/// Known-answer vector, instantiate, reseed and generate twice
+///
+/// Only the output of the second generate call is compared, as in the NIST
+/// CAVP CTR_DRBG vectors.
+#[derive(Debug)]
+pub struct Vector {
+    pub name: &'static str,
+    pub entropy: &'static [u8; SEED_LEN],
+    pub personalization: &'static [u8],
+    pub reseed_entropy: &'static [u8; SEED_LEN],
+    pub reseed_additional: &'static [u8],
+    pub additional: [&'static [u8]; 2],
+    pub output: &'static [u8],
+}
+
+/// Run `vector` through a DRBG using cipher `C`
+pub fn check_vector<C: AesCipher<KeySize = U32>>(vector: &Vector) -> bool {
+    let mut buffer = [0; super::MAX_MESSAGE];
+    vector_output::<C>(vector, &mut buffer) == Some(vector.output)
+}
+
+/// Output of the second generate call of `vector` using cipher `C`, in
+/// `buffer`, `None` if the vector does not fit
+pub fn vector_output<'a, C: AesCipher<KeySize = U32>>(
+    vector: &Vector,
+    buffer: &'a mut [u8; super::MAX_MESSAGE],
+) -> Option<&'a [u8]> {
+    let output = buffer.get_mut(..vector.output.len())?;
+    let mut drbg = CtrDrbg::<C>::new(vector.entropy, vector.personalization).ok()?;
+    drbg.reseed(vector.reseed_entropy, vector.reseed_additional)
+        .ok()?;
+    drbg.generate(output, vector.additional[0]).ok()?;
+    drbg.generate(output, vector.additional[1]).ok()?;
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::selftest::DRBG_VECTORS;
+
+    #[test]
+    fn known_answers() {
+        for vector in DRBG_VECTORS {
+            assert!(check_vector::<aes::Aes256>(vector), "{}", vector.name);
+        }
+    }
+
+    #[test]
+    fn zero_additional_input_counts() {
+        // An all-zero additional input still updates the state, unlike none
+        let vector = &DRBG_VECTORS[2];
+        assert_eq!(vector.additional[1], &[0; SEED_LEN][..]);
+        let mut buffer = [0; crate::crypto::MAX_MESSAGE];
+        let output = vector_output::<aes::Aes256>(
+            &Vector {
+                additional: [vector.additional[0], &[]],
+                ..*vector
+            },
+            &mut buffer,
+        );
+        assert_ne!(output.unwrap(), vector.output);
+    }
+
+    #[test]
+    fn limits() {
+        let mut drbg = CtrDrbg::<aes::Aes256>::new(&[0; SEED_LEN], &[]).unwrap();
+        assert_eq!(
+            drbg.reseed(&[0; SEED_LEN], &[0; SEED_LEN + 1]),
+            Err(Error::InputTooLong)
+        );
+        let mut output = [0; MAX_REQUEST + 1];
+        assert_eq!(drbg.generate(&mut output, &[]), Err(Error::RequestTooLarge));
+        assert_eq!(drbg.generate(&mut output[..MAX_REQUEST], &[]), Ok(()));
+    }
+}
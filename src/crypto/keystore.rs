@@ -0,0 +1,306 @@
+//! AES key slots in non-volatile storage
+//!
+//! Keys are kept wrapped with a key-encryption key (RFC 3394 AES key wrap),
+//! so storage never holds raw key material and a damaged or foreign slot is
+//! caught by the wrap integrity check. Keys are referenced by slot number and
+//! only unwrapped into [`Zeroizing`] buffers; nothing in here prints them.
+//!
+//! Each slot is [`SLOT_SIZE`] bytes: a magic byte, the key length, two
+//! reserved bytes and the wrapped key.
+
+use super::{cmac, AesCipher};
+use crate::compare::Diff;
+use cipher::generic_array::GenericArray;
+use core::fmt;
+use zeroize::{Zeroize, Zeroizing};
+
+/// Number of key slots
+pub const SLOTS: usize = 4;
+/// Bytes taken by one slot
+pub const SLOT_SIZE: usize = 48;
+/// Bytes taken by the whole key store
+pub const SIZE: usize = SLOTS * SLOT_SIZE;
+/// Longest key a slot holds, AES-256
+pub const MAX_KEY: usize = 32;
+
+/// Marks a slot as holding a key
+const MAGIC: u8 = 0x4b;
+const HEADER: usize = 4;
+/// RFC 3394 default initial value
+const WRAP_IV: [u8; 8] = [0xa6; 8];
+/// CMAC message used to derive the key-encryption key
+const KEK_LABEL: &[u8] = b"atsamd-demo key store";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// Slot number is not below [`SLOTS`]
+    InvalidSlot,
+    /// Slot holds no key
+    Empty,
+    /// Key is not 16, 24 or 32 bytes long, or does not fit the cipher
+    InvalidKey,
+    /// Slot failed the key wrap integrity check
+    Corrupt,
+    /// Storage did not read back what was written
+    Storage,
+}
+
+/// Byte addressable non-volatile storage backing the key slots
+pub trait Storage {
+    fn read(&mut self, offset: usize, buffer: &mut [u8]);
+    fn write(&mut self, offset: usize, data: &[u8]);
+}
+
+/// RAM backed storage, offsets past the end read as zero and drop writes
+impl Storage for [u8] {
+    fn read(&mut self, offset: usize, buffer: &mut [u8]) {
+        buffer.fill(0);
+        for (byte, stored) in buffer.iter_mut().zip(self.iter().skip(offset)) {
+            *byte = *stored;
+        }
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) {
+        for (stored, byte) in self.iter_mut().skip(offset).zip(data) {
+            *stored = *byte;
+        }
+    }
+}
+
+#[cfg(feature = "hal-aes")]
+impl Storage
+    for atsamd_hal_aes::nvm::smart_eeprom::SmartEeprom<
+        '_,
+        atsamd_hal_aes::nvm::smart_eeprom::Unlocked,
+    >
+{
+    fn read(&mut self, offset: usize, buffer: &mut [u8]) {
+        buffer.fill(0);
+        for (byte, stored) in buffer.iter_mut().zip(self.iter::<u8>().skip(offset)) {
+            *byte = *stored;
+        }
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) {
+        for (stored, byte) in self.iter_mut::<u8>().skip(offset).zip(data) {
+            *stored = *byte;
+        }
+    }
+}
+
+/// Key slots at `offset` in `storage`, wrapped with the cipher `K`
+pub struct KeyStore<'a, S: ?Sized, K> {
+    storage: &'a mut S,
+    offset: usize,
+    kek: K,
+}
+
+impl<'a, S: Storage + ?Sized, K: AesCipher> KeyStore<'a, S, K> {
+    /// The slots take [`SIZE`] bytes of `storage` starting at `offset`
+    pub fn new(storage: &'a mut S, offset: usize, kek: K) -> Self {
+        Self {
+            storage,
+            offset,
+            kek,
+        }
+    }
+
+    /// Wrap `key` and store it in `slot`, replacing what was there
+    ///
+    /// Fails with [`Error::Storage`] if the slot does not read back what was
+    /// written; [`KeyStore::report`] shows where.
+    pub fn store(&mut self, slot: usize, key: &[u8]) -> Result<(), Error> {
+        let offset = self.slot_offset(slot)?;
+        let record = self.record(key)?;
+        self.storage.write(offset, &record);
+
+        let mut stored = [0; SLOT_SIZE];
+        self.storage.read(offset, &mut stored);
+        if Diff::new(&record, &stored).is_match() {
+            Ok(())
+        } else {
+            Err(Error::Storage)
+        }
+    }
+
+    /// Print how many bytes of `slot` differ from what storing `key` writes
+    /// to it, and the first of them
+    ///
+    /// Key wrap is deterministic, so this repeats the check of
+    /// [`KeyStore::store`]. The wrapped key is not printed either: the
+    /// key-encryption key derives from the public serial number.
+    pub fn report<W>(&mut self, slot: usize, key: &[u8], tx: &mut W) -> Result<(), Error>
+    where
+        W: fmt::Write + ?Sized,
+    {
+        let offset = self.slot_offset(slot)?;
+        let record = self.record(key)?;
+        let mut stored = [0; SLOT_SIZE];
+        self.storage.read(offset, &mut stored);
+        let diff = Diff::new(&record, &stored);
+        if let Some(first) = diff.first_mismatch() {
+            write!(
+                tx,
+                "slot {}: {} of {} bytes differ, first at {}\r\n",
+                slot,
+                diff.count(),
+                diff.len(),
+                first
+            )
+            .ok();
+        }
+        Ok(())
+    }
+
+    /// Slot contents holding `key`
+    fn record(&self, key: &[u8]) -> Result<[u8; SLOT_SIZE], Error> {
+        if !matches!(key.len(), 16 | 24 | 32) {
+            return Err(Error::InvalidKey);
+        }
+
+        let mut record = [0; SLOT_SIZE];
+        record[0] = MAGIC;
+        record[1] = key.len() as u8;
+        wrap(&self.kek, key, &mut record[HEADER..HEADER + key.len() + 8])?;
+        Ok(record)
+    }
+
+    /// Clear `slot`
+    pub fn erase(&mut self, slot: usize) -> Result<(), Error> {
+        let offset = self.slot_offset(slot)?;
+        self.storage.write(offset, &[0; SLOT_SIZE]);
+        Ok(())
+    }
+
+    /// Length of the key in `slot` in bytes, without unwrapping it
+    pub fn key_len(&mut self, slot: usize) -> Result<usize, Error> {
+        let offset = self.slot_offset(slot)?;
+        let mut header = [0; HEADER];
+        self.storage.read(offset, &mut header);
+        parse_header(&header)
+    }
+
+    /// Unwrap the key in `slot` into `key`, returning its length
+    pub fn load(
+        &mut self,
+        slot: usize,
+        key: &mut Zeroizing<[u8; MAX_KEY]>,
+    ) -> Result<usize, Error> {
+        let offset = self.slot_offset(slot)?;
+        let mut record = [0; SLOT_SIZE];
+        self.storage.read(offset, &mut record);
+        let len = parse_header(&record)?;
+        unwrap(
+            &self.kek,
+            &record[HEADER..HEADER + len + 8],
+            &mut key[..len],
+        )?;
+        Ok(len)
+    }
+
+    /// Cipher keyed with the key in `slot`
+    pub fn cipher<C: AesCipher>(&mut self, slot: usize) -> Result<C, Error> {
+        let mut key = Zeroizing::new([0; MAX_KEY]);
+        let len = self.load(slot, &mut key)?;
+        C::new_from_slice(&key[..len]).map_err(|_| Error::InvalidKey)
+    }
+
+    fn slot_offset(&self, slot: usize) -> Result<usize, Error> {
+        if slot < SLOTS {
+            Ok(self.offset + slot * SLOT_SIZE)
+        } else {
+            Err(Error::InvalidSlot)
+        }
+    }
+}
+
+fn parse_header(header: &[u8]) -> Result<usize, Error> {
+    match (header[0], header[1]) {
+        (MAGIC, len @ (16 | 24 | 32)) => Ok(len as usize),
+        (MAGIC, _) => Err(Error::Corrupt),
+        _ => Err(Error::Empty),
+    }
+}
+
+/// Key-encryption key bound to `device_id`, the chip serial number
+///
+/// The serial number is not secret, so this only ties the slots to one chip:
+/// a copy of the storage does not unwrap on another device. It does not
+/// protect against anyone able to read out the chip itself. `C` must be an
+/// AES-128 cipher.
+pub fn device_kek<C: AesCipher>(device_id: &[u8; 16]) -> Result<C, Error> {
+    let kek = Zeroizing::new(cmac::<C>(device_id, KEK_LABEL).map_err(|_| Error::InvalidKey)?);
+    C::new_from_slice(&kek[..]).map_err(|_| Error::InvalidKey)
+}
+
+/// Wrap `key` with `kek` as specified by RFC 3394
+///
+/// `key` must be at least two 64-bit blocks, `wrapped` one block longer.
+pub fn wrap<C: AesCipher>(kek: &C, key: &[u8], wrapped: &mut [u8]) -> Result<(), Error> {
+    let n = key.len() / 8;
+    if key.len() % 8 != 0 || n < 2 || wrapped.len() != key.len() + 8 {
+        return Err(Error::InvalidKey);
+    }
+
+    let (a, r) = wrapped.split_at_mut(8);
+    a.copy_from_slice(&WRAP_IV);
+    r.copy_from_slice(key);
+
+    let mut block = GenericArray::default();
+    for j in 0..6 {
+        for (i, r) in r.chunks_exact_mut(8).enumerate() {
+            block[..8].copy_from_slice(a);
+            block[8..].copy_from_slice(r);
+            kek.encrypt_block(&mut block);
+            let t = (n * j + i + 1) as u64;
+            for (a, (b, t)) in a.iter_mut().zip(block.iter().zip(t.to_be_bytes())) {
+                *a = b ^ t;
+            }
+            r.copy_from_slice(&block[8..]);
+        }
+    }
+    block.as_mut_slice().zeroize();
+
+    Ok(())
+}
+
+/// Unwrap `wrapped` with `kek` as specified by RFC 3394
+///
+/// `key` is zeroed if the integrity check fails.
+pub fn unwrap<C: AesCipher>(kek: &C, wrapped: &[u8], key: &mut [u8]) -> Result<(), Error> {
+    let n = key.len() / 8;
+    if key.len() % 8 != 0 || n < 2 || wrapped.len() != key.len() + 8 {
+        return Err(Error::InvalidKey);
+    }
+
+    let mut a = [0; 8];
+    a.copy_from_slice(&wrapped[..8]);
+    key.copy_from_slice(&wrapped[8..]);
+
+    let mut block = GenericArray::default();
+    for j in (0..6).rev() {
+        for (i, r) in key.chunks_exact_mut(8).enumerate().rev() {
+            let t = (n * j + i + 1) as u64;
+            for (b, (a, t)) in block.iter_mut().zip(a.iter().zip(t.to_be_bytes())) {
+                *b = a ^ t;
+            }
+            block[8..].copy_from_slice(r);
+            kek.decrypt_block(&mut block);
+            a.copy_from_slice(&block[..8]);
+            r.copy_from_slice(&block[8..]);
+        }
+    }
+    block.as_mut_slice().zeroize();
+
+    // Compare without an early exit
+    let difference = a
+        .iter()
+        .zip(WRAP_IV.iter())
+        .fold(0, |difference, (a, iv)| difference | (a ^ iv));
+    if difference == 0 {
+        Ok(())
+    } else {
+        key.zeroize();
+        Err(Error::Corrupt)
+    }
+}
@@ -0,0 +1,430 @@
+//! Table-driven crypto self-test
+//!
+//! Runs every entry of [`VECTORS`] and prints one line per vector followed
+//! by a summary. Meant to be run as a power-on self-test and on demand from
+//! the console.
+
+use super::{check_vector, drbg, vector_output, AesCipher, Mode, Vector, MAX_MESSAGE};
+use crate::compare::Diff;
+use crate::hex::decode_array as hex;
+use cipher::consts::U32;
+
+/// Key shared by the SP 800-38A and RFC 4493 AES-128 vectors
+const KEY_128: [u8; 16] = hex("2b7e151628aed2a6abf7158809cf4f3c");
+/// Key shared by the SP 800-38A and SP 800-38B AES-192 vectors
+const KEY_192: [u8; 24] = hex("8e73b0f7da0e6452c810f32b809079e562f8ead2522c6b7b");
+/// Key shared by the SP 800-38A and SP 800-38B AES-256 vectors
+const KEY_256: [u8; 32] = hex("603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4");
+
+/// SP 800-38A example plaintext, four blocks
+const PLAINTEXT: [u8; 64] = hex(concat!(
+    "6bc1bee22e409f96e93d7e117393172a",
+    "ae2d8a571e03ac9c9eb76fac45af8e51",
+    "30c81c46a35ce411e5fbc1191a0a52ef",
+    "f69f2445df4f9b17ad2b417be66c3710",
+));
+
+/// SP 800-38A CBC initialization vector
+const CBC_IV: [u8; 16] = hex("000102030405060708090a0b0c0d0e0f");
+
+/// SP 800-38A initial counter block
+const COUNTER: [u8; 16] = hex("f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff");
+
+/// FIPS-197 Appendix C plaintext
+const FIPS197_PLAINTEXT: [u8; 16] = hex("00112233445566778899aabbccddeeff");
+
+/// Every vector run by [`run`]
+pub const VECTORS: &[Vector] = &[
+    Vector {
+        name: "FIPS-197 C.1",
+        mode: Mode::Ecb,
+        key: &hex::<16>("000102030405060708090a0b0c0d0e0f"),
+        iv: &[],
+        input: &FIPS197_PLAINTEXT,
+        output: &hex::<16>("69c4e0d86a7b0430d8cdb78070b4c55a"),
+    },
+    Vector {
+        name: "FIPS-197 C.2",
+        mode: Mode::Ecb,
+        key: &hex::<24>("000102030405060708090a0b0c0d0e0f1011121314151617"),
+        iv: &[],
+        input: &FIPS197_PLAINTEXT,
+        output: &hex::<16>("dda97ca4864cdfe06eaf70a0ec0d7191"),
+    },
+    Vector {
+        name: "FIPS-197 C.3",
+        mode: Mode::Ecb,
+        key: &hex::<32>("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"),
+        iv: &[],
+        input: &FIPS197_PLAINTEXT,
+        output: &hex::<16>("8ea2b7ca516745bfeafc49904b496089"),
+    },
+    Vector {
+        name: "Kung Fu",
+        mode: Mode::Ecb,
+        key: b"Thats my Kung Fu",
+        iv: &[],
+        input: b"Two One Nine Two",
+        output: &hex::<16>("29c3505f571420f6402299b31a02d73a"),
+    },
+    Vector {
+        name: "SP 800-38A F.1.1",
+        mode: Mode::Ecb,
+        key: &KEY_128,
+        iv: &[],
+        input: &PLAINTEXT,
+        output: &hex::<64>(concat!(
+            "3ad77bb40d7a3660a89ecaf32466ef97",
+            "f5d3d58503b9699de785895a96fdbaaf",
+            "43b1cd7f598ece23881b00e3ed030688",
+            "7b0c785e27e8ad3f8223207104725dd4",
+        )),
+    },
+    Vector {
+        name: "SP 800-38A F.2.1",
+        mode: Mode::Cbc,
+        key: &KEY_128,
+        iv: &CBC_IV,
+        input: &PLAINTEXT,
+        output: &hex::<64>(concat!(
+            "7649abac8119b246cee98e9b12e9197d",
+            "5086cb9b507219ee95db113a917678b2",
+            "73bed6b8e3c1743b7116e69e22229516",
+            "3ff1caa1681fac09120eca307586e1a7",
+        )),
+    },
+    Vector {
+        name: "SP 800-38A F.2.3",
+        mode: Mode::Cbc,
+        key: &KEY_192,
+        iv: &CBC_IV,
+        input: &PLAINTEXT,
+        output: &hex::<64>(concat!(
+            "4f021db243bc633d7178183a9fa071e8",
+            "b4d9ada9ad7dedf4e5e738763f69145a",
+            "571b242012fb7ae07fa9baac3df102e0",
+            "08b0e27988598881d920a9e64f5615cd",
+        )),
+    },
+    Vector {
+        name: "SP 800-38A F.2.5",
+        mode: Mode::Cbc,
+        key: &KEY_256,
+        iv: &CBC_IV,
+        input: &PLAINTEXT,
+        output: &hex::<64>(concat!(
+            "f58c4c04d6e5f1ba779eabfb5f7bfbd6",
+            "9cfc4e967edb808d679f777bc6702c7d",
+            "39f23369a9d9bacfa530e26304231461",
+            "b2eb05e2c39be9fcda6c19078c6a9d1b",
+        )),
+    },
+    Vector {
+        name: "SP 800-38A F.5.1",
+        mode: Mode::Ctr,
+        key: &KEY_128,
+        iv: &COUNTER,
+        input: &PLAINTEXT,
+        output: &hex::<64>(concat!(
+            "874d6191b620e3261bef6864990db6ce",
+            "9806f66b7970fdff8617187bb9fffdff",
+            "5ae4df3edbd5d35e5b4f09020db03eab",
+            "1e031dda2fbe03d1792170a0f3009cee",
+        )),
+    },
+    Vector {
+        name: "SP 800-38A F.5.3",
+        mode: Mode::Ctr,
+        key: &KEY_192,
+        iv: &COUNTER,
+        input: &PLAINTEXT,
+        output: &hex::<64>(concat!(
+            "1abc932417521ca24f2b0459fe7e6e0b",
+            "090339ec0aa6faefd5ccc2c6f4ce8e94",
+            "1e36b26bd1ebc670d1bd1d665620abf7",
+            "4f78a7f6d29809585a97daec58c6b050",
+        )),
+    },
+    Vector {
+        name: "SP 800-38A F.5.5",
+        mode: Mode::Ctr,
+        key: &KEY_256,
+        iv: &COUNTER,
+        input: &PLAINTEXT,
+        output: &hex::<64>(concat!(
+            "601ec313775789a5b7a7f504bbf3d228",
+            "f443e3ca4d62b59aca84e990cacaf5c5",
+            "2b0930daa23de94ce87017ba2d84988d",
+            "dfc9c58db67aada613c2dd08457941a6",
+        )),
+    },
+    Vector {
+        name: "Secret nonce",
+        mode: Mode::Ctr,
+        key: b"very secret key.",
+        iv: b"and secret nonce",
+        input: &[1, 2, 3, 4, 5, 6, 7],
+        output: &[6, 245, 126, 124, 180, 146, 37],
+    },
+    Vector {
+        name: "RFC 4493 1",
+        mode: Mode::Cmac,
+        key: &KEY_128,
+        iv: &[],
+        input: &[],
+        output: &hex::<16>("bb1d6929e95937287fa37d129b756746"),
+    },
+    Vector {
+        name: "RFC 4493 2",
+        mode: Mode::Cmac,
+        key: &KEY_128,
+        iv: &[],
+        input: &hex::<16>("6bc1bee22e409f96e93d7e117393172a"),
+        output: &hex::<16>("070a16b46b4d4144f79bdd9dd04a287c"),
+    },
+    Vector {
+        name: "RFC 4493 3",
+        mode: Mode::Cmac,
+        key: &KEY_128,
+        iv: &[],
+        input: &hex::<40>(concat!(
+            "6bc1bee22e409f96e93d7e117393172a",
+            "ae2d8a571e03ac9c9eb76fac45af8e51",
+            "30c81c46a35ce411",
+        )),
+        output: &hex::<16>("dfa66747de9ae63030ca32611497c827"),
+    },
+    Vector {
+        name: "RFC 4493 4",
+        mode: Mode::Cmac,
+        key: &KEY_128,
+        iv: &[],
+        input: &PLAINTEXT,
+        output: &hex::<16>("51f0bebf7e3b9d92fc49741779363cfe"),
+    },
+    Vector {
+        name: "SP 800-38B D.2",
+        mode: Mode::Cmac,
+        key: &KEY_192,
+        iv: &[],
+        input: &PLAINTEXT,
+        output: &hex::<16>("a1d5df0eed790f794d77589659f39a11"),
+    },
+    Vector {
+        name: "SP 800-38B D.3",
+        mode: Mode::Cmac,
+        key: &KEY_256,
+        iv: &[],
+        input: &PLAINTEXT,
+        output: &hex::<16>("e1992190549f6ed5696a2c056c315410"),
+    },
+    Vector {
+        name: "RFC 3394 4.1",
+        mode: Mode::KeyWrap,
+        key: &hex::<16>("000102030405060708090a0b0c0d0e0f"),
+        iv: &[],
+        input: &FIPS197_PLAINTEXT,
+        output: &hex::<24>("1fa68b0a8112b447aef34bd8fb5a7b829d3e862371d2cfe5"),
+    },
+    Vector {
+        name: "RFC 3394 4.5",
+        mode: Mode::KeyWrap,
+        key: &hex::<24>("000102030405060708090a0b0c0d0e0f1011121314151617"),
+        iv: &[],
+        input: &hex::<24>("00112233445566778899aabbccddeeff0001020304050607"),
+        output: &hex::<32>("031d33264e15d33268f24ec260743edce1c6c7ddee725a936ba814915c6762d2"),
+    },
+    Vector {
+        name: "RFC 3394 4.6",
+        mode: Mode::KeyWrap,
+        key: &hex::<32>("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"),
+        iv: &[],
+        input: &hex::<32>("00112233445566778899aabbccddeeff000102030405060708090a0b0c0d0e0f"),
+        output: &hex::<40>(concat!(
+            "28c9f404c4b810f4cbccb35cfb87f826",
+            "3f5786e2d80ed326cbc7f0e71a99f43b",
+            "fb988b9b7a02dd21",
+        )),
+    },
+];
+
+/// Every CTR_DRBG vector run by [`run`], AES-256 without derivation function
+pub const DRBG_VECTORS: &[drbg::Vector] = &[
+    // NIST CAVP CTR_DRBG, [AES-256 no df] without prediction resistance,
+    // COUNT = 0, also the BoringSSL CTR_DRBG basic test
+    drbg::Vector {
+        name: "NIST CAVP AES-256 no df",
+        entropy: &hex(concat!(
+            "e4bc23c5089a19d86f4119cb3fa08c0a4991e0a1def17e101e4c14d9c323460a",
+            "7c2fb58e0b086c6c57b55f56cae25bad",
+        )),
+        personalization: &[],
+        reseed_entropy: &hex(concat!(
+            "fd85a836bba85019881e8c6bad23c9061adc75477659acaea8e4a01dfe07a183",
+            "2dad1c136f59d70f8653a5dc118663d6",
+        )),
+        reseed_additional: &[],
+        additional: [&[], &[]],
+        output: &hex::<64>(concat!(
+            "b2cb8905c05e5950ca31895096be29ea3d5a3b82b269495554eb80fe07de43e1",
+            "93b9e7c3ece73b80e062b1c1f68202fbb1c52a040ea2478864295282234aaada",
+        )),
+    },
+    // Expected output from OpenSSL's CTR-DRBG, AES-256-CTR without derivation
+    // function; full-length personalization and additional inputs
+    drbg::Vector {
+        name: "OpenSSL full-length inputs",
+        entropy: &hex(concat!(
+            "c3c6633e506ff48597eee45cc780cfe30daa45c248287ee4155475e7de36ae48",
+            "3266a9f046d29390d41124999e32245c",
+        )),
+        personalization: &hex::<48>(concat!(
+            "b5d1fe029601e7e1cf640a6ab0ebbd521705c9094ac329f07b72aea1004b2120",
+            "737ab84c1302194838ce2291099c6781",
+        )),
+        reseed_entropy: &hex(concat!(
+            "80cb73bd1f6acf245553e6c7cec14e8340dc0455ed5d3663010fe904cecee5bb",
+            "66c533562eb712a7f894ec86e1031bf1",
+        )),
+        reseed_additional: &hex::<48>(concat!(
+            "c210d3034a784a5b1cb2f1044d036771c33fde24950632e7ce30f9a055e4c917",
+            "e02f658854ae449127ab53a6792a1951",
+        )),
+        additional: [
+            &hex::<48>(concat!(
+                "c7f2b3b162d96e9a75323131073ad4f5eee52f2b61d5f384fea5b8afb6cfacf2",
+                "0da7410d43e40489297d663808fafee9",
+            )),
+            &hex::<48>(concat!(
+                "618d623a7cb35f8d466ce5402603e3291afdbc75ccaa657efefd47ecc267563b",
+                "6fb6dfa4235e649a2cca378cc9b604ea",
+            )),
+        ],
+        output: &hex::<64>(concat!(
+            "7a851983c7dd5e43000e15369af03c564b717e4f8989a87322d345c597c31083",
+            "fc497d3ee2fe04f0d3561f5e711009c8c2710bf3ed538cb2260e8326058d2c6e",
+        )),
+    },
+    // Expected output from OpenSSL as above; short inputs, and an all-zero
+    // additional input which must still update the state
+    drbg::Vector {
+        name: "OpenSSL short inputs",
+        entropy: &hex(concat!(
+            "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+            "202122232425262728292a2b2c2d2e2f",
+        )),
+        personalization: b"atsamd-demo personalization",
+        reseed_entropy: &hex(concat!(
+            "6465666768696a6b6c6d6e6f707172737475767778797a7b7c7d7e7f80818283",
+            "8485868788898a8b8c8d8e8f90919293",
+        )),
+        reseed_additional: b"reseed additional input",
+        additional: [b"first additional input", &[0; drbg::SEED_LEN]],
+        output: &hex::<64>(concat!(
+            "369ca59515fb6d128641ad3b56ab7f271793bd69e6c9862238dcf5745c96406d",
+            "a43ff8d42994513c548b3949e7b17088968e6a05f7df9d2ecf621c0d3d6f05b9",
+        )),
+    },
+];
+
+/// Outcome of a self-test run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub passed: usize,
+    pub failed: usize,
+}
+
+/// Run every vector in [`VECTORS`] and [`DRBG_VECTORS`], printing the outcome
+/// to `tx`
+///
+/// The cipher used for each vector is picked by key length. Returns `Err` if
+/// any vector failed.
+pub fn run<A128, A192, A256, W>(tx: &mut W) -> Result<Summary, Summary>
+where
+    A128: AesCipher,
+    A192: AesCipher,
+    A256: AesCipher<KeySize = U32>,
+    W: core::fmt::Write + ?Sized,
+{
+    let mut summary = Summary {
+        passed: 0,
+        failed: 0,
+    };
+
+    for vector in VECTORS {
+        let mut buffer = [0; MAX_MESSAGE];
+        let (bits, result) = match vector.key.len() {
+            16 => (128, check::<A128>(vector, &mut buffer)),
+            24 => (192, check::<A192>(vector, &mut buffer)),
+            32 => (256, check::<A256>(vector, &mut buffer)),
+            _ => (0, Err(None)),
+        };
+        let passed = result.is_ok();
+        if passed {
+            summary.passed += 1;
+        } else {
+            summary.failed += 1;
+        }
+        write!(
+            tx,
+            "{} {:?} AES-{} {}\r\n",
+            if passed { "pass" } else { "FAIL" },
+            vector.mode,
+            bits,
+            vector.name
+        )
+        .ok();
+        // An output that matches failed on the way back, decrypting or
+        // unwrapping
+        if let Err(Some(actual)) = result {
+            write!(tx, "{}", Diff::new(vector.output, actual)).ok();
+        }
+    }
+
+    for vector in DRBG_VECTORS {
+        let passed = drbg::check_vector::<A256>(vector);
+        if passed {
+            summary.passed += 1;
+        } else {
+            summary.failed += 1;
+        }
+        write!(
+            tx,
+            "{} CtrDrbg AES-256 {}\r\n",
+            if passed { "pass" } else { "FAIL" },
+            vector.name
+        )
+        .ok();
+        if !passed {
+            let mut buffer = [0; MAX_MESSAGE];
+            if let Some(actual) = drbg::vector_output::<A256>(vector, &mut buffer) {
+                write!(tx, "{}", Diff::new(vector.output, actual)).ok();
+            }
+        }
+    }
+
+    write!(
+        tx,
+        "self-test: {} passed, {} failed\r\n",
+        summary.passed, summary.failed
+    )
+    .ok();
+
+    if summary.failed == 0 {
+        Ok(summary)
+    } else {
+        Err(summary)
+    }
+}
+
+/// Check `vector` with cipher `C`, with its actual output on failure
+fn check<'a, C: AesCipher>(
+    vector: &Vector,
+    buffer: &'a mut [u8; MAX_MESSAGE],
+) -> Result<(), Option<&'a [u8]>> {
+    if check_vector::<C>(vector) {
+        Ok(())
+    } else {
+        Err(vector_output::<C>(vector, buffer))
+    }
+}
@@ -0,0 +1,69 @@
+//! Hexadecimal encoding and decoding
+
+/// Decode a hex string into a byte array at compile time
+///
+/// Panics, failing the build when used in a `const`, if `hex` is not exactly
+/// `2 * N` hex digits.
+pub const fn decode_array<const N: usize>(hex: &str) -> [u8; N] {
+    let digits = hex.as_bytes();
+    assert!(digits.len() == 2 * N, "hex string length mismatch");
+
+    let mut bytes = [0; N];
+    let mut i = 0;
+    while i < N {
+        bytes[i] = (nibble(digits[2 * i]) << 4) | nibble(digits[2 * i + 1]);
+        i += 1;
+    }
+    bytes
+}
+
+const fn nibble(digit: u8) -> u8 {
+    match digit {
+        b'0'..=b'9' => digit - b'0',
+        b'a'..=b'f' => digit - b'a' + 10,
+        b'A'..=b'F' => digit - b'A' + 10,
+        _ => panic!("invalid hex digit"),
+    }
+}
+
+/// Error returned by [`decode`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// Odd number of digits
+    OddLength,
+    /// Not a hex digit
+    InvalidDigit,
+    /// Decoded data does not fit the output buffer
+    TooLong,
+}
+
+/// Decode a hex string into `bytes`, returning the number of bytes written
+pub fn decode(hex: &str, bytes: &mut [u8]) -> Result<usize, Error> {
+    let digits = hex.as_bytes();
+    if digits.len() % 2 != 0 {
+        return Err(Error::OddLength);
+    }
+    if digits.len() / 2 > bytes.len() {
+        return Err(Error::TooLong);
+    }
+
+    for (byte, pair) in bytes.iter_mut().zip(digits.chunks_exact(2)) {
+        let high = (pair[0] as char).to_digit(16).ok_or(Error::InvalidDigit)?;
+        let low = (pair[1] as char).to_digit(16).ok_or(Error::InvalidDigit)?;
+        *byte = (high << 4 | low) as u8;
+    }
+
+    Ok(digits.len() / 2)
+}
+
+/// Formats a byte slice as lowercase hex without separators
+pub struct Hex<'a>(pub &'a [u8]);
+
+impl core::fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
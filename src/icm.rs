@@ -0,0 +1,329 @@
+//! SHA engine on the Integrity Check Monitor
+//!
+//! The ICM hashes memory regions described by descriptors in RAM and writes
+//! the digest back to a hash area. It does no padding and only ever starts
+//! from an initial hash value, so [`Sha1`], [`Sha224`] and [`Sha256`] keep the
+//! running state in software ([`sha::State`]) and use the ICM for the
+//! compression function alone: every run loads the chaining state as the user
+//! initial hash value, hashes a few whole blocks copied to an aligned buffer
+//! and reads the new state back from the hash area.
+//!
+//! The digests implement the `digest` 0.9 traits, so [`hmac::Hmac`] and
+//! [`hkdf::Hkdf`] work on top of them. The ICM has to be handed over with
+//! [`install`] first; it can not be used for region monitoring meanwhile.
+//! Those traits have no room for errors, so an ICM bus error is recorded for
+//! [`check`] instead, and the digests computed since are wrong.
+//!
+//! [`RegionDescriptor`] describes a region for monitoring; together with
+//! [`PaddedMessage`] it covers arbitrary data without hand-made padding.
+//! [`config`] builds validated descriptor lists for the ICM's own region
+//! hashing, [`console`] inspects and controls them from the UART, [`events`]
+//! queues their interrupts for reporting outside the handler, and [`monitor`]
+//! keeps the ICM checking the firmware in flash.
+
+pub mod config;
+pub mod console;
+pub mod events;
+pub mod monitor;
+
+use crate::board::pac::{ICM, MCLK};
+use crate::sha::{self, Algorithm, PaddedMessage, BLOCK_LEN, STATE_LEN};
+use core::cell::RefCell;
+use core::sync::atomic::{compiler_fence, AtomicBool, Ordering};
+use cortex_m::interrupt::{self, Mutex};
+use digest::{
+    consts::{U20, U28, U32, U64},
+    generic_array::GenericArray,
+    BlockInput, FixedOutputDirty, Reset, Update,
+};
+
+pub type HmacSha1 = hmac::Hmac<Sha1>;
+pub type HmacSha224 = hmac::Hmac<Sha224>;
+pub type HmacSha256 = hmac::Hmac<Sha256>;
+pub type HkdfSha1 = hkdf::Hkdf<Sha1>;
+pub type HkdfSha224 = hkdf::Hkdf<Sha224>;
+pub type HkdfSha256 = hkdf::Hkdf<Sha256>;
+
+/// Blocks hashed per ICM run, bounds the time spent with interrupts masked
+const RUN_BLOCKS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// The ICM reported a bus error while reading a block
+    Bus,
+}
+
+/// RCFG bits
+const RCFG_CDWBN: u32 = 1 << 0;
+const RCFG_WRAP: u32 = 1 << 1;
+const RCFG_EOM: u32 = 1 << 2;
+/// RCFG algorithm field offset
+const RCFG_ALGO: u32 = 12;
+
+/// ICM region descriptor as laid out in memory
+///
+/// Descriptors of a list follow each other; the first one has to be 64-byte
+/// aligned.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegionDescriptor {
+    pub raddr: u32,
+    pub rcfg: u32,
+    pub rctrl: u32,
+    pub rnext: u32,
+}
+
+impl RegionDescriptor {
+    /// Write-back region of `blocks` whole blocks at `address`
+    pub fn new(address: *const u32, blocks: usize, algorithm: Algorithm) -> Self {
+        let algo = match algorithm {
+            Algorithm::Sha1 => 0,
+            Algorithm::Sha256 => 1,
+            Algorithm::Sha224 => 4,
+        };
+        Self {
+            raddr: address as u32,
+            rcfg: algo << RCFG_ALGO,
+            rctrl: blocks.saturating_sub(1) as u32,
+            rnext: 0,
+        }
+    }
+
+    /// Write-back region covering `message` including its padding
+    pub fn for_message<const BLOCKS: usize>(
+        message: &PaddedMessage<BLOCKS>,
+        algorithm: Algorithm,
+    ) -> Self {
+        Self::new(message.as_ptr(), message.blocks(), algorithm)
+    }
+
+    /// Compare the digest with the hash area instead of writing it back
+    pub fn compare(mut self) -> Self {
+        self.rcfg |= RCFG_CDWBN;
+        self
+    }
+
+    /// Continue with the first descriptor of the list after this one
+    pub fn wrap(mut self) -> Self {
+        self.rcfg |= RCFG_WRAP;
+        self
+    }
+
+    /// Last descriptor of the list
+    pub fn end_of_list(mut self) -> Self {
+        self.rcfg |= RCFG_EOM;
+        self
+    }
+
+    /// Algorithm selected by RCFG.ALGO, `None` for reserved values
+    pub fn algorithm(&self) -> Option<Algorithm> {
+        match self.rcfg >> RCFG_ALGO & 0b111 {
+            0 => Some(Algorithm::Sha1),
+            1 => Some(Algorithm::Sha256),
+            4 => Some(Algorithm::Sha224),
+            _ => None,
+        }
+    }
+
+    /// Whether the digest is compared rather than written back
+    pub fn is_compare(&self) -> bool {
+        self.rcfg & RCFG_CDWBN != 0
+    }
+
+    /// Whether the ICM continues with the first descriptor after this one
+    pub fn is_wrap(&self) -> bool {
+        self.rcfg & RCFG_WRAP != 0
+    }
+
+    /// Blocks covered by this descriptor
+    pub fn blocks(&self) -> usize {
+        self.rctrl as usize + 1
+    }
+}
+
+/// Descriptor list of the SHA engine, a single region
+#[repr(C, align(64))]
+struct Descriptor(RegionDescriptor);
+
+/// Hash area, 128-byte aligned with one eight word slot per region
+#[repr(C, align(128))]
+struct HashArea([u32; 32]);
+
+/// Message blocks read by the ICM
+#[repr(C, align(4))]
+struct Blocks([u8; RUN_BLOCKS * BLOCK_LEN]);
+
+static ENGINE: Mutex<RefCell<Option<ICM>>> = Mutex::new(RefCell::new(None));
+
+/// A run failed since the last [`check`]
+static FAILED: AtomicBool = AtomicBool::new(false);
+
+// Only touched inside a critical section while ENGINE is borrowed
+static mut DESCRIPTOR: Descriptor = Descriptor(RegionDescriptor {
+    raddr: 0,
+    rcfg: 0,
+    rctrl: 0,
+    rnext: 0,
+});
+static mut HASH_AREA: HashArea = HashArea([0; 32]);
+static mut BLOCKS: Blocks = Blocks([0; RUN_BLOCKS * BLOCK_LEN]);
+
+/// Enable the ICM clocks and hand the ICM to the SHA engine
+pub fn install(icm: ICM, mclk: &mut MCLK) {
+    mclk.ahbmask.modify(|_, w| w.icm_().set_bit());
+    mclk.apbcmask.modify(|_, w| w.icm_().set_bit());
+    icm.ctrl.write(|w| w.swrst().set_bit());
+    interrupt::free(|cs| ENGINE.borrow(cs).replace(Some(icm)));
+}
+
+/// Take the ICM back, e.g. to use it for region monitoring
+pub fn release() -> Option<ICM> {
+    interrupt::free(|cs| ENGINE.borrow(cs).take())
+}
+
+/// Whether every run since the last call went through
+///
+/// Digests finalized after an error are wrong.
+pub fn check() -> Result<(), Error> {
+    if FAILED.swap(false, Ordering::Relaxed) {
+        Err(Error::Bus)
+    } else {
+        Ok(())
+    }
+}
+
+/// Run the compression function of `algorithm` over `blocks`
+///
+/// Interrupts are masked for one run at a time. Panics if the ICM has not been
+/// [`install`]ed; bus errors are left to [`check`].
+fn compress(algorithm: Algorithm, state: &mut [u8; STATE_LEN], blocks: &[u8]) {
+    for run in blocks.chunks(RUN_BLOCKS * BLOCK_LEN) {
+        let result = interrupt::free(|cs| {
+            let engine = ENGINE.borrow(cs).borrow();
+            let icm = engine.as_ref().expect("ICM SHA engine not installed");
+            // Safe, the statics are only used while ENGINE is borrowed
+            unsafe { hash_run(icm, algorithm, state, run) }
+        });
+        if result.is_err() {
+            FAILED.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Hash one run of at most [`RUN_BLOCKS`] blocks, continuing from `state`
+///
+/// # Safety
+///
+/// Must have exclusive access to the descriptor, hash area and block buffer.
+unsafe fn hash_run(
+    icm: &ICM,
+    algorithm: Algorithm,
+    state: &mut [u8; STATE_LEN],
+    run: &[u8],
+) -> Result<(), Error> {
+    // SHA-224 write-back is truncated to seven words, which can not be
+    // chained, so it runs as SHA-256 from the SHA-224 initial value
+    let chained = match algorithm {
+        Algorithm::Sha1 => Algorithm::Sha1,
+        Algorithm::Sha224 | Algorithm::Sha256 => Algorithm::Sha256,
+    };
+
+    BLOCKS.0[..run.len()].copy_from_slice(run);
+    DESCRIPTOR = Descriptor(
+        RegionDescriptor::new(BLOCKS.0.as_ptr().cast(), run.len() / BLOCK_LEN, chained)
+            .end_of_list(),
+    );
+
+    icm.ctrl.write(|w| w.swrst().set_bit());
+    icm.cfg.write(|w| {
+        let w = w.uihash().set_bit();
+        match chained {
+            Algorithm::Sha1 => w.ualgo().sha1(),
+            _ => w.ualgo().sha256(),
+        }
+    });
+    // The hash area and initial value hold the state as little endian words
+    for (uihval, word) in icm.uihval.iter().zip(state.chunks_exact(4)) {
+        uihval.write(|w| w.bits(u32::from_le_bytes([word[0], word[1], word[2], word[3]])));
+    }
+    icm.dscr.write(|w| w.bits(&DESCRIPTOR as *const _ as u32));
+    icm.hash.write(|w| w.bits(&HASH_AREA as *const _ as u32));
+
+    compiler_fence(Ordering::SeqCst);
+    icm.ctrl.write(|w| w.enable().set_bit());
+    let result = loop {
+        let isr = icm.isr.read();
+        if isr.rbe().bits() & 1 != 0 {
+            break Err(Error::Bus);
+        }
+        if isr.rhc().bits() & 1 != 0 {
+            break Ok(());
+        }
+    };
+    icm.ctrl.write(|w| w.disable().set_bit());
+    while icm.sr.read().enable().bit_is_set() {}
+    compiler_fence(Ordering::SeqCst);
+    result?;
+
+    let hash = &HASH_AREA.0;
+    for (i, bytes) in state[..algorithm.state_len()]
+        .chunks_exact_mut(4)
+        .enumerate()
+    {
+        let word = core::ptr::read_volatile(&hash[i]);
+        bytes.copy_from_slice(&word.to_le_bytes());
+    }
+    Ok(())
+}
+
+macro_rules! icm_digest {
+    ($name:ident, $algorithm:expr, $size:ty, $doc:literal) => {
+        #[doc = $doc]
+        #[derive(Clone)]
+        pub struct $name(sha::State);
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self(sha::State::new($algorithm))
+            }
+        }
+
+        impl BlockInput for $name {
+            type BlockSize = U64;
+        }
+
+        impl Update for $name {
+            fn update(&mut self, data: impl AsRef<[u8]>) {
+                self.0.update(data.as_ref(), compress);
+            }
+        }
+
+        impl FixedOutputDirty for $name {
+            type OutputSize = $size;
+
+            fn finalize_into_dirty(&mut self, out: &mut GenericArray<u8, $size>) {
+                self.0.finalize(out, compress);
+            }
+        }
+
+        impl Reset for $name {
+            fn reset(&mut self) {
+                *self = Self::default();
+            }
+        }
+    };
+}
+
+icm_digest!(Sha1, Algorithm::Sha1, U20, "SHA-1 computed by the ICM");
+icm_digest!(
+    Sha224,
+    Algorithm::Sha224,
+    U28,
+    "SHA-224 computed by the ICM"
+);
+icm_digest!(
+    Sha256,
+    Algorithm::Sha256,
+    U32,
+    "SHA-256 computed by the ICM"
+);
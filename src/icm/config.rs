@@ -0,0 +1,779 @@
+//! Typed ICM configuration
+//!
+//! [`IcmConfig`] describes the main descriptor list region by region and the
+//! controller settings around it:
+//!
+//! ```ignore
+//! let config = IcmConfig::new()
+//!     .region(&DATA, Algorithm::Sha256)
+//!     .compare()
+//!     .interrupt_on_mismatch()
+//!     .wrap()
+//!     .build(Memory::take().unwrap())
+//!     .map_err(|(error, _memory)| error)?;
+//! config.start(&icm);
+//! ```
+//!
+//! [`IcmConfig::build`] validates the description and encodes it into a
+//! [`Memory`], the statically owned descriptor list and hash area with the
+//! alignment the ICM needs. The resulting [`Configuration`] owns that memory
+//! until it is handed back with [`Configuration::into_memory`].
+//!
+//! A region does not have to be contiguous: [`IcmConfig::chunk`] continues it
+//! with further memory through a secondary list, linked from the region's
+//! descriptor with RNEXT and hashed into the same digest.
+//!
+//! Descriptors and register values are encoded here rather than through the
+//! HAL's `icm` types. Those fix every region in the type, `Region<Region0>`
+//! and `Regions::region0` onwards, so they can not take regions added at run
+//! time, and they only build for the target, where the encoding tests below
+//! can not run. [`Configuration::start`] writes them to the ICM.
+
+use super::{Algorithm, RegionDescriptor, ICM, RCFG_CDWBN, RCFG_EOM, RCFG_WRAP};
+use crate::sha::BLOCK_WORDS;
+
+/// Regions in the main list, one hash area slot each
+pub const MAX_REGIONS: usize = 4;
+/// Words per hash area slot
+pub const DIGEST_WORDS: usize = 8;
+/// Blocks covered by one descriptor, RCTRL.TRSIZE is 16 bits wide
+pub const MAX_BLOCKS: usize = 1 << 16;
+/// Descriptors in the secondary list of one region
+pub const MAX_SECONDARY: usize = 3;
+
+/// CFG bits
+const CFG_BBC: u32 = 4;
+pub(super) const CFG_ASCD: u32 = 1 << 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// More than [`MAX_REGIONS`] regions
+    TooManyRegions,
+    /// More than [`MAX_SECONDARY`] chunks after a region
+    TooManyChunks,
+    /// No region was added
+    NoRegions,
+    /// A region setting was used before the first region
+    NoRegion,
+    /// A region is empty or not a whole number of blocks
+    NotWholeBlocks,
+    /// A region is longer than [`MAX_BLOCKS`] blocks
+    TooLong,
+    /// A region or RNEXT link is not word aligned
+    Misaligned,
+    /// A mismatch interrupt on a region that writes its digest back
+    MismatchWithoutCompare,
+    /// The list does not end in exactly one WRAP or EOM descriptor
+    ListEnd,
+    /// A secondary list is not linked in order from its region, or has WRAP
+    /// or EOM set
+    SecondaryList,
+    /// Bus burden control above 15
+    BusBurden,
+}
+
+/// Per-region interrupt sources, in IER/IDR/IMR/ISR field order
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interrupt {
+    /// Region hash completed
+    HashCompleted,
+    /// Region digest mismatch
+    DigestMismatch,
+    /// Region bus error
+    BusError,
+    /// Wrap condition detected
+    WrapCondition,
+    /// End bit condition detected
+    EndCondition,
+    /// Region status updated
+    StatusUpdated,
+}
+
+impl Interrupt {
+    /// Bit of `region` in the interrupt registers
+    pub const fn bit(self, region: usize) -> u32 {
+        1 << (self as usize * MAX_REGIONS + region)
+    }
+
+    /// Mask of the regions with this source set in interrupt register `bits`
+    pub const fn regions(self, bits: u32) -> u8 {
+        (bits >> (self as usize * MAX_REGIONS)) as u8 & ((1 << MAX_REGIONS) - 1)
+    }
+}
+
+/// Register values encoded from an [`IcmConfig`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Registers {
+    pub cfg: u32,
+    pub ier: u32,
+    /// Regions with monitoring enabled, CTRL.RMEN
+    pub rmen: u8,
+}
+
+/// Main descriptor list
+#[derive(Debug)]
+#[repr(C, align(64))]
+struct DescriptorList([RegionDescriptor; MAX_REGIONS]);
+
+/// Hash area, one slot per region
+#[derive(Debug)]
+#[repr(C, align(128))]
+struct HashArea([[u32; DIGEST_WORDS]; MAX_REGIONS]);
+
+/// Secondary lists, one per region
+#[derive(Debug)]
+#[repr(C, align(64))]
+struct SecondaryLists([[RegionDescriptor; MAX_SECONDARY]; MAX_REGIONS]);
+
+/// Descriptor lists and hash area read and written by the ICM
+#[derive(Debug)]
+#[repr(C)]
+pub struct Memory {
+    list: DescriptorList,
+    hash: HashArea,
+    secondary: SecondaryLists,
+}
+
+const EMPTY: RegionDescriptor = RegionDescriptor {
+    raddr: 0,
+    rcfg: 0,
+    rctrl: 0,
+    rnext: 0,
+};
+
+impl Memory {
+    pub const fn new() -> Self {
+        Self {
+            list: DescriptorList([EMPTY; MAX_REGIONS]),
+            hash: HashArea([[0; DIGEST_WORDS]; MAX_REGIONS]),
+            secondary: SecondaryLists([[EMPTY; MAX_SECONDARY]; MAX_REGIONS]),
+        }
+    }
+
+    /// The one statically allocated instance, `None` after the first call
+    pub fn take() -> Option<&'static mut Self> {
+        cortex_m::singleton!(: Memory = Memory::new())
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RegionConfig {
+    descriptor: RegionDescriptor,
+    /// Chunks after the first, in secondary list order
+    secondary: [RegionDescriptor; MAX_SECONDARY],
+    chunks: usize,
+    /// Enabled interrupts as IER bits
+    ier: u32,
+}
+
+/// Blocks in `data`, which has to be whole blocks
+fn blocks(data: &[u32]) -> Result<usize, Error> {
+    if data.is_empty() || data.len() % BLOCK_WORDS != 0 {
+        return Err(Error::NotWholeBlocks);
+    }
+    let blocks = data.len() / BLOCK_WORDS;
+    if blocks > MAX_BLOCKS {
+        return Err(Error::TooLong);
+    }
+    Ok(blocks)
+}
+
+/// Builder for the main descriptor list and controller settings
+///
+/// Region settings apply to the region added last. Mistakes are kept and
+/// reported by [`IcmConfig::build`].
+#[derive(Debug, Clone)]
+pub struct IcmConfig {
+    regions: [Option<RegionConfig>; MAX_REGIONS],
+    count: usize,
+    wrap: bool,
+    bus_burden: u8,
+    auto_compare: bool,
+    error: Option<Error>,
+}
+
+impl IcmConfig {
+    /// No regions, a single pass without bus burden control
+    pub const fn new() -> Self {
+        Self {
+            regions: [None; MAX_REGIONS],
+            count: 0,
+            wrap: false,
+            bus_burden: 0,
+            auto_compare: false,
+            error: None,
+        }
+    }
+
+    fn fail(mut self, error: Error) -> Self {
+        self.error.get_or_insert(error);
+        self
+    }
+
+    fn last(mut self, f: impl FnOnce(&mut RegionConfig, usize) -> Result<(), Error>) -> Self {
+        match self.count.checked_sub(1) {
+            Some(index) => match f(self.regions[index].as_mut().unwrap(), index) {
+                Ok(()) => self,
+                Err(error) => self.fail(error),
+            },
+            None => self.fail(Error::NoRegion),
+        }
+    }
+
+    /// Hash `data` with `algorithm`, writing the digest back
+    ///
+    /// `data` has to be whole blocks. The ICM keeps reading it for as long as
+    /// the configuration is running, which can be for good, so it is borrowed
+    /// for `'static`.
+    pub fn region(mut self, data: &'static [u32], algorithm: Algorithm) -> Self {
+        if self.count == MAX_REGIONS {
+            return self.fail(Error::TooManyRegions);
+        }
+        let blocks = match blocks(data) {
+            Ok(blocks) => blocks,
+            Err(error) => return self.fail(error),
+        };
+
+        self.regions[self.count] = Some(RegionConfig {
+            descriptor: RegionDescriptor::new(data.as_ptr(), blocks, algorithm),
+            secondary: [EMPTY; MAX_SECONDARY],
+            chunks: 0,
+            ier: 0,
+        });
+        self.count += 1;
+        self
+    }
+
+    /// Continue the last region with `data`, through its secondary list
+    ///
+    /// `data` has to be whole blocks and is hashed into the same digest, after
+    /// the region's earlier chunks. It is borrowed for `'static` like the
+    /// region's.
+    pub fn chunk(self, data: &'static [u32]) -> Self {
+        self.last(|region, _| {
+            let descriptor = region
+                .secondary
+                .get_mut(region.chunks)
+                .ok_or(Error::TooManyChunks)?;
+            *descriptor = RegionDescriptor {
+                raddr: data.as_ptr() as u32,
+                rctrl: blocks(data)? as u32 - 1,
+                ..EMPTY
+            };
+            region.chunks += 1;
+            Ok(())
+        })
+    }
+
+    /// Compare the digest of the last region with its hash area slot
+    pub fn compare(self) -> Self {
+        self.last(|region, _| {
+            region.descriptor = region.descriptor.compare();
+            Ok(())
+        })
+    }
+
+    /// Raise the `ICM` interrupt on `interrupt` for the last region
+    pub fn interrupt(self, interrupt: Interrupt) -> Self {
+        self.last(|region, index| {
+            region.ier |= interrupt.bit(index);
+            Ok(())
+        })
+    }
+
+    /// Raise the `ICM` interrupt when the last region's digest mismatches
+    pub fn interrupt_on_mismatch(self) -> Self {
+        self.interrupt(Interrupt::DigestMismatch)
+    }
+
+    /// Raise the `ICM` interrupt when the last region has been hashed
+    pub fn interrupt_on_hash_complete(self) -> Self {
+        self.interrupt(Interrupt::HashCompleted)
+    }
+
+    /// Start over from the first region after the last, monitoring for good
+    pub fn wrap(mut self) -> Self {
+        self.wrap = true;
+        self
+    }
+
+    /// Wait 2^`bbc` cycles between blocks to keep the bus available
+    pub fn bus_burden(mut self, bbc: u8) -> Self {
+        if bbc > 15 {
+            return self.fail(Error::BusBurden);
+        }
+        self.bus_burden = bbc;
+        self
+    }
+
+    /// Switch write-back regions to compare after their first digest
+    pub fn auto_switch_to_compare(mut self) -> Self {
+        self.auto_compare = true;
+        self
+    }
+
+    /// Validate the configuration and write the descriptor list to `memory`
+    ///
+    /// The hash area is left as it is, so digests written back under one
+    /// configuration serve as reference for the next. On error `memory` is
+    /// handed back, for another configuration to be built in.
+    pub fn build(
+        &self,
+        memory: &'static mut Memory,
+    ) -> Result<Configuration, (Error, &'static mut Memory)> {
+        match self.encode(memory) {
+            Ok((chunks, registers)) => Ok(Configuration {
+                memory,
+                count: self.count,
+                chunks,
+                registers,
+            }),
+            Err(error) => Err((error, memory)),
+        }
+    }
+
+    /// Write the descriptor lists to `memory`, returning the secondary list
+    /// lengths and register values
+    fn encode(&self, memory: &mut Memory) -> Result<([usize; MAX_REGIONS], Registers), Error> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        if self.count == 0 {
+            return Err(Error::NoRegions);
+        }
+
+        let mut list = [EMPTY; MAX_REGIONS];
+        let mut chunks = [0; MAX_REGIONS];
+        let mut ier = 0;
+        for (index, region) in self.regions.iter().flatten().enumerate() {
+            let compare = region.descriptor.rcfg & RCFG_CDWBN != 0;
+            let mismatch = region.ier & Interrupt::DigestMismatch.bit(index) != 0;
+            if mismatch && !compare && !self.auto_compare {
+                return Err(Error::MismatchWithoutCompare);
+            }
+            list[index] = region.descriptor;
+            ier |= region.ier;
+
+            // Secondary descriptors share the region's configuration
+            let secondary = &mut memory.secondary.0[index][..region.chunks];
+            for (descriptor, chunk) in secondary.iter_mut().zip(&region.secondary) {
+                *descriptor = RegionDescriptor {
+                    rcfg: region.descriptor.rcfg,
+                    ..*chunk
+                };
+            }
+            link(&mut list[index], secondary);
+            chunks[index] = region.chunks;
+        }
+        let last = self.count - 1;
+        list[last] = if self.wrap {
+            list[last].wrap()
+        } else {
+            list[last].end_of_list()
+        };
+        validate(&list[..self.count])?;
+        for (descriptor, (secondary, &chunks)) in
+            list.iter().zip(memory.secondary.0.iter().zip(&chunks))
+        {
+            validate_secondary(descriptor, &secondary[..chunks])?;
+        }
+
+        memory.list.0 = list;
+        let registers = Registers {
+            cfg: (self.bus_burden as u32) << CFG_BBC | if self.auto_compare { CFG_ASCD } else { 0 },
+            ier,
+            rmen: (1 << self.count) - 1,
+        };
+        Ok((chunks, registers))
+    }
+}
+
+impl Default for IcmConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Check a main descriptor list
+///
+/// Every region and RNEXT link has to be word aligned, and exactly the last
+/// descriptor has to end the list with either WRAP or EOM. Where the list
+/// itself goes is up to [`Memory`], which aligns it for DSCR.
+pub fn validate(list: &[RegionDescriptor]) -> Result<(), Error> {
+    let (last, rest) = list.split_last().ok_or(Error::NoRegions)?;
+    for descriptor in list {
+        if descriptor.raddr % 4 != 0 || descriptor.rnext % 4 != 0 {
+            return Err(Error::Misaligned);
+        }
+        if descriptor.rctrl as usize >= MAX_BLOCKS {
+            return Err(Error::TooLong);
+        }
+    }
+    let ends = |d: &RegionDescriptor| d.rcfg & (RCFG_WRAP | RCFG_EOM);
+    if rest.iter().any(|d| ends(d) != 0) || !matches!(ends(last), RCFG_WRAP | RCFG_EOM) {
+        return Err(Error::ListEnd);
+    }
+    Ok(())
+}
+
+/// Point `descriptor` at `secondary` and chain the secondary descriptors
+fn link(descriptor: &mut RegionDescriptor, secondary: &mut [RegionDescriptor]) {
+    let mut next = 0;
+    for chunk in secondary.iter_mut().rev() {
+        chunk.rnext = next;
+        next = chunk as *const _ as u32;
+    }
+    descriptor.rnext = next;
+}
+
+/// Check the secondary list of the main list descriptor `descriptor`
+///
+/// The list has to be linked in order from `descriptor` through RNEXT, end
+/// with a zero RNEXT, and not use WRAP or EOM.
+pub fn validate_secondary(
+    descriptor: &RegionDescriptor,
+    list: &[RegionDescriptor],
+) -> Result<(), Error> {
+    let mut next = descriptor.rnext;
+    for chunk in list {
+        if next != chunk as *const _ as u32 || chunk.rcfg & (RCFG_WRAP | RCFG_EOM) != 0 {
+            return Err(Error::SecondaryList);
+        }
+        if chunk.raddr % 4 != 0 {
+            return Err(Error::Misaligned);
+        }
+        if chunk.rctrl as usize >= MAX_BLOCKS {
+            return Err(Error::TooLong);
+        }
+        next = chunk.rnext;
+    }
+    if next != 0 {
+        return Err(Error::SecondaryList);
+    }
+    Ok(())
+}
+
+/// Validated descriptor lists in their [`Memory`], with the register values
+pub struct Configuration {
+    memory: &'static mut Memory,
+    count: usize,
+    /// Secondary list length per region
+    chunks: [usize; MAX_REGIONS],
+    registers: Registers,
+}
+
+impl Configuration {
+    /// The main descriptor list
+    pub fn descriptors(&self) -> &[RegionDescriptor] {
+        &self.memory.list.0[..self.count]
+    }
+
+    /// Secondary list of `region`, empty for a contiguous region
+    pub fn secondary(&self, region: usize) -> &[RegionDescriptor] {
+        match self.chunks[..self.count].get(region) {
+            Some(&chunks) => &self.memory.secondary.0[region][..chunks],
+            None => &[],
+        }
+    }
+
+    pub fn registers(&self) -> Registers {
+        self.registers
+    }
+
+    /// Hash area slot of `region`, in hash area order
+    pub fn digest(&self, region: usize) -> Option<[u32; DIGEST_WORDS]> {
+        let slot = self.memory.hash.0[..self.count].get(region)?;
+        let mut digest = [0; DIGEST_WORDS];
+        for (word, stored) in digest.iter_mut().zip(slot) {
+            // The ICM writes the hash area behind the compiler's back
+            *word = unsafe { core::ptr::read_volatile(stored) };
+        }
+        Some(digest)
+    }
+
+    /// Set the reference digest `region` is compared against
+    pub fn set_digest(&mut self, region: usize, digest: [u32; DIGEST_WORDS]) {
+        if region < self.count {
+            // Safe, the slot is owned through the memory borrow
+            unsafe { core::ptr::write_volatile(&mut self.memory.hash.0[region], digest) };
+        }
+    }
+
+    /// Reset the ICM, program it with this configuration and enable it
+    pub fn start(&self, icm: &ICM) {
+        icm.ctrl.write(|w| w.swrst().set_bit());
+
+        let registers = self.registers;
+        // Safe, the values come from a validated configuration and the
+        // memory stays borrowed while the ICM uses it
+        unsafe {
+            icm.cfg.write(|w| w.bits(registers.cfg));
+            icm.dscr
+                .write(|w| w.bits(&self.memory.list as *const _ as u32));
+            icm.hash
+                .write(|w| w.bits(&self.memory.hash as *const _ as u32));
+            icm.ier.write(|w| w.bits(registers.ier));
+            icm.ctrl.write(|w| w.rmen().bits(registers.rmen));
+        }
+        icm.ctrl.write(|w| w.enable().set_bit());
+    }
+
+    /// Give the memory back, e.g. to build the next configuration
+    ///
+    /// The ICM must be disabled or reset first.
+    pub fn into_memory(self) -> &'static mut Memory {
+        self.memory
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::boxed::Box;
+
+    static ONE: [u32; 16] = [0; 16];
+    static TWO: [u32; 32] = [0; 32];
+
+    fn memory() -> &'static mut Memory {
+        Box::leak(Box::new(Memory::new()))
+    }
+
+    #[test]
+    fn layout() {
+        let memory = memory();
+        assert_eq!(&memory.list as *const _ as usize % 64, 0);
+        assert_eq!(&memory.hash as *const _ as usize % 128, 0);
+        assert_eq!(&memory.secondary as *const _ as usize % 64, 0);
+        assert_eq!(core::mem::size_of::<RegionDescriptor>(), 16);
+    }
+
+    #[test]
+    fn encoding() {
+        let config = IcmConfig::new()
+            .region(&ONE, Algorithm::Sha1)
+            .interrupt_on_hash_complete()
+            .region(&TWO, Algorithm::Sha224)
+            .compare()
+            .interrupt_on_mismatch()
+            .region(&ONE, Algorithm::Sha256)
+            .interrupt(Interrupt::BusError)
+            .wrap()
+            .bus_burden(4)
+            .build(memory())
+            .unwrap();
+
+        assert_eq!(
+            config.descriptors(),
+            &[
+                RegionDescriptor {
+                    raddr: ONE.as_ptr() as u32,
+                    rcfg: 0x0000,
+                    rctrl: 0,
+                    rnext: 0,
+                },
+                RegionDescriptor {
+                    raddr: TWO.as_ptr() as u32,
+                    rcfg: 0x4001,
+                    rctrl: 1,
+                    rnext: 0,
+                },
+                RegionDescriptor {
+                    raddr: ONE.as_ptr() as u32,
+                    rcfg: 0x1002,
+                    rctrl: 0,
+                    rnext: 0,
+                },
+            ]
+        );
+        assert_eq!(
+            config.registers(),
+            Registers {
+                cfg: 0x40,
+                ier: 0x0000_0421,
+                rmen: 0b0111,
+            }
+        );
+    }
+
+    #[test]
+    fn single_pass_ends_list() {
+        let config = IcmConfig::new()
+            .region(&ONE, Algorithm::Sha256)
+            .auto_switch_to_compare()
+            .interrupt_on_mismatch()
+            .build(memory())
+            .unwrap();
+        assert_eq!(config.descriptors()[0].rcfg, 0x1004);
+        assert_eq!(config.registers().cfg, 0x100);
+        assert_eq!(config.registers().ier, 0x10);
+    }
+
+    #[test]
+    fn hash_area_kept() {
+        let mut config = IcmConfig::new()
+            .region(&ONE, Algorithm::Sha256)
+            .build(memory())
+            .unwrap();
+        config.set_digest(0, [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(config.digest(1), None);
+
+        // A rejected configuration hands the memory back
+        let (error, memory) = IcmConfig::new().build(config.into_memory()).err().unwrap();
+        assert_eq!(error, Error::NoRegions);
+
+        let config = IcmConfig::new()
+            .region(&TWO, Algorithm::Sha256)
+            .compare()
+            .build(memory)
+            .unwrap();
+        assert_eq!(config.digest(0), Some([1, 2, 3, 4, 5, 6, 7, 8]));
+    }
+
+    #[test]
+    fn secondary_lists() {
+        let config = IcmConfig::new()
+            .region(&ONE, Algorithm::Sha1)
+            .region(&TWO, Algorithm::Sha256)
+            .chunk(&ONE)
+            .chunk(&TWO)
+            .compare()
+            .wrap()
+            .build(memory())
+            .unwrap();
+
+        assert!(config.secondary(0).is_empty());
+        assert_eq!(config.descriptors()[0].rnext, 0);
+        let secondary = config.secondary(1);
+        assert_eq!(
+            config.descriptors()[1].rnext,
+            &secondary[0] as *const _ as u32
+        );
+        assert_eq!(config.descriptors()[1].rcfg, 0x1003);
+        assert_eq!(
+            secondary,
+            &[
+                RegionDescriptor {
+                    raddr: ONE.as_ptr() as u32,
+                    rcfg: 0x1001,
+                    rctrl: 0,
+                    rnext: &secondary[1] as *const _ as u32,
+                },
+                RegionDescriptor {
+                    raddr: TWO.as_ptr() as u32,
+                    rcfg: 0x1001,
+                    rctrl: 1,
+                    rnext: 0,
+                },
+            ]
+        );
+        assert_eq!(config.secondary(2), &[]);
+    }
+
+    #[test]
+    fn secondary_validation() {
+        let mut list = [RegionDescriptor::new(ONE.as_ptr(), 1, Algorithm::Sha256); 2];
+        let mut descriptor = list[0];
+        link(&mut descriptor, &mut list);
+        assert_eq!(validate_secondary(&descriptor, &list), Ok(()));
+        assert_eq!(
+            validate_secondary(&descriptor, &list[..1]),
+            Err(Error::SecondaryList)
+        );
+        assert_eq!(validate_secondary(&EMPTY, &list), Err(Error::SecondaryList));
+
+        let mut wrapped = list;
+        wrapped[1] = wrapped[1].wrap();
+        link(&mut descriptor, &mut wrapped);
+        assert_eq!(
+            validate_secondary(&descriptor, &wrapped),
+            Err(Error::SecondaryList)
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let build = |config: IcmConfig| config.build(memory()).err().map(|(error, _)| error);
+        assert_eq!(build(IcmConfig::new()), Some(Error::NoRegions));
+        assert_eq!(build(IcmConfig::new().compare()), Some(Error::NoRegion));
+        assert_eq!(
+            build(IcmConfig::new().region(&ONE[..15], Algorithm::Sha1)),
+            Some(Error::NotWholeBlocks)
+        );
+        assert_eq!(
+            build(IcmConfig::new().region(&[], Algorithm::Sha1)),
+            Some(Error::NotWholeBlocks)
+        );
+        assert_eq!(
+            build(
+                IcmConfig::new()
+                    .region(&ONE, Algorithm::Sha1)
+                    .interrupt_on_mismatch()
+            ),
+            Some(Error::MismatchWithoutCompare)
+        );
+        assert_eq!(
+            build(
+                IcmConfig::new()
+                    .region(&ONE, Algorithm::Sha1)
+                    .bus_burden(16)
+            ),
+            Some(Error::BusBurden)
+        );
+        let mut full = IcmConfig::new();
+        for _ in 0..=MAX_REGIONS {
+            full = full.region(&ONE, Algorithm::Sha1);
+        }
+        assert_eq!(build(full), Some(Error::TooManyRegions));
+        let mut chunks = IcmConfig::new().region(&ONE, Algorithm::Sha1);
+        for _ in 0..=MAX_SECONDARY {
+            chunks = chunks.chunk(&ONE);
+        }
+        assert_eq!(build(chunks), Some(Error::TooManyChunks));
+        assert_eq!(
+            build(
+                IcmConfig::new()
+                    .region(&ONE, Algorithm::Sha1)
+                    .chunk(&ONE[1..])
+            ),
+            Some(Error::NotWholeBlocks)
+        );
+    }
+
+    #[test]
+    fn list_validation() {
+        let descriptor = RegionDescriptor::new(ONE.as_ptr(), 1, Algorithm::Sha256);
+        assert_eq!(validate(&[]), Err(Error::NoRegions));
+        assert_eq!(validate(&[descriptor]), Err(Error::ListEnd));
+        assert_eq!(validate(&[descriptor.end_of_list()]), Ok(()));
+        assert_eq!(validate(&[descriptor.wrap()]), Ok(()));
+        assert_eq!(
+            validate(&[descriptor.wrap().end_of_list()]),
+            Err(Error::ListEnd)
+        );
+        assert_eq!(
+            validate(&[descriptor.end_of_list(), descriptor.wrap()]),
+            Err(Error::ListEnd)
+        );
+        let misaligned = RegionDescriptor {
+            raddr: descriptor.raddr + 2,
+            ..descriptor
+        };
+        assert_eq!(
+            validate(&[misaligned.end_of_list()]),
+            Err(Error::Misaligned)
+        );
+        let long = RegionDescriptor {
+            rctrl: MAX_BLOCKS as u32,
+            ..descriptor
+        };
+        assert_eq!(validate(&[long.wrap()]), Err(Error::TooLong));
+    }
+}
@@ -0,0 +1,176 @@
+//! `icm` console commands
+//!
+//! Shows and pokes a running [`Configuration`]: region setup and status, the
+//! hash area, monitoring of single regions and recomputing digests. The
+//! interrupt flags are cleared when read, so the caller keeps the ones seen
+//! last and passes them in.
+
+use super::{
+    config::{Configuration, CFG_ASCD, DIGEST_WORDS, MAX_REGIONS},
+    ICM,
+};
+use core::fmt;
+
+/// Command summary, printed on usage errors
+pub const USAGE: &str = "usage:\r\n\
+    \x20 icm status\r\n\
+    \x20 icm digest <region>\r\n\
+    \x20 icm monitor on|off <region>\r\n\
+    \x20 icm rehash\r\n";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// Unknown command, or missing or extra arguments
+    Usage,
+    /// The ICM has not been configured
+    NotConfigured,
+    /// No such region in the configuration
+    NoRegion,
+}
+
+/// Interrupt register bits, one group of four regions per source in
+/// [`Interrupt`](super::config::Interrupt) order
+pub struct Flags(pub u32);
+
+impl fmt::Display for Flags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sources = ["rhc", "rdm", "rbe", "rwc", "rec", "rsu"];
+        for (index, name) in sources.iter().enumerate() {
+            let bits = self.0 >> (index * MAX_REGIONS) & ((1 << MAX_REGIONS) - 1);
+            if index > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{} {:04b}", name, bits)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run the `icm` command with the whitespace separated `args` after it
+///
+/// `flags` are the interrupt status flags last read from ISR.
+pub fn execute<'a, I, W>(
+    mut args: I,
+    icm: &ICM,
+    config: Option<&Configuration>,
+    flags: u32,
+    tx: &mut W,
+) -> Result<(), Error>
+where
+    I: Iterator<Item = &'a str>,
+    W: fmt::Write + ?Sized,
+{
+    let config = config.ok_or(Error::NotConfigured)?;
+    let count = config.descriptors().len();
+    let region = |arg: Option<&str>| match arg.and_then(|arg| arg.parse().ok()) {
+        Some(region) if region < count => Ok(region),
+        Some(_) => Err(Error::NoRegion),
+        None => Err(Error::Usage),
+    };
+
+    match args.next() {
+        Some("status") => {
+            end(args)?;
+            status(icm, config, flags, tx);
+        }
+        Some("digest") => {
+            let region = region(args.next())?;
+            end(args)?;
+            let digest = config.digest(region).ok_or(Error::NoRegion)?;
+            for (index, word) in digest.iter().enumerate() {
+                let end = if index + 1 == DIGEST_WORDS {
+                    "\r\n"
+                } else {
+                    " "
+                };
+                write!(tx, "{:08x}{}", word, end).ok();
+            }
+        }
+        Some("monitor") => {
+            let on = match args.next() {
+                Some("on") => true,
+                Some("off") => false,
+                _ => return Err(Error::Usage),
+            };
+            let region = region(args.next())?;
+            end(args)?;
+            // Safe, only touches monitoring of a configured region
+            unsafe {
+                if on {
+                    icm.ctrl.write(|w| w.rmen().bits(1 << region));
+                } else {
+                    icm.ctrl.write(|w| w.rmdis().bits(1 << region));
+                }
+            }
+        }
+        Some("rehash") => {
+            end(args)?;
+            // Safe, only requests new digests of configured regions
+            unsafe {
+                icm.ctrl.write(|w| w.rehash().bits((1 << count) - 1));
+            }
+        }
+        _ => return Err(Error::Usage),
+    }
+    Ok(())
+}
+
+/// Fail on extra arguments
+fn end<'a>(mut args: impl Iterator<Item = &'a str>) -> Result<(), Error> {
+    match args.next() {
+        Some(_) => Err(Error::Usage),
+        None => Ok(()),
+    }
+}
+
+/// Print the controller state and every region of `config`
+fn status<W: fmt::Write + ?Sized>(icm: &ICM, config: &Configuration, flags: u32, tx: &mut W) {
+    let sr = icm.sr.read();
+    let descriptors = config.descriptors();
+    let wrap = descriptors.last().map_or(false, |d| d.is_wrap());
+    write!(
+        tx,
+        "ICM {}, {}{}\r\n",
+        if sr.enable().bit_is_set() {
+            "enabled"
+        } else {
+            "disabled"
+        },
+        if wrap { "monitoring" } else { "single pass" },
+        if config.registers().cfg & CFG_ASCD != 0 {
+            ", auto switch to compare"
+        } else {
+            ""
+        },
+    )
+    .ok();
+
+    let disabled = sr.rmdis().bits();
+    for (index, descriptor) in descriptors.iter().enumerate() {
+        let secondary = config.secondary(index);
+        let blocks = descriptor.blocks() + secondary.iter().map(|d| d.blocks()).sum::<usize>();
+        write!(
+            tx,
+            "region {}: {}, {}, {} blocks in {} chunks at {:#010x}, monitoring {}\r\n",
+            index,
+            descriptor.algorithm().map_or("reserved", |a| a.name()),
+            if descriptor.is_compare() {
+                "compare"
+            } else {
+                "write back"
+            },
+            blocks,
+            1 + secondary.len(),
+            descriptor.raddr,
+            if disabled & 1 << index != 0 {
+                "off"
+            } else {
+                "on"
+            },
+        )
+        .ok();
+    }
+
+    write!(tx, "interrupts: {}\r\n", Flags(icm.imr.read().bits())).ok();
+    write!(tx, "last flags: {}\r\n", Flags(flags)).ok();
+}
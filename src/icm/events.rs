@@ -0,0 +1,225 @@
+//! ICM interrupt events
+//!
+//! The `ICM` interrupt only has to [`capture`] the interrupt status: every
+//! raised source is decoded into an [`Event`] and queued, reporting and any
+//! reconfiguration are left to a lower priority task draining the queue.
+//!
+//! ```ignore
+//! // ICM interrupt
+//! events::capture(&icm, &mut producer);
+//!
+//! // Software task
+//! while let Some(event) = consumer.dequeue() {
+//!     write!(tx, "{}\r\n", event).ok();
+//! }
+//! ```
+
+use super::{config::Interrupt, ICM};
+use core::fmt;
+use core::sync::atomic::{AtomicU32, Ordering};
+use heapless::spsc;
+
+/// Queue capacity, one slot is kept free by the queue
+pub const QUEUE_LEN: usize = 16;
+
+pub type Queue = spsc::Queue<Event, QUEUE_LEN>;
+pub type Producer<'a> = spsc::Producer<'a, Event, QUEUE_LEN>;
+pub type Consumer<'a> = spsc::Consumer<'a, Event, QUEUE_LEN>;
+
+/// Per-region sources, in interrupt register order
+const SOURCES: [Interrupt; 6] = [
+    Interrupt::HashCompleted,
+    Interrupt::DigestMismatch,
+    Interrupt::BusError,
+    Interrupt::WrapCondition,
+    Interrupt::EndCondition,
+    Interrupt::StatusUpdated,
+];
+
+/// Undefined register access bit, not tied to a region
+const URAD: u32 = 1 << 24;
+
+/// Events lost to a full queue since last read
+static DROPPED: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    /// A per-region source, with the mask of regions that raised it
+    Region(Interrupt, u8),
+    /// Undefined register access, with the UASR.URAT trace
+    UndefinedAccess(u8),
+}
+
+impl Event {
+    /// Events raised in the ISR value `status`, `trace` is UASR.URAT
+    pub fn decode(status: u32, trace: u8) -> impl Iterator<Item = Event> {
+        let regions = SOURCES.iter().filter_map(move |&source| {
+            let regions = source.regions(status);
+            if regions != 0 {
+                Some(Event::Region(source, regions))
+            } else {
+                None
+            }
+        });
+        let undefined = if status & URAD != 0 {
+            Some(Event::UndefinedAccess(trace))
+        } else {
+            None
+        };
+        regions.chain(undefined)
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Event::Region(source, regions) => {
+                let name = match source {
+                    Interrupt::HashCompleted => "hash completed",
+                    Interrupt::DigestMismatch => "digest mismatch",
+                    Interrupt::BusError => "bus error",
+                    Interrupt::WrapCondition => "wrap condition",
+                    Interrupt::EndCondition => "end condition",
+                    Interrupt::StatusUpdated => "status updated",
+                };
+                write!(f, "{}, regions {:04b}", name, regions)
+            }
+            Event::UndefinedAccess(trace) => {
+                let cause = match trace {
+                    0 => "unspecified descriptor member set",
+                    1 => "CFG modified while monitoring",
+                    2 => "DSCR modified while monitoring",
+                    3 => "HASH modified while monitoring",
+                    4 => "write-only register read",
+                    _ => "reserved",
+                };
+                write!(f, "undefined register access, {}", cause)
+            }
+        }
+    }
+}
+
+/// Read and clear the interrupt status, call from the `ICM` interrupt
+///
+/// Raised sources that are enabled are queued and disabled, so a lasting
+/// condition does not keep the CPU in the handler; starting a configuration
+/// enables them again. Returns the ISR value read.
+pub fn capture(icm: &ICM, events: &mut Producer) -> u32 {
+    let status = icm.isr.read().bits();
+    let raised = status & icm.imr.read().bits();
+    let trace = icm.uasr.read().urat().bits();
+
+    // Safe, only disables the sources just seen
+    unsafe {
+        icm.idr.write(|w| w.bits(raised));
+    }
+
+    queue(raised, trace, events);
+    status
+}
+
+/// Queue the events raised in `status`, counting those that do not fit
+fn queue(status: u32, trace: u8, events: &mut Producer) {
+    for event in Event::decode(status, trace) {
+        if events.enqueue(event).is_err() {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Events lost to a full queue since the last call
+pub fn dropped() -> u32 {
+    DROPPED.swap(0, Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::icm::config::MAX_REGIONS;
+    use std::{string::ToString, vec::Vec};
+
+    fn decode(status: u32, trace: u8) -> Vec<Event> {
+        Event::decode(status, trace).collect()
+    }
+
+    #[test]
+    fn sources() {
+        for &source in SOURCES.iter() {
+            assert_eq!(
+                decode(source.bit(0) | source.bit(2), 0),
+                [Event::Region(source, 0b0101)]
+            );
+        }
+        assert_eq!(decode(0, 0), []);
+    }
+
+    #[test]
+    fn all_sources() {
+        let status = SOURCES
+            .iter()
+            .enumerate()
+            .fold(URAD, |status, (index, source)| {
+                status | source.bit(index % MAX_REGIONS)
+            });
+        assert_eq!(
+            decode(status, 3),
+            [
+                Event::Region(Interrupt::HashCompleted, 0b0001),
+                Event::Region(Interrupt::DigestMismatch, 0b0010),
+                Event::Region(Interrupt::BusError, 0b0100),
+                Event::Region(Interrupt::WrapCondition, 0b1000),
+                Event::Region(Interrupt::EndCondition, 0b0001),
+                Event::Region(Interrupt::StatusUpdated, 0b0010),
+                Event::UndefinedAccess(3),
+            ]
+        );
+    }
+
+    #[test]
+    fn undefined_access() {
+        assert_eq!(decode(URAD, 2), [Event::UndefinedAccess(2)]);
+        // The trace only counts with URAD set, reserved bits are ignored
+        assert_eq!(decode(0, 2), []);
+        assert_eq!(decode(!(URAD | 0xff_ffff), 2), []);
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(
+            Event::Region(Interrupt::DigestMismatch, 0b1001).to_string(),
+            "digest mismatch, regions 1001"
+        );
+        assert_eq!(
+            Event::UndefinedAccess(1).to_string(),
+            "undefined register access, CFG modified while monitoring"
+        );
+        assert_eq!(
+            Event::UndefinedAccess(7).to_string(),
+            "undefined register access, reserved"
+        );
+    }
+
+    #[test]
+    fn dropped_when_full() {
+        let mut events = Queue::new();
+        let (mut producer, mut consumer) = events.split();
+        let status = Interrupt::HashCompleted.bit(0) | Interrupt::DigestMismatch.bit(1);
+
+        // Two events each, the queue holds one less than its length
+        for _ in 0..QUEUE_LEN / 2 {
+            queue(status, 0, &mut producer);
+        }
+        assert_eq!(consumer.len(), QUEUE_LEN - 1);
+        assert_eq!(dropped(), 1);
+        assert_eq!(dropped(), 0);
+
+        assert_eq!(
+            consumer.dequeue(),
+            Some(Event::Region(Interrupt::HashCompleted, 1))
+        );
+        queue(status, 0, &mut producer);
+        assert_eq!(dropped(), 1);
+    }
+}
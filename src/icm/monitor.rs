@@ -0,0 +1,224 @@
+//! Firmware integrity monitor
+//!
+//! Keeps the ICM hashing the application flash, by default the `.text` and
+//! `.rodata` ranges from the linker script, and compares every pass against
+//! reference digests. A mismatch raises the `ICM` interrupt, in which
+//! [`Monitor::capture`] queues it for a lower priority task to report, see
+//! [`events`].
+//!
+//! The descriptor lists are built with [`IcmConfig`], one region of the main
+//! list per monitored region. A region over [`MAX_BLOCKS`] blocks continues in
+//! chunks of its secondary list, hashed into the same digest.
+//!
+//! Flash is hashed as it is, without SHA padding, so the digests are the
+//! SHA-256 chaining value after the region's last block rather than standard
+//! SHA-256 digests. Reference digests are read from the `.icm_digests`
+//! section, patched in after linking by `tools/icm-digests`. When that section
+//! is blank the first pass is written back and serves as the reference from
+//! then on.
+
+use super::config::{
+    Configuration, Error, IcmConfig, Interrupt, Memory, DIGEST_WORDS, MAX_BLOCKS, MAX_REGIONS,
+};
+use super::events::{self, Producer};
+use super::{ICM, MCLK};
+use crate::sha::{Algorithm, BLOCK_LEN, BLOCK_WORDS};
+
+/// Bus burden control, 2^BBC cycles between blocks keep the bus available
+const BUS_BURDEN: u8 = 4;
+
+/// Reference digests, one slot per region in the order they are monitored
+///
+/// Words are in hash area order. All zero, or erased, means no reference.
+#[repr(C)]
+pub struct Digests(pub [[u32; DIGEST_WORDS]; MAX_REGIONS]);
+
+#[link_section = ".icm_digests"]
+#[used]
+#[no_mangle]
+pub static ICM_DIGESTS: Digests = Digests([[0; DIGEST_WORDS]; MAX_REGIONS]);
+
+extern "C" {
+    static __stext: u8;
+    static __etext: u8;
+    static __srodata: u8;
+    static __erodata: u8;
+}
+
+/// Memory range hashed into one digest, whole blocks only
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Region {
+    pub name: &'static str,
+    pub start: u32,
+    pub end: u32,
+}
+
+impl Region {
+    /// Region covering `start..end`, widened to block boundaries
+    pub fn new(name: &'static str, start: *const u8, end: *const u8) -> Self {
+        let mask = BLOCK_LEN as u32 - 1;
+        Self {
+            name,
+            start: start as u32 & !mask,
+            end: (end as u32 + mask) & !mask,
+        }
+    }
+
+    pub fn blocks(&self) -> usize {
+        (self.end.saturating_sub(self.start)) as usize / BLOCK_LEN
+    }
+
+    /// The region's memory, in chunks of at most [`MAX_BLOCKS`] blocks
+    fn chunks(&self) -> impl Iterator<Item = &'static [u32]> {
+        // Safe, regions are flash or RAM that stays readable, the ICM reads
+        // them all the same
+        let words = unsafe {
+            core::slice::from_raw_parts(self.start as *const u32, self.blocks() * BLOCK_WORDS)
+        };
+        words.chunks(MAX_BLOCKS * BLOCK_WORDS)
+    }
+}
+
+/// The `.text` and `.rodata` ranges
+pub fn firmware_regions() -> [Region; 2] {
+    // Safe, only the addresses of the linker symbols are taken
+    unsafe {
+        [
+            Region::new(".text", &__stext, &__etext),
+            Region::new(".rodata", &__srodata, &__erodata),
+        ]
+    }
+}
+
+/// Where the digests compared against come from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reference {
+    /// Patched into `.icm_digests` after linking
+    Build,
+    /// Written back by the first pass after start-up
+    FirstPass,
+}
+
+/// Continuous ICM check of up to [`MAX_REGIONS`] regions
+pub struct Monitor {
+    icm: ICM,
+    config: Configuration,
+    regions: [Option<Region>; MAX_REGIONS],
+    reference: Option<[[u32; DIGEST_WORDS]; MAX_REGIONS]>,
+}
+
+impl Monitor {
+    /// Build the descriptor lists for `regions` in `memory` and start
+    /// monitoring
+    ///
+    /// On error the ICM and `memory` are handed back untouched.
+    pub fn new(
+        icm: ICM,
+        mclk: &mut MCLK,
+        memory: &'static mut Memory,
+        regions: &[Region],
+    ) -> Result<Self, (Error, ICM, &'static mut Memory)> {
+        let reference = read_reference(regions.len().min(MAX_REGIONS));
+        let compare = reference.is_some();
+
+        let mut config = IcmConfig::new().bus_burden(BUS_BURDEN).wrap();
+        for region in regions {
+            let mut chunks = region.chunks();
+            // An empty region is left for the builder to report
+            config = config.region(chunks.next().unwrap_or(&[]), Algorithm::Sha256);
+            for chunk in chunks {
+                config = config.chunk(chunk);
+            }
+            if compare {
+                config = config.compare();
+            }
+            config = config
+                .interrupt_on_mismatch()
+                .interrupt(Interrupt::BusError);
+        }
+        // Without a reference the first pass is written back, then the ICM
+        // switches to comparing on its own
+        if !compare {
+            config = config.auto_switch_to_compare();
+        }
+        let config = match config.build(memory) {
+            Ok(config) => config,
+            Err((error, memory)) => return Err((error, icm, memory)),
+        };
+
+        mclk.ahbmask.modify(|_, w| w.icm_().set_bit());
+        mclk.apbcmask.modify(|_, w| w.icm_().set_bit());
+
+        let mut slots = [None; MAX_REGIONS];
+        for (slot, region) in slots.iter_mut().zip(regions) {
+            *slot = Some(*region);
+        }
+
+        let mut monitor = Self {
+            icm,
+            config,
+            regions: slots,
+            reference,
+        };
+        monitor.start();
+        Ok(monitor)
+    }
+
+    /// Monitored regions, in hash area order
+    pub fn regions(&self) -> impl Iterator<Item = &Region> {
+        self.regions.iter().flatten()
+    }
+
+    pub fn reference(&self) -> Reference {
+        match self.reference {
+            Some(_) => Reference::Build,
+            None => Reference::FirstPass,
+        }
+    }
+
+    /// Reference digest of region `index`, in hash area order
+    pub fn digest(&self, index: usize) -> Option<[u32; DIGEST_WORDS]> {
+        self.config.digest(index)
+    }
+
+    /// Reset the ICM and start over from the reference digests
+    pub fn start(&mut self) {
+        self.icm.ctrl.write(|w| w.swrst().set_bit());
+        if let Some(reference) = self.reference {
+            for (region, digest) in reference.iter().enumerate() {
+                self.config.set_digest(region, *digest);
+            }
+        }
+        self.config.start(&self.icm);
+    }
+
+    /// Queue the interrupt status with [`events::capture`], call from the
+    /// `ICM` interrupt
+    ///
+    /// Queued sources have their interrupt disabled so a lasting mismatch
+    /// does not keep the CPU in the handler; [`Monitor::start`] enables them
+    /// again.
+    pub fn capture(&mut self, events: &mut Producer) -> u32 {
+        events::capture(&self.icm, events)
+    }
+}
+
+/// Reference digests for the first `count` regions, unless blank
+fn read_reference(count: usize) -> Option<[[u32; DIGEST_WORDS]; MAX_REGIONS]> {
+    let mut digests = [[0; DIGEST_WORDS]; MAX_REGIONS];
+    // The section is patched after linking, so it must not be constant folded
+    for (digest, stored) in digests.iter_mut().zip(ICM_DIGESTS.0.iter()) {
+        for (word, stored) in digest.iter_mut().zip(stored) {
+            *word = unsafe { core::ptr::read_volatile(stored) };
+        }
+    }
+
+    let blank = digests[..count]
+        .iter()
+        .any(|digest| digest.iter().all(|&w| w == 0) || digest.iter().all(|&w| w == !0));
+    if blank {
+        None
+    } else {
+        Some(digests)
+    }
+}
@@ -1,3 +1,14 @@
 #![no_std]
 
+pub mod board;
+pub mod calendar;
+pub mod clock;
+pub mod compare;
+pub mod crashlog;
+pub mod crypto;
+pub mod hex;
+pub mod icm;
+pub mod monotonic;
+pub mod sha;
+pub mod trng;
 pub mod uart;
@@ -0,0 +1,210 @@
+//! RTIC monotonics that keep time across core clock changes
+//!
+//! DWT/SysTick monotonics take the core clock as a const generic, so
+//! `spawn_after` runs fast or slow once it changes, see
+//! [`crate::clock::switch`]. Two monotonics here do not:
+//!
+//! - [`SysTickMonotonic`] counts SysTick interrupts at [`SYSTICK_HZ`], with the
+//!   SysTick reload worked out from the core clock and set again with
+//!   [`SysTickMonotonic::rescale`] after a clock switch. The time between the
+//!   switch and the rescale is counted at the old rate.
+//! - [`RtcMonotonic`] runs the RTC as a 32-bit counter at 32.768 kHz with
+//!   compare 0 for the timer queue. It does not depend on the core clock at
+//!   all, and keeps counting while the core sleeps: the RTC and its clock run
+//!   in standby, and compare 0 wakes the core. Instants wrap after 36 hours;
+//!   delays up to half of that are fine.
+//!
+//! ```ignore
+//! #[monotonic(binds = RTC, default = true)]
+//! type Mono = RtcMonotonic;
+//!
+//! let mono = RtcMonotonic::new(device.RTC, RtcClock::Xosc32k);
+//! ```
+//!
+//! Should XOSC32K fail, its failure detector hands the RTC OSCULP32K instead,
+//! see [`crate::clock::failure`], and the RTC counter carries on less
+//! accurately.
+
+use crate::board::pac::{OSC32KCTRL, RTC};
+use cortex_m::peripheral::{syst::SystClkSource, SYST};
+use rtic::Monotonic;
+
+/// Tick rate of [`SysTickMonotonic`]
+pub const SYSTICK_HZ: u32 = 1_000;
+
+pub type SysTickInstant = fugit::TimerInstantU64<SYSTICK_HZ>;
+pub type SysTickDuration = fugit::TimerDurationU64<SYSTICK_HZ>;
+
+pub struct SysTickMonotonic {
+    systick: SYST,
+    ticks: u64,
+}
+
+impl SysTickMonotonic {
+    /// Tick at [`SYSTICK_HZ`] from a core clock of `cpu_hz`, RTIC starts it
+    pub fn new(mut systick: SYST, cpu_hz: u32) -> Self {
+        systick.disable_counter();
+        systick.set_clock_source(SystClkSource::Core);
+        systick.set_reload(reload(cpu_hz));
+        Self { systick, ticks: 0 }
+    }
+
+    /// Keep ticking at [`SYSTICK_HZ`] after the core clock changed to `cpu_hz`
+    ///
+    /// Takes effect from the next tick.
+    pub fn rescale(cpu_hz: u32) {
+        // Safe, RTIC owns the SysTick but only the reload value changes, which
+        // the counter loads at the next wrap
+        unsafe { (*SYST::PTR).rvr.write(reload(cpu_hz)) };
+    }
+}
+
+/// SysTick reload for [`SYSTICK_HZ`], at least 1
+fn reload(cpu_hz: u32) -> u32 {
+    (cpu_hz / SYSTICK_HZ).max(2) - 1
+}
+
+impl Monotonic for SysTickMonotonic {
+    // Ticks are counted in the interrupt, it has to keep coming
+    const DISABLE_INTERRUPT_ON_EMPTY_QUEUE: bool = false;
+
+    type Instant = SysTickInstant;
+    type Duration = SysTickDuration;
+
+    fn now(&mut self) -> SysTickInstant {
+        if self.systick.has_wrapped() {
+            self.ticks += 1;
+        }
+        SysTickInstant::from_ticks(self.ticks)
+    }
+
+    // Every tick interrupts, RTIC checks the queue each time
+    fn set_compare(&mut self, _: SysTickInstant) {}
+
+    fn clear_compare_flag(&mut self) {}
+
+    fn on_interrupt(&mut self) {
+        if self.systick.has_wrapped() {
+            self.ticks += 1;
+        }
+    }
+
+    fn zero() -> SysTickInstant {
+        SysTickInstant::from_ticks(0)
+    }
+
+    unsafe fn reset(&mut self) {
+        self.systick.clear_current();
+        self.systick.enable_counter();
+    }
+
+    fn enable_timer(&mut self) {
+        self.systick.enable_interrupt();
+    }
+
+    fn disable_timer(&mut self) {
+        self.systick.disable_interrupt();
+    }
+}
+
+/// Counter frequency of [`RtcMonotonic`]
+pub const RTC_HZ: u32 = 32_768;
+
+pub type RtcInstant = fugit::TimerInstantU32<RTC_HZ>;
+pub type RtcDuration = fugit::TimerDurationU32<RTC_HZ>;
+
+/// CTRLA, 32-bit counter mode with COUNT read synchronization, no prescaler
+const CTRLA_MODE0: u16 = 1 << 15 | 1 << 8;
+const CTRLA_ENABLE: u16 = 1 << 1;
+const SYNCBUSY_COMP0: u32 = 1 << 5;
+
+const XOSC32K_EN32K: u16 = 1 << 3;
+const XOSC32K_EN1K: u16 = 1 << 4;
+const XOSC32K_RUNSTDBY: u16 = 1 << 6;
+const XOSC32K_ONDEMAND: u16 = 1 << 7;
+
+/// RTC clock, OSC32KCTRL.RTCCTRL.RTCSEL, also used by [`crate::calendar`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RtcClock {
+    /// Internal ultra low power oscillator, always running but inaccurate
+    OscUlp32k,
+    /// 32.768 kHz crystal, which has to be enabled already
+    Xosc32k,
+}
+
+impl RtcClock {
+    /// Clock the RTC from the 32.768 kHz output, or the 1.024 kHz one
+    pub(crate) fn select(self, one_khz: bool) {
+        // Safe, the HAL does not use the RTC clock selection, nor the outputs
+        // and standby setting of XOSC32K
+        let osc32kctrl = unsafe { &*OSC32KCTRL::ptr() };
+        if self == RtcClock::Xosc32k {
+            let output = if one_khz { XOSC32K_EN1K } else { XOSC32K_EN32K };
+            osc32kctrl.xosc32k.modify(|r, w| unsafe {
+                w.bits(r.bits() & !XOSC32K_ONDEMAND | output | XOSC32K_RUNSTDBY)
+            });
+        }
+        let rtcsel = match (self, one_khz) {
+            (RtcClock::OscUlp32k, true) => 0,
+            (RtcClock::OscUlp32k, false) => 1,
+            (RtcClock::Xosc32k, true) => 4,
+            (RtcClock::Xosc32k, false) => 5,
+        };
+        osc32kctrl.rtcctrl.write(|w| unsafe { w.bits(rtcsel) });
+    }
+}
+
+pub struct RtcMonotonic {
+    rtc: RTC,
+}
+
+impl RtcMonotonic {
+    /// Select the RTC clock and reset the RTC, RTIC starts it
+    ///
+    /// The 32 kHz output of XOSC32K is turned on and kept running in standby.
+    pub fn new(rtc: RTC, clock: RtcClock) -> Self {
+        clock.select(false);
+
+        let mode0 = rtc.mode0();
+        mode0.ctrla.write(|w| w.swrst().set_bit());
+        while mode0.syncbusy.read().swrst().bit_is_set() {}
+
+        Self { rtc }
+    }
+}
+
+impl Monotonic for RtcMonotonic {
+    type Instant = RtcInstant;
+    type Duration = RtcDuration;
+
+    fn now(&mut self) -> RtcInstant {
+        let mode0 = self.rtc.mode0();
+        while mode0.syncbusy.read().count().bit_is_set() {}
+        RtcInstant::from_ticks(mode0.count.read().bits())
+    }
+
+    fn set_compare(&mut self, instant: RtcInstant) {
+        let mode0 = self.rtc.mode0();
+        // Safe, any compare value is valid
+        unsafe {
+            mode0.comp[0].write(|w| w.bits(instant.duration_since_epoch().ticks()));
+        }
+        while mode0.syncbusy.read().bits() & SYNCBUSY_COMP0 != 0 {}
+    }
+
+    fn clear_compare_flag(&mut self) {
+        self.rtc.mode0().intflag.write(|w| w.cmp0().set_bit());
+    }
+
+    fn zero() -> RtcInstant {
+        RtcInstant::from_ticks(0)
+    }
+
+    unsafe fn reset(&mut self) {
+        // The counter starts from 0 after the reset in `new`
+        let mode0 = self.rtc.mode0();
+        mode0.ctrla.write(|w| w.bits(CTRLA_MODE0 | CTRLA_ENABLE));
+        while mode0.syncbusy.read().enable().bit_is_set() {}
+        mode0.intenset.write(|w| w.cmp0().set_bit());
+    }
+}
@@ -0,0 +1,257 @@
+//! SHA-1 and SHA-2 message buffering and padding
+//!
+//! Hash engines such as the ICM only run the compression function over whole
+//! 64-byte blocks. [`State`] collects arbitrary-length input into blocks,
+//! appends the FIPS 180-4 padding when finalized and hands the blocks to a
+//! compression closure supplied by the engine.
+//!
+//! ICM regions are hashed as they are in memory, so data monitored there has
+//! to carry its own padding; [`PaddedMessage`] lays a message out that way.
+
+pub mod selftest;
+
+use digest::{BlockInput, FixedOutput, Reset, Update};
+
+/// Bytes per message block, the same for all supported algorithms
+pub const BLOCK_LEN: usize = 64;
+/// Words per message block
+pub const BLOCK_WORDS: usize = BLOCK_LEN / 4;
+/// Bytes of chaining state, large enough for SHA-256
+pub const STATE_LEN: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// The padded message does not fit the buffer
+    TooLong,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Algorithm {
+    Sha1,
+    Sha224,
+    Sha256,
+}
+
+impl Algorithm {
+    /// Name as written in FIPS 180-4
+    pub const fn name(self) -> &'static str {
+        match self {
+            Algorithm::Sha1 => "SHA-1",
+            Algorithm::Sha224 => "SHA-224",
+            Algorithm::Sha256 => "SHA-256",
+        }
+    }
+
+    /// Length of the digest in bytes
+    pub const fn digest_len(self) -> usize {
+        match self {
+            Algorithm::Sha1 => 20,
+            Algorithm::Sha224 => 28,
+            Algorithm::Sha256 => 32,
+        }
+    }
+
+    /// Length of the chaining state in bytes
+    ///
+    /// SHA-224 carries the full SHA-256 state and truncates it at the end.
+    pub const fn state_len(self) -> usize {
+        match self {
+            Algorithm::Sha1 => 20,
+            Algorithm::Sha224 | Algorithm::Sha256 => 32,
+        }
+    }
+
+    /// Initial hash value H(0) from FIPS 180-4 section 5.3, big endian
+    pub const fn initial_state(self) -> [u8; STATE_LEN] {
+        let words: [u32; 8] = match self {
+            Algorithm::Sha1 => [
+                0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0, 0, 0, 0,
+            ],
+            Algorithm::Sha224 => [
+                0xc1059ed8, 0x367cd507, 0x3070dd17, 0xf70e5939, 0xffc00b31, 0x68581511, 0x64f98fa7,
+                0xbefa4fa4,
+            ],
+            Algorithm::Sha256 => [
+                0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+                0x5be0cd19,
+            ],
+        };
+        let mut state = [0; STATE_LEN];
+        let mut i = 0;
+        while i < STATE_LEN {
+            state[i] = (words[i / 4] >> (24 - 8 * (i % 4))) as u8;
+            i += 1;
+        }
+        state
+    }
+}
+
+/// Hash functions usable with [`hmac::Hmac`] and [`hkdf::Hkdf`]
+pub trait HashFunction: Update + BlockInput + FixedOutput + Reset + Default + Clone {}
+
+impl<D: Update + BlockInput + FixedOutput + Reset + Default + Clone> HashFunction for D {}
+
+/// Running hash: chaining state, partial block and message length
+#[derive(Clone)]
+pub struct State {
+    algorithm: Algorithm,
+    hash: [u8; STATE_LEN],
+    buffer: [u8; BLOCK_LEN],
+    buffered: usize,
+    length: u64,
+}
+
+impl State {
+    pub const fn new(algorithm: Algorithm) -> Self {
+        Self {
+            algorithm,
+            hash: algorithm.initial_state(),
+            buffer: [0; BLOCK_LEN],
+            buffered: 0,
+            length: 0,
+        }
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// Absorb `data`, passing every completed run of blocks to `compress`
+    ///
+    /// `compress` receives the big endian chaining state and a whole number
+    /// of blocks, and must update the state in place.
+    pub fn update<F>(&mut self, mut data: &[u8], mut compress: F)
+    where
+        F: FnMut(Algorithm, &mut [u8; STATE_LEN], &[u8]),
+    {
+        self.length += data.len() as u64;
+
+        if self.buffered > 0 {
+            let take = data.len().min(BLOCK_LEN - self.buffered);
+            self.buffer[self.buffered..self.buffered + take].copy_from_slice(&data[..take]);
+            self.buffered += take;
+            data = &data[take..];
+            if self.buffered < BLOCK_LEN {
+                return;
+            }
+            compress(self.algorithm, &mut self.hash, &self.buffer);
+            self.buffered = 0;
+        }
+
+        let whole = data.len() - data.len() % BLOCK_LEN;
+        if whole > 0 {
+            compress(self.algorithm, &mut self.hash, &data[..whole]);
+        }
+        let rest = &data[whole..];
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.buffered = rest.len();
+    }
+
+    /// Pad the message, run the final blocks and write the digest to `out`
+    ///
+    /// `out` must be [`Algorithm::digest_len`] bytes. The state is left
+    /// finalized; start over with [`State::new`].
+    pub fn finalize<F>(&mut self, out: &mut [u8], mut compress: F)
+    where
+        F: FnMut(Algorithm, &mut [u8; STATE_LEN], &[u8]),
+    {
+        let mut blocks = [0; 2 * BLOCK_LEN];
+        let len = pad(&self.buffer[..self.buffered], self.length, &mut blocks);
+        compress(self.algorithm, &mut self.hash, &blocks[..len]);
+        out.copy_from_slice(&self.hash[..self.algorithm.digest_len()]);
+    }
+}
+
+/// Bytes a message of `len` bytes takes once padded to whole blocks
+pub const fn padded_len(len: usize) -> usize {
+    (len + 9 + BLOCK_LEN - 1) / BLOCK_LEN * BLOCK_LEN
+}
+
+/// Write the trailing partial block `tail` of a `length` byte message,
+/// followed by the FIPS 180-4 section 5.1.1 padding, to `out`
+///
+/// Returns the number of bytes written, one or two blocks. `tail` must be
+/// shorter than a block and `out` two blocks long.
+fn pad(tail: &[u8], length: u64, out: &mut [u8; 2 * BLOCK_LEN]) -> usize {
+    let len = padded_len(tail.len());
+    out[..tail.len()].copy_from_slice(tail);
+    out[tail.len()] = 0x80;
+    out[len - 8..len].copy_from_slice(&(length * 8).to_be_bytes());
+    len
+}
+
+/// Message followed by its FIPS 180-4 padding, in up to `BLOCKS` whole,
+/// word-aligned blocks
+///
+/// Words hold the message bytes in memory order, the way the ICM reads them,
+/// so `b"abc"` starts with the word `0x80636261`.
+#[derive(Debug, Clone)]
+pub struct PaddedMessage<const BLOCKS: usize> {
+    words: [[u32; BLOCK_WORDS]; BLOCKS],
+    blocks: usize,
+}
+
+impl<const BLOCKS: usize> PaddedMessage<BLOCKS> {
+    /// Pad `message`, panics if it does not fit
+    ///
+    /// Meant for statics, where a message that does not fit fails the build.
+    pub const fn new(message: &[u8]) -> Self {
+        match Self::try_new(message) {
+            Ok(padded) => padded,
+            Err(_) => panic!("padded message does not fit"),
+        }
+    }
+
+    /// Pad `message`
+    pub const fn try_new(message: &[u8]) -> Result<Self, Error> {
+        let len = padded_len(message.len());
+        if len > BLOCKS * BLOCK_LEN {
+            return Err(Error::TooLong);
+        }
+
+        let bits = message.len() as u64 * 8;
+        let mut words = [[0; BLOCK_WORDS]; BLOCKS];
+        let mut i = 0;
+        while i < len {
+            let byte = if i < message.len() {
+                message[i]
+            } else if i == message.len() {
+                0x80
+            } else if i >= len - 8 {
+                (bits >> (8 * (len - 1 - i))) as u8
+            } else {
+                0
+            };
+            words[i / BLOCK_LEN][i % BLOCK_LEN / 4] |= (byte as u32) << (8 * (i % 4));
+            i += 1;
+        }
+
+        Ok(Self {
+            words,
+            blocks: len / BLOCK_LEN,
+        })
+    }
+
+    /// Number of blocks taken by the padded message
+    pub const fn blocks(&self) -> usize {
+        self.blocks
+    }
+
+    /// ICM RCTRL transfer size, the number of blocks minus one
+    pub const fn trsize(&self) -> u16 {
+        (self.blocks - 1) as u16
+    }
+
+    /// The padded message, [`blocks`](Self::blocks) times [`BLOCK_WORDS`] words
+    pub fn words(&self) -> &[u32] {
+        // Safe, the blocks are contiguous arrays of words
+        unsafe {
+            core::slice::from_raw_parts(self.words.as_ptr().cast(), self.blocks * BLOCK_WORDS)
+        }
+    }
+
+    /// Region start address for the ICM
+    pub fn as_ptr(&self) -> *const u32 {
+        self.words.as_ptr().cast()
+    }
+}
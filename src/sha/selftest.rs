@@ -0,0 +1,459 @@
+//! Table-driven hash self-test
+//!
+//! Checks SHA-1, SHA-224 and SHA-256 against the FIPS 180-4 examples, HMAC
+//! against RFC 4231 and HKDF against RFC 5869. The hash implementations are
+//! generic, so the ICM engine and software hashes run the same tables.
+
+use super::{Algorithm, HashFunction};
+use crate::crypto::selftest::Summary;
+use crate::hex::decode_array as hex;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac, NewMac};
+
+/// Longest HKDF output in [`HKDF_VECTORS`]
+const MAX_OKM: usize = 82;
+
+/// Message digest of `message` repeated `repeat` times
+#[derive(Debug)]
+pub struct DigestVector {
+    pub name: &'static str,
+    pub algorithm: Algorithm,
+    pub message: &'static [u8],
+    pub repeat: usize,
+    pub digest: &'static [u8],
+}
+
+/// HMAC of `data`, `mac` may be truncated
+#[derive(Debug)]
+pub struct HmacVector {
+    pub name: &'static str,
+    pub algorithm: Algorithm,
+    pub key: &'static [u8],
+    pub data: &'static [u8],
+    pub mac: &'static [u8],
+}
+
+/// HKDF extract and expand
+#[derive(Debug)]
+pub struct HkdfVector {
+    pub name: &'static str,
+    pub algorithm: Algorithm,
+    pub ikm: &'static [u8],
+    pub salt: Option<&'static [u8]>,
+    pub info: &'static [u8],
+    pub prk: &'static [u8],
+    pub okm: &'static [u8],
+}
+
+/// FIPS 180-4 two-block example message
+const TWO_BLOCK: &[u8] = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+
+/// Every digest vector run by [`run`]
+pub const DIGEST_VECTORS: &[DigestVector] = &[
+    DigestVector {
+        name: "FIPS 180-4 abc",
+        algorithm: Algorithm::Sha1,
+        message: b"abc",
+        repeat: 1,
+        digest: &hex::<20>("a9993e364706816aba3e25717850c26c9cd0d89d"),
+    },
+    DigestVector {
+        name: "FIPS 180-4 two blocks",
+        algorithm: Algorithm::Sha1,
+        message: TWO_BLOCK,
+        repeat: 1,
+        digest: &hex::<20>("84983e441c3bd26ebaae4aa1f95129e5e54670f1"),
+    },
+    DigestVector {
+        name: "empty",
+        algorithm: Algorithm::Sha1,
+        message: b"",
+        repeat: 1,
+        digest: &hex::<20>("da39a3ee5e6b4b0d3255bfef95601890afd80709"),
+    },
+    DigestVector {
+        name: "one million a",
+        algorithm: Algorithm::Sha1,
+        message: &[b'a'; 1000],
+        repeat: 1000,
+        digest: &hex::<20>("34aa973cd4c4daa4f61eeb2bdbad27316534016f"),
+    },
+    DigestVector {
+        name: "FIPS 180-4 abc",
+        algorithm: Algorithm::Sha224,
+        message: b"abc",
+        repeat: 1,
+        digest: &hex::<28>("23097d223405d8228642a477bda255b32aadbce4bda0b3f7e36c9da7"),
+    },
+    DigestVector {
+        name: "FIPS 180-4 two blocks",
+        algorithm: Algorithm::Sha224,
+        message: TWO_BLOCK,
+        repeat: 1,
+        digest: &hex::<28>("75388b16512776cc5dba5da1fd890150b0c6455cb4f58b1952522525"),
+    },
+    DigestVector {
+        name: "empty",
+        algorithm: Algorithm::Sha224,
+        message: b"",
+        repeat: 1,
+        digest: &hex::<28>("d14a028c2a3a2bc9476102bb288234c415a2b01f828ea62ac5b3e42f"),
+    },
+    DigestVector {
+        name: "one million a",
+        algorithm: Algorithm::Sha224,
+        message: &[b'a'; 1000],
+        repeat: 1000,
+        digest: &hex::<28>("20794655980c91d8bbb4c1ea97618a4bf03f42581948b2ee4ee7ad67"),
+    },
+    DigestVector {
+        name: "FIPS 180-4 abc",
+        algorithm: Algorithm::Sha256,
+        message: b"abc",
+        repeat: 1,
+        digest: &hex::<32>("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+    },
+    DigestVector {
+        name: "FIPS 180-4 two blocks",
+        algorithm: Algorithm::Sha256,
+        message: TWO_BLOCK,
+        repeat: 1,
+        digest: &hex::<32>("248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"),
+    },
+    DigestVector {
+        name: "empty",
+        algorithm: Algorithm::Sha256,
+        message: b"",
+        repeat: 1,
+        digest: &hex::<32>("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"),
+    },
+    DigestVector {
+        name: "one million a",
+        algorithm: Algorithm::Sha256,
+        message: &[b'a'; 1000],
+        repeat: 1000,
+        digest: &hex::<32>("cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"),
+    },
+];
+
+/// RFC 4231 test case 4 key
+const RFC4231_KEY_4: [u8; 25] = hex("0102030405060708090a0b0c0d0e0f10111213141516171819");
+/// RFC 4231 test case 6 and 7 key, longer than a block
+const RFC4231_KEY_LONG: [u8; 131] = [0xaa; 131];
+const RFC4231_DATA_6: &[u8] = b"Test Using Larger Than Block-Size Key - Hash Key First";
+const RFC4231_DATA_7: &[u8] = b"This is a test using a larger than block-size key and a larger than block-size data. The key needs to be hashed before being used by the HMAC algorithm.";
+
+/// Every HMAC vector run by [`run`]
+pub const HMAC_VECTORS: &[HmacVector] = &[
+    HmacVector {
+        name: "RFC 4231 1",
+        algorithm: Algorithm::Sha224,
+        key: &[0x0b; 20],
+        data: b"Hi There",
+        mac: &hex::<28>("896fb1128abbdf196832107cd49df33f47b4b1169912ba4f53684b22"),
+    },
+    HmacVector {
+        name: "RFC 4231 2",
+        algorithm: Algorithm::Sha224,
+        key: b"Jefe",
+        data: b"what do ya want for nothing?",
+        mac: &hex::<28>("a30e01098bc6dbbf45690f3a7e9e6d0f8bbea2a39e6148008fd05e44"),
+    },
+    HmacVector {
+        name: "RFC 4231 3",
+        algorithm: Algorithm::Sha224,
+        key: &[0xaa; 20],
+        data: &[0xdd; 50],
+        mac: &hex::<28>("7fb3cb3588c6c1f6ffa9694d7d6ad2649365b0c1f65d69d1ec8333ea"),
+    },
+    HmacVector {
+        name: "RFC 4231 4",
+        algorithm: Algorithm::Sha224,
+        key: &RFC4231_KEY_4,
+        data: &[0xcd; 50],
+        mac: &hex::<28>("6c11506874013cac6a2abc1bb382627cec6a90d86efc012de7afec5a"),
+    },
+    HmacVector {
+        name: "RFC 4231 5",
+        algorithm: Algorithm::Sha224,
+        key: &[0x0c; 20],
+        data: b"Test With Truncation",
+        mac: &hex::<16>("0e2aea68a90c8d37c988bcdb9fca6fa8"),
+    },
+    HmacVector {
+        name: "RFC 4231 6",
+        algorithm: Algorithm::Sha224,
+        key: &RFC4231_KEY_LONG,
+        data: RFC4231_DATA_6,
+        mac: &hex::<28>("95e9a0db962095adaebe9b2d6f0dbce2d499f112f2d2b7273fa6870e"),
+    },
+    HmacVector {
+        name: "RFC 4231 7",
+        algorithm: Algorithm::Sha224,
+        key: &RFC4231_KEY_LONG,
+        data: RFC4231_DATA_7,
+        mac: &hex::<28>("3a854166ac5d9f023f54d517d0b39dbd946770db9c2b95c9f6f565d1"),
+    },
+    HmacVector {
+        name: "RFC 4231 1",
+        algorithm: Algorithm::Sha256,
+        key: &[0x0b; 20],
+        data: b"Hi There",
+        mac: &hex::<32>("b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"),
+    },
+    HmacVector {
+        name: "RFC 4231 2",
+        algorithm: Algorithm::Sha256,
+        key: b"Jefe",
+        data: b"what do ya want for nothing?",
+        mac: &hex::<32>("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"),
+    },
+    HmacVector {
+        name: "RFC 4231 3",
+        algorithm: Algorithm::Sha256,
+        key: &[0xaa; 20],
+        data: &[0xdd; 50],
+        mac: &hex::<32>("773ea91e36800e46854db8ebd09181a72959098b3ef8c122d9635514ced565fe"),
+    },
+    HmacVector {
+        name: "RFC 4231 4",
+        algorithm: Algorithm::Sha256,
+        key: &RFC4231_KEY_4,
+        data: &[0xcd; 50],
+        mac: &hex::<32>("82558a389a443c0ea4cc819899f2083a85f0faa3e578f8077a2e3ff46729665b"),
+    },
+    HmacVector {
+        name: "RFC 4231 5",
+        algorithm: Algorithm::Sha256,
+        key: &[0x0c; 20],
+        data: b"Test With Truncation",
+        mac: &hex::<16>("a3b6167473100ee06e0c796c2955552b"),
+    },
+    HmacVector {
+        name: "RFC 4231 6",
+        algorithm: Algorithm::Sha256,
+        key: &RFC4231_KEY_LONG,
+        data: RFC4231_DATA_6,
+        mac: &hex::<32>("60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"),
+    },
+    HmacVector {
+        name: "RFC 4231 7",
+        algorithm: Algorithm::Sha256,
+        key: &RFC4231_KEY_LONG,
+        data: RFC4231_DATA_7,
+        mac: &hex::<32>("9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2"),
+    },
+];
+
+/// RFC 5869 basic test case input keying material
+const RFC5869_IKM: [u8; 22] = [0x0b; 22];
+const RFC5869_SALT: [u8; 13] = hex("000102030405060708090a0b0c");
+const RFC5869_INFO: [u8; 10] = hex("f0f1f2f3f4f5f6f7f8f9");
+/// RFC 5869 long input test case, three 80-byte counting sequences
+const RFC5869_LONG_IKM: [u8; 80] = hex(concat!(
+    "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+    "202122232425262728292a2b2c2d2e2f303132333435363738393a3b3c3d3e3f",
+    "404142434445464748494a4b4c4d4e4f",
+));
+const RFC5869_LONG_SALT: [u8; 80] = hex(concat!(
+    "606162636465666768696a6b6c6d6e6f707172737475767778797a7b7c7d7e7f",
+    "808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f",
+    "a0a1a2a3a4a5a6a7a8a9aaabacadaeaf",
+));
+const RFC5869_LONG_INFO: [u8; 80] = hex(concat!(
+    "b0b1b2b3b4b5b6b7b8b9babbbcbdbebfc0c1c2c3c4c5c6c7c8c9cacbcccdcecf",
+    "d0d1d2d3d4d5d6d7d8d9dadbdcdddedfe0e1e2e3e4e5e6e7e8e9eaebecedeeef",
+    "f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff",
+));
+
+/// Every HKDF vector run by [`run`]
+pub const HKDF_VECTORS: &[HkdfVector] = &[
+    HkdfVector {
+        name: "RFC 5869 A.1",
+        algorithm: Algorithm::Sha256,
+        ikm: &RFC5869_IKM,
+        salt: Some(&RFC5869_SALT),
+        info: &RFC5869_INFO,
+        prk: &hex::<32>("077709362c2e32df0ddc3f0dc47bba6390b6c73bb50f9c3122ec844ad7c2b3e5"),
+        okm: &hex::<42>(concat!(
+            "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf",
+            "34007208d5b887185865",
+        )),
+    },
+    HkdfVector {
+        name: "RFC 5869 A.2",
+        algorithm: Algorithm::Sha256,
+        ikm: &RFC5869_LONG_IKM,
+        salt: Some(&RFC5869_LONG_SALT),
+        info: &RFC5869_LONG_INFO,
+        prk: &hex::<32>("06a6b88c5853361a06104c9ceb35b45cef760014904671014a193f40c15fc244"),
+        okm: &hex::<82>(concat!(
+            "b11e398dc80327a1c8e7f78c596a49344f012eda2d4efad8a050cc4c19afa97c",
+            "59045a99cac7827271cb41c65e590e09da3275600c2f09b8367793a9aca3db71",
+            "cc30c58179ec3e87c14c01d5c1f3434f1d87",
+        )),
+    },
+    HkdfVector {
+        name: "RFC 5869 A.3",
+        algorithm: Algorithm::Sha256,
+        ikm: &RFC5869_IKM,
+        salt: Some(&[]),
+        info: &[],
+        prk: &hex::<32>("19ef24a32c717b167f33a91d6f648bdf96596776afdb6377ac434c1c293ccb04"),
+        okm: &hex::<42>(concat!(
+            "8da4e775a563c18f715f802a063c5a31b8a11f5c5ee1879ec3454e5f3c738d2d",
+            "9d201395faa4b61a96c8",
+        )),
+    },
+    HkdfVector {
+        name: "RFC 5869 A.4",
+        algorithm: Algorithm::Sha1,
+        ikm: &[0x0b; 11],
+        salt: Some(&RFC5869_SALT),
+        info: &RFC5869_INFO,
+        prk: &hex::<20>("9b6c18c432a7bf8f0e71c8eb88f4b30baa2ba243"),
+        okm: &hex::<42>(concat!(
+            "085a01ea1b10f36933068b56efa5ad81a4f14b822f5b091568a9cdd4f155fda2",
+            "c22e422478d305f3f896",
+        )),
+    },
+    HkdfVector {
+        name: "RFC 5869 A.5",
+        algorithm: Algorithm::Sha1,
+        ikm: &RFC5869_LONG_IKM,
+        salt: Some(&RFC5869_LONG_SALT),
+        info: &RFC5869_LONG_INFO,
+        prk: &hex::<20>("8adae09a2a307059478d309b26c4115a224cfaf6"),
+        okm: &hex::<82>(concat!(
+            "0bd770a74d1160f7c9f12cd5912a06ebff6adcae899d92191fe4305673ba2ffe",
+            "8fa3f1a4e5ad79f3f334b3b202b2173c486ea37ce3d397ed034c7f9dfeb15c5e",
+            "927336d0441f4c4300e2cff0d0900b52d3b4",
+        )),
+    },
+    HkdfVector {
+        name: "RFC 5869 A.6",
+        algorithm: Algorithm::Sha1,
+        ikm: &RFC5869_IKM,
+        salt: Some(&[]),
+        info: &[],
+        prk: &hex::<20>("da8c8a73c7fa77288ec6f5e7c297786aa0d32d01"),
+        okm: &hex::<42>(concat!(
+            "0ac1af7002b3d761d1e55298da9d0506b9ae52057220a306e07b6b87e8df21d0",
+            "ea00033de03984d34918",
+        )),
+    },
+    HkdfVector {
+        name: "RFC 5869 A.7",
+        algorithm: Algorithm::Sha1,
+        ikm: &[0x0c; 22],
+        salt: None,
+        info: &[],
+        prk: &hex::<20>("2adccada18779e7c2077ad2eb19d3f3e731385dd"),
+        okm: &hex::<42>(concat!(
+            "2c91117204d745f3500d636a62f64f0ab3bae548aa53d423b0d1f27ebba6f5e5",
+            "673a081d70cce7acfc48",
+        )),
+    },
+];
+
+pub fn check_digest<D: HashFunction>(vector: &DigestVector) -> bool {
+    let mut digest = D::default();
+    for _ in 0..vector.repeat {
+        digest.update(vector.message);
+    }
+    digest.finalize_fixed()[..] == *vector.digest
+}
+
+pub fn check_hmac<D: HashFunction>(vector: &HmacVector) -> bool {
+    let mut mac = match Hmac::<D>::new_from_slice(vector.key) {
+        Ok(mac) => mac,
+        Err(_) => return false,
+    };
+    mac.update(vector.data);
+    let mac = mac.finalize().into_bytes();
+    mac.len() >= vector.mac.len() && mac[..vector.mac.len()] == *vector.mac
+}
+
+pub fn check_hkdf<D: HashFunction>(vector: &HkdfVector) -> bool {
+    let mut okm = [0; MAX_OKM];
+    if vector.okm.len() > okm.len() {
+        return false;
+    }
+    let okm = &mut okm[..vector.okm.len()];
+
+    let (prk, hkdf) = Hkdf::<D>::extract(vector.salt, vector.ikm);
+    hkdf.expand(vector.info, okm).is_ok() && prk[..] == *vector.prk && okm == vector.okm
+}
+
+/// Run every vector in [`DIGEST_VECTORS`], [`HMAC_VECTORS`] and
+/// [`HKDF_VECTORS`], printing the outcome to `tx`
+///
+/// Returns `Err` if any vector failed.
+pub fn run<S1, S224, S256, W>(tx: &mut W) -> Result<Summary, Summary>
+where
+    S1: HashFunction,
+    S224: HashFunction,
+    S256: HashFunction,
+    W: core::fmt::Write + ?Sized,
+{
+    let mut summary = Summary {
+        passed: 0,
+        failed: 0,
+    };
+    let mut report = |passed: bool, kind: &str, algorithm: Algorithm, name: &str| {
+        if passed {
+            summary.passed += 1;
+        } else {
+            summary.failed += 1;
+        }
+        write!(
+            tx,
+            "{} {}{} {}\r\n",
+            if passed { "pass" } else { "FAIL" },
+            kind,
+            algorithm.name(),
+            name
+        )
+        .ok();
+    };
+
+    for vector in DIGEST_VECTORS {
+        let passed = match vector.algorithm {
+            Algorithm::Sha1 => check_digest::<S1>(vector),
+            Algorithm::Sha224 => check_digest::<S224>(vector),
+            Algorithm::Sha256 => check_digest::<S256>(vector),
+        };
+        report(passed, "", vector.algorithm, vector.name);
+    }
+
+    for vector in HMAC_VECTORS {
+        let passed = match vector.algorithm {
+            Algorithm::Sha1 => check_hmac::<S1>(vector),
+            Algorithm::Sha224 => check_hmac::<S224>(vector),
+            Algorithm::Sha256 => check_hmac::<S256>(vector),
+        };
+        report(passed, "HMAC-", vector.algorithm, vector.name);
+    }
+
+    for vector in HKDF_VECTORS {
+        let passed = match vector.algorithm {
+            Algorithm::Sha1 => check_hkdf::<S1>(vector),
+            Algorithm::Sha224 => check_hkdf::<S224>(vector),
+            Algorithm::Sha256 => check_hkdf::<S256>(vector),
+        };
+        report(passed, "HKDF-", vector.algorithm, vector.name);
+    }
+
+    write!(
+        tx,
+        "hash self-test: {} passed, {} failed\r\n",
+        summary.passed, summary.failed
+    )
+    .ok();
+
+    if summary.failed == 0 {
+        Ok(summary)
+    } else {
+        Err(summary)
+    }
+}
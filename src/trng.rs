@@ -0,0 +1,144 @@
+//! True random number generator
+//!
+//! [`Trng`] collects TRNG output in the `TRNG` interrupt into a small pool and
+//! hands it out through [`rand_core::RngCore`]. Reads that drain the pool poll
+//! the peripheral for the rest. Every word passes the SP 800-90B health tests
+//! in [`health`] first; after a failure the generator refuses to produce
+//! output until [`Trng::restart`] succeeds.
+
+pub mod health;
+
+use crate::board::pac::{MCLK, TRNG};
+use core::num::NonZeroU32;
+use health::{Failure, HealthTests, STARTUP_SAMPLES};
+use heapless::spsc::Queue;
+use rand_core::{impls, CryptoRng, RngCore};
+
+/// Words buffered by the interrupt, one slot is kept free by the queue
+const POOL_SIZE: usize = 16;
+
+/// `rand_core` error code reported after a health test failure
+const HEALTH_TEST_FAILED: u32 = rand_core::Error::CUSTOM_START;
+
+pub struct Trng {
+    trng: TRNG,
+    pool: Queue<u32, POOL_SIZE>,
+    health: HealthTests,
+    failure: Option<Failure>,
+}
+
+impl Trng {
+    /// Enable the TRNG and run the start-up health tests
+    ///
+    /// The generator is usable even if the start-up tests fail, but refuses to
+    /// produce output; check [`Trng::failure`].
+    pub fn new(trng: TRNG, mclk: &mut MCLK) -> Self {
+        mclk.apbcmask.modify(|_, w| w.trng_().set_bit());
+        trng.ctrla.write(|w| w.enable().set_bit());
+
+        let mut this = Self {
+            trng,
+            pool: Queue::new(),
+            health: HealthTests::new(),
+            failure: None,
+        };
+        this.restart().ok();
+        this
+    }
+
+    /// Health test failure that stopped the generator, if any
+    pub fn failure(&self) -> Option<Failure> {
+        self.failure
+    }
+
+    /// Drop buffered output, rerun the start-up health tests and resume
+    pub fn restart(&mut self) -> Result<(), Failure> {
+        self.trng.intenclr.write(|w| w.datardy().set_bit());
+        while self.pool.dequeue().is_some() {}
+        self.health = HealthTests::new();
+        self.failure = None;
+
+        for _ in 0..STARTUP_SAMPLES / 4 {
+            let word = self.poll();
+            if let Err(failure) = self.health.feed_word(word) {
+                self.failure = Some(failure);
+                return Err(failure);
+            }
+        }
+
+        self.trng.intenset.write(|w| w.datardy().set_bit());
+        Ok(())
+    }
+
+    /// Move a word into the pool, call from the `TRNG` interrupt
+    ///
+    /// The interrupt is disabled once the pool is full and re-enabled when
+    /// output is taken from it.
+    pub fn on_interrupt(&mut self) {
+        if self.trng.intflag.read().datardy().bit_is_clear() {
+            return;
+        }
+        if let Ok(word) = self.sample() {
+            self.pool.enqueue(word).ok();
+        }
+        if self.pool.is_full() || self.failure.is_some() {
+            self.trng.intenclr.write(|w| w.datardy().set_bit());
+        }
+    }
+
+    /// Next health tested word, from the pool or polled from the peripheral
+    pub fn next_word(&mut self) -> Result<u32, Failure> {
+        if let Some(failure) = self.failure {
+            return Err(failure);
+        }
+        let word = match self.pool.dequeue() {
+            Some(word) => word,
+            None => self.sample()?,
+        };
+        self.trng.intenset.write(|w| w.datardy().set_bit());
+        Ok(word)
+    }
+
+    /// Read one word and run it through the health tests
+    fn sample(&mut self) -> Result<u32, Failure> {
+        let word = self.poll();
+        self.health.feed_word(word).map_err(|failure| {
+            self.failure = Some(failure);
+            failure
+        })?;
+        Ok(word)
+    }
+
+    /// Wait for and read the next output word, clearing DATARDY
+    fn poll(&mut self) -> u32 {
+        while self.trng.intflag.read().datardy().bit_is_clear() {}
+        self.trng.data.read().bits()
+    }
+}
+
+impl RngCore for Trng {
+    /// Panics if the health tests have failed
+    fn next_u32(&mut self) -> u32 {
+        self.next_word().expect("TRNG health test failed")
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        impls::next_u64_via_u32(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.try_fill_bytes(dest).expect("TRNG health test failed")
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        for chunk in dest.chunks_mut(4) {
+            let word = self
+                .next_word()
+                .map_err(|_| NonZeroU32::new(HEALTH_TEST_FAILED).unwrap())?;
+            chunk.copy_from_slice(&word.to_le_bytes()[..chunk.len()]);
+        }
+        Ok(())
+    }
+}
+
+impl CryptoRng for Trng {}
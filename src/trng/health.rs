@@ -0,0 +1,171 @@
+//! Continuous health tests from NIST SP 800-90B section 4.4
+//!
+//! Every TRNG output byte is treated as one sample. The cutoffs assume a
+//! conservative min-entropy of [`MIN_ENTROPY`] bits per byte and a false
+//! positive probability of 2^-40 per sample.
+
+/// Assessed min-entropy per sample, in bits
+pub const MIN_ENTROPY: u32 = 4;
+/// Repetition count test cutoff, `1 + ceil(40 / MIN_ENTROPY)`
+pub const RCT_CUTOFF: u32 = 11;
+/// Adaptive proportion test window size for non-binary samples
+pub const APT_WINDOW: u32 = 512;
+/// Adaptive proportion test cutoff, `1 + CRITBINOM(512, 2^-4, 1 - 2^-40)`
+pub const APT_CUTOFF: u32 = 78;
+/// Samples run through both tests before first use, section 4.3
+pub const STARTUP_SAMPLES: u32 = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Failure {
+    /// The same sample repeated [`RCT_CUTOFF`] times in a row
+    RepetitionCount,
+    /// The first sample of a window recurred [`APT_CUTOFF`] times in it
+    AdaptiveProportion,
+}
+
+/// Repetition count and adaptive proportion test state
+#[derive(Debug, Clone)]
+pub struct HealthTests {
+    /// Last sample and how often it repeated back to back
+    previous: Option<u8>,
+    repetitions: u32,
+    /// Window reference sample, how often it occurred and samples seen
+    reference: u8,
+    matches: u32,
+    window: u32,
+}
+
+impl HealthTests {
+    pub const fn new() -> Self {
+        Self {
+            previous: None,
+            repetitions: 0,
+            reference: 0,
+            matches: 0,
+            window: 0,
+        }
+    }
+
+    /// Run one sample through both tests
+    pub fn feed(&mut self, sample: u8) -> Result<(), Failure> {
+        if self.previous == Some(sample) {
+            self.repetitions += 1;
+        } else {
+            self.previous = Some(sample);
+            self.repetitions = 1;
+        }
+
+        if self.window == 0 {
+            self.reference = sample;
+            self.matches = 1;
+        } else if sample == self.reference {
+            self.matches += 1;
+        }
+        self.window = (self.window + 1) % APT_WINDOW;
+
+        if self.repetitions >= RCT_CUTOFF {
+            Err(Failure::RepetitionCount)
+        } else if self.matches >= APT_CUTOFF {
+            Err(Failure::AdaptiveProportion)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Run every byte of a TRNG output word through both tests
+    pub fn feed_word(&mut self, word: u32) -> Result<(), Failure> {
+        word.to_le_bytes()
+            .iter()
+            .try_for_each(|sample| self.feed(*sample))
+    }
+}
+
+impl Default for HealthTests {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REFERENCE: u8 = 0xaa;
+
+    /// Sample `index` of a sequence that never repeats back to back and never
+    /// matches [`REFERENCE`]
+    fn other(index: u32) -> u8 {
+        let sample = (index % 255) as u8;
+        if sample >= REFERENCE {
+            sample + 1
+        } else {
+            sample
+        }
+    }
+
+    /// Feed [`REFERENCE`] `matches` times, separated by other samples, and
+    /// return the samples fed
+    fn interleave(tests: &mut HealthTests, matches: u32) -> Result<u32, Failure> {
+        for index in 0..matches {
+            if index > 0 {
+                tests.feed(other(index))?;
+            }
+            tests.feed(REFERENCE)?;
+        }
+        Ok(2 * matches - 1)
+    }
+
+    #[test]
+    fn repetition_count() {
+        let mut tests = HealthTests::new();
+        for _ in 0..RCT_CUTOFF - 1 {
+            assert_eq!(tests.feed(0x55), Ok(()));
+        }
+        assert_eq!(tests.feed(0x55), Err(Failure::RepetitionCount));
+
+        // Any other sample starts the count over
+        let mut tests = HealthTests::new();
+        for _ in 0..RCT_CUTOFF - 1 {
+            assert_eq!(tests.feed(0x55), Ok(()));
+        }
+        assert_eq!(tests.feed(0x56), Ok(()));
+        for _ in 0..RCT_CUTOFF - 2 {
+            assert_eq!(tests.feed(0x55), Ok(()));
+        }
+    }
+
+    #[test]
+    fn adaptive_proportion() {
+        let mut tests = HealthTests::new();
+        assert_eq!(
+            interleave(&mut tests, APT_CUTOFF - 1),
+            Ok(2 * APT_CUTOFF - 3)
+        );
+        assert_eq!(tests.feed(other(0)), Ok(()));
+        assert_eq!(tests.feed(REFERENCE), Err(Failure::AdaptiveProportion));
+    }
+
+    #[test]
+    fn adaptive_proportion_window() {
+        let mut tests = HealthTests::new();
+        let fed = interleave(&mut tests, APT_CUTOFF - 1).unwrap();
+        for index in fed..APT_WINDOW {
+            assert_eq!(tests.feed(other(index)), Ok(()));
+        }
+
+        // A new window counts its own first sample from scratch
+        assert_eq!(
+            interleave(&mut tests, APT_CUTOFF - 1),
+            Ok(2 * APT_CUTOFF - 3)
+        );
+    }
+
+    #[test]
+    fn words() {
+        let mut tests = HealthTests::new();
+        assert_eq!(tests.feed_word(0x0403_0201), Ok(()));
+        assert_eq!(tests.feed_word(0x0404_0404), Ok(()));
+        assert_eq!(tests.feed_word(0x0404_0404), Ok(()));
+        assert_eq!(tests.feed_word(0x0000_0404), Err(Failure::RepetitionCount));
+    }
+}
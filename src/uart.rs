@@ -1,27 +1,12 @@
 //! UART Related types and config
 
-cfg_if::cfg_if! {
-    if #[cfg(feature = "clockv1")] {
-        pub use atsamd_hal_clockv1::{
-            gpio::{Alternate, Pin, Pins, D, PA04, PA05},
-            ehal::serial::Write,
-            sercom::{uart::*, IoSet3, Sercom0},
-        };
+pub use crate::board::hal::{
+    ehal::serial::Write,
+    gpio::{Alternate, Pin, Pins, D, PA04, PA05},
+    sercom::{uart::*, IoSet3, Sercom0},
+};
 
-    } else if #[cfg(feature = "hal-aes")] {
-        pub use atsamd_hal_aes::{
-            gpio::{Alternate, Pin, Pins, D, PA04, PA05},
-            ehal::serial::Write,
-            sercom::{uart::*, IoSet3, Sercom0},
-        };
-    } else {
-        pub use atsamd_hal::{
-            gpio::{Alternate, Pin, Pins, D, PA04, PA05},
-            ehal::serial::Write,
-            sercom::{uart::*, IoSet3, Sercom0},
-        };
-    }
-}
+use crate::board::{pac::SERCOM0, CONSOLE_BAUD};
 
 #[derive(Debug)]
 pub enum UartCommand {
@@ -33,13 +18,49 @@ pub enum UartCommand {
     ReadError(Error),
 }
 
-pub type Uart0Tx =
-    Uart<Config<Pads<Sercom0, IoSet3, Pin<PA05, Alternate<D>>, Pin<PA04, Alternate<D>>>>, TxDuplex>;
-pub type Uart0Rx =
-    Uart<Config<Pads<Sercom0, IoSet3, Pin<PA05, Alternate<D>>, Pin<PA04, Alternate<D>>>>, RxDuplex>;
+/// Console pads, PA05 RX and PA04 TX
+pub type Uart0Pads = Pads<Sercom0, IoSet3, Pin<PA05, Alternate<D>>, Pin<PA04, Alternate<D>>>;
+pub type Uart0 = Uart<Config<Uart0Pads>, Duplex>;
+pub type Uart0Tx = Uart<Config<Uart0Pads>, TxDuplex>;
+pub type Uart0Rx = Uart<Config<Uart0Pads>, RxDuplex>;
 
 pub type String = heapless::String<256>;
 
+/// BAUD register value for [`CONSOLE_BAUD`] from a SERCOM clock of
+/// `clock_hz`, `None` if the clock is too slow for the baud rate
+pub fn console_baud(clock_hz: u32) -> Option<u16> {
+    if clock_hz == 0 {
+        return None;
+    }
+    // Arithmetic mode, 16x oversampling: BAUD = 65536 * (1 - 16 * f_baud / f_ref)
+    let ratio = 65536 * 16 * CONSOLE_BAUD as u64 / clock_hz as u64;
+    if ratio >= 65536 {
+        return None;
+    }
+    Some((65536 - ratio) as u16)
+}
+
+/// Set the console back to [`CONSOLE_BAUD`] after its core clock changed
+///
+/// The HAL works out BAUD once, when the console is set up; after GCLK0 moves
+/// under it, as in a clock failure fallback, the console needs a new one for
+/// `clock_hz`. Returns `false` if `clock_hz` is too slow for the baud rate.
+pub fn set_console_baud(clock_hz: u32) -> bool {
+    let baud = match console_baud(clock_hz) {
+        Some(baud) => baud,
+        None => return false,
+    };
+
+    // Safe, BAUD only changes while the USART is disabled
+    let usart = unsafe { &*SERCOM0::ptr() }.usart_int();
+    usart.ctrla.modify(|_, w| w.enable().clear_bit());
+    while usart.syncbusy.read().enable().bit_is_set() {}
+    usart.baud().write(|w| unsafe { w.bits(baud) });
+    usart.ctrla.modify(|_, w| w.enable().set_bit());
+    while usart.syncbusy.read().enable().bit_is_set() {}
+    true
+}
+
 #[macro_export]
 macro_rules! clear_screen {
     ($tx:tt) => {{
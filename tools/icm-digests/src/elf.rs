@@ -0,0 +1,166 @@
+//! Just enough of a 32-bit little endian ELF reader for linked firmware
+
+use std::convert::TryInto;
+use std::fmt;
+
+const PT_LOAD: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Not a 32-bit little endian ELF file
+    NotElf32,
+    /// A header or table points past the end of the file
+    Truncated,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NotElf32 => write!(f, "not a 32-bit little endian ELF file"),
+            Error::Truncated => write!(f, "ELF file is truncated"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    pub name: String,
+    pub kind: u32,
+    pub address: u32,
+    pub offset: u32,
+    pub size: u32,
+    pub link: u32,
+}
+
+/// Loadable segment, placed at its physical (load) address
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub offset: u32,
+    pub address: u32,
+    pub file_size: u32,
+}
+
+pub struct Elf<'a> {
+    data: &'a [u8],
+    pub sections: Vec<Section>,
+    pub segments: Vec<Segment>,
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, Error> {
+    data.get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes(b.try_into().unwrap()))
+        .ok_or(Error::Truncated)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, Error> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or(Error::Truncated)
+}
+
+/// Nul terminated string at `offset` of a string table
+fn str_at(table: &[u8], offset: usize) -> Result<String, Error> {
+    let bytes = table.get(offset..).ok_or(Error::Truncated)?;
+    let len = bytes.iter().position(|&b| b == 0).ok_or(Error::Truncated)?;
+    Ok(String::from_utf8_lossy(&bytes[..len]).into_owned())
+}
+
+impl<'a> Elf<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, Error> {
+        // Magic, ELFCLASS32, ELFDATA2LSB
+        if data.get(..6) != Some(&[0x7f, b'E', b'L', b'F', 1, 1][..]) {
+            return Err(Error::NotElf32);
+        }
+
+        let phoff = u32_at(data, 28)? as usize;
+        let shoff = u32_at(data, 32)? as usize;
+        let phentsize = u16_at(data, 42)? as usize;
+        let phnum = u16_at(data, 44)? as usize;
+        let shentsize = u16_at(data, 46)? as usize;
+        let shnum = u16_at(data, 48)? as usize;
+        let shstrndx = u16_at(data, 50)? as usize;
+
+        let mut segments = Vec::new();
+        for index in 0..phnum {
+            let header = phoff + index * phentsize;
+            if u32_at(data, header)? == PT_LOAD {
+                segments.push(Segment {
+                    offset: u32_at(data, header + 4)?,
+                    address: u32_at(data, header + 12)?,
+                    file_size: u32_at(data, header + 16)?,
+                });
+            }
+        }
+
+        let mut sections = Vec::new();
+        let mut names = Vec::new();
+        for index in 0..shnum {
+            let header = shoff + index * shentsize;
+            names.push(u32_at(data, header)? as usize);
+            sections.push(Section {
+                name: String::new(),
+                kind: u32_at(data, header + 4)?,
+                address: u32_at(data, header + 12)?,
+                offset: u32_at(data, header + 16)?,
+                size: u32_at(data, header + 20)?,
+                link: u32_at(data, header + 24)?,
+            });
+        }
+
+        if let Some(strtab) = sections.get(shstrndx) {
+            let start = strtab.offset as usize;
+            let table = data
+                .get(start..start + strtab.size as usize)
+                .ok_or(Error::Truncated)?;
+            for (section, name) in sections.iter_mut().zip(names) {
+                section.name = str_at(table, name)?;
+            }
+        }
+
+        Ok(Self {
+            data,
+            sections,
+            segments,
+        })
+    }
+
+    /// File contents of `section`
+    pub fn contents(&self, section: &Section) -> Result<&'a [u8], Error> {
+        let start = section.offset as usize;
+        self.data
+            .get(start..start + section.size as usize)
+            .ok_or(Error::Truncated)
+    }
+
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|s| s.name == name)
+    }
+
+    /// File contents of `segment`
+    pub fn segment_contents(&self, segment: &Segment) -> Result<&'a [u8], Error> {
+        let start = segment.offset as usize;
+        self.data
+            .get(start..start + segment.file_size as usize)
+            .ok_or(Error::Truncated)
+    }
+
+    /// Value of the symbol `name` from the symbol table
+    pub fn symbol(&self, name: &str) -> Result<Option<u32>, Error> {
+        let symtab = match self.sections.iter().find(|s| s.kind == SHT_SYMTAB) {
+            Some(symtab) => symtab,
+            None => return Ok(None),
+        };
+        let strtab = self
+            .sections
+            .get(symtab.link as usize)
+            .ok_or(Error::Truncated)?;
+        let strings = self.contents(strtab)?;
+        for symbol in self.contents(symtab)?.chunks_exact(16) {
+            if str_at(strings, u32_at(symbol, 0)? as usize)? == name {
+                return Ok(Some(u32_at(symbol, 4)?));
+            }
+        }
+        Ok(None)
+    }
+}
@@ -0,0 +1,204 @@
+//! Reference digests for the ICM firmware monitor
+//!
+//! `atsamd_demo::icm::monitor` compares the ICM digests of the `.text` and
+//! `.rodata` flash ranges against the `.icm_digests` section. This crate works
+//! those digests out from the linked ELF and patches them into the section, so
+//! the monitor compares from the first pass after boot instead of trusting it.
+//!
+//! The digests are computed the way the ICM produces them: ranges widened to
+//! 64-byte blocks, bytes outside the loaded segments read as erased flash,
+//! SHA-256 compression without padding, and the chaining value stored in hash
+//! area order (big endian bytes).
+
+pub mod elf;
+
+use sha2::digest::generic_array::GenericArray;
+use std::fmt;
+
+/// Bytes per SHA-256 block, regions are widened to whole blocks
+pub const BLOCK_LEN: u32 = 64;
+/// Bytes per digest slot
+pub const DIGEST_LEN: usize = 32;
+/// Slots in the section, `config::MAX_REGIONS`
+pub const MAX_REGIONS: usize = 4;
+/// Section holding the reference digests
+pub const SECTION: &str = ".icm_digests";
+
+/// Regions of `monitor::firmware_regions`, in hash area order, with the
+/// linker symbols bounding them
+pub const FIRMWARE_REGIONS: [(&str, &str, &str); 2] = [
+    (".text", "__stext", "__etext"),
+    (".rodata", "__srodata", "__erodata"),
+];
+
+/// SHA-256 chaining value in hash area order
+pub type Digest = [u8; DIGEST_LEN];
+
+/// Flash bytes not written by any segment
+const ERASED: u8 = 0xff;
+
+const SHA256_INITIAL_STATE: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Elf(elf::Error),
+    /// A linker symbol bounding a region is missing
+    MissingSymbol(&'static str),
+    /// The ELF has no `.icm_digests` section
+    MissingSection,
+    /// The section has room for fewer than [`MAX_REGIONS`] digests
+    SectionTooSmall(u32),
+    /// A region covers the section, so its digest would depend on itself
+    Overlap(&'static str),
+    /// A region ends before it starts
+    InvertedRegion(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Elf(error) => error.fmt(f),
+            Error::MissingSymbol(name) => write!(f, "symbol {} not found", name),
+            Error::MissingSection => write!(f, "no {} section", SECTION),
+            Error::SectionTooSmall(size) => write!(
+                f,
+                "{} is {} bytes, {} needed",
+                SECTION,
+                size,
+                MAX_REGIONS * DIGEST_LEN
+            ),
+            Error::Overlap(name) => write!(f, "{} overlaps {}", name, SECTION),
+            Error::InvertedRegion(name) => write!(f, "{} ends before it starts", name),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<elf::Error> for Error {
+    fn from(error: elf::Error) -> Self {
+        Error::Elf(error)
+    }
+}
+
+/// Flash range hashed into one digest, matching `monitor::Region`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Region {
+    pub name: &'static str,
+    pub start: u32,
+    pub end: u32,
+}
+
+impl Region {
+    /// Region covering `start..end`, widened to block boundaries
+    pub fn new(name: &'static str, start: u32, end: u32) -> Self {
+        let mask = BLOCK_LEN - 1;
+        Self {
+            name,
+            start: start & !mask,
+            end: (end + mask) & !mask,
+        }
+    }
+
+    fn overlaps(&self, start: u32, end: u32) -> bool {
+        self.start < end && start < self.end
+    }
+}
+
+/// The `.text` and `.rodata` ranges of `elf`
+pub fn firmware_regions(elf: &elf::Elf) -> Result<Vec<Region>, Error> {
+    FIRMWARE_REGIONS
+        .iter()
+        .map(|&(name, start, end)| {
+            let symbol = |symbol| elf.symbol(symbol)?.ok_or(Error::MissingSymbol(symbol));
+            Ok(Region::new(name, symbol(start)?, symbol(end)?))
+        })
+        .collect()
+}
+
+/// Flash contents of `region` as programmed from the loadable segments
+pub fn flash(elf: &elf::Elf, region: &Region) -> Result<Vec<u8>, Error> {
+    let len = region
+        .end
+        .checked_sub(region.start)
+        .ok_or(Error::InvertedRegion(region.name))?;
+    let mut bytes = vec![ERASED; len as usize];
+    for segment in &elf.segments {
+        let start = segment.address.max(region.start);
+        let end = segment
+            .address
+            .saturating_add(segment.file_size)
+            .min(region.end);
+        if start < end {
+            let contents = elf.segment_contents(segment)?;
+            bytes[(start - region.start) as usize..(end - region.start) as usize].copy_from_slice(
+                &contents[(start - segment.address) as usize..(end - segment.address) as usize],
+            );
+        }
+    }
+    Ok(bytes)
+}
+
+/// SHA-256 chaining value after the whole blocks of `data`, big endian
+pub fn digest(data: &[u8]) -> Digest {
+    let mut state = SHA256_INITIAL_STATE;
+    let blocks: Vec<_> = data
+        .chunks_exact(BLOCK_LEN as usize)
+        .map(GenericArray::clone_from_slice)
+        .collect();
+    sha2::compress256(&mut state, &blocks);
+
+    let mut digest = [0; DIGEST_LEN];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(state.iter()) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+/// Regions of `elf` with their digests
+pub fn digests(elf: &elf::Elf) -> Result<Vec<(Region, Digest)>, Error> {
+    let section = elf.section(SECTION).ok_or(Error::MissingSection)?;
+    let regions = firmware_regions(elf)?;
+    let section_end = section.address + section.size;
+    if let Some(region) = regions
+        .iter()
+        .find(|r| r.overlaps(section.address, section_end))
+    {
+        return Err(Error::Overlap(region.name));
+    }
+
+    regions
+        .into_iter()
+        .map(|region| Ok((region, digest(&flash(elf, &region)?))))
+        .collect()
+}
+
+/// Contents of the `.icm_digests` section for `digests`, unused slots blank
+pub fn section_contents(digests: &[Digest]) -> [u8; MAX_REGIONS * DIGEST_LEN] {
+    let mut contents = [0; MAX_REGIONS * DIGEST_LEN];
+    for (slot, digest) in contents.chunks_exact_mut(DIGEST_LEN).zip(digests) {
+        slot.copy_from_slice(digest);
+    }
+    contents
+}
+
+/// Compute the digests of the ELF file in `file` and patch them in
+///
+/// Returns the regions and their digests, and whether the section changed.
+pub fn patch(file: &mut [u8]) -> Result<(Vec<(Region, Digest)>, bool), Error> {
+    let elf = elf::Elf::parse(file)?;
+    let section = elf.section(SECTION).ok_or(Error::MissingSection)?;
+    if (section.size as usize) < MAX_REGIONS * DIGEST_LEN {
+        return Err(Error::SectionTooSmall(section.size));
+    }
+    let regions = digests(&elf)?;
+    let contents = section_contents(&regions.iter().map(|(_, d)| *d).collect::<Vec<_>>());
+
+    let start = section.offset as usize;
+    let stored = &mut file[start..start + contents.len()];
+    let changed = stored != &contents[..];
+    stored.copy_from_slice(&contents);
+    Ok((regions, changed))
+}
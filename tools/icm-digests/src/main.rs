@@ -0,0 +1,57 @@
+//! Patch ICM reference digests into a linked firmware ELF
+//!
+//! ```text
+//! icm-digests [--check] <elf>
+//! ```
+//!
+//! With `--check` the file is left alone and the exit status tells whether
+//! the stored digests are up to date.
+
+use std::{env, fs, process};
+
+fn main() {
+    let mut check = false;
+    let mut paths = Vec::new();
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--check" => check = true,
+            _ => paths.push(arg),
+        }
+    }
+    let path = match paths.as_mut_slice() {
+        [path] => std::mem::take(path),
+        _ => {
+            eprintln!("usage: icm-digests [--check] <elf>");
+            process::exit(2);
+        }
+    };
+
+    let mut file = fs::read(&path).unwrap_or_else(|error| {
+        eprintln!("{}: {}", path, error);
+        process::exit(1);
+    });
+    let (regions, changed) = icm_digests::patch(&mut file).unwrap_or_else(|error| {
+        eprintln!("{}: {}", path, error);
+        process::exit(1);
+    });
+
+    for (region, digest) in &regions {
+        let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+        println!(
+            "{:<8} {:#010x}..{:#010x} {}",
+            region.name, region.start, region.end, hex
+        );
+    }
+
+    if check {
+        if changed {
+            eprintln!("{}: {} out of date", path, icm_digests::SECTION);
+            process::exit(1);
+        }
+    } else if changed {
+        if let Err(error) = fs::write(&path, &file) {
+            eprintln!("{}: {}", path, error);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! Digests of a small linked ELF, `tests/fixture`, against values computed
+//! independently of this crate
+
+use icm_digests::{elf::Elf, Error, Region, DIGEST_LEN, MAX_REGIONS};
+
+const FIXTURE: &[u8] = include_bytes!("fixture/fixture.elf");
+
+/// `.icm_digests` file offset in the fixture
+const SECTION_OFFSET: usize = 0x10200;
+
+const TEXT_DIGEST: &str = "6dba94efbd1abe3ebac62d5a6f45c3cc2280733857c231e6dae3200e72fcbb05";
+const RODATA_DIGEST: &str = "3cdff9a7f778d3fb179882021644f595659c3152c43804dfba662699fca026b2";
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[test]
+fn regions_widened_to_blocks() {
+    let elf = Elf::parse(FIXTURE).unwrap();
+    assert_eq!(elf.symbol("__etext").unwrap(), Some(0x144));
+    assert_eq!(elf.symbol("__erodata").unwrap(), Some(0x1c8));
+    assert_eq!(
+        icm_digests::firmware_regions(&elf).unwrap(),
+        vec![
+            Region {
+                name: ".text",
+                start: 0,
+                end: 0x180,
+            },
+            Region {
+                name: ".rodata",
+                start: 0x140,
+                end: 0x200,
+            },
+        ]
+    );
+}
+
+#[test]
+fn flash_from_load_addresses() {
+    let elf = Elf::parse(FIXTURE).unwrap();
+    // .data is loaded at 0x280, right after .icm_digests
+    let data = Region::new(".data", 0x280, 0x284);
+    let flash = icm_digests::flash(&elf, &data).unwrap();
+    assert_eq!(&flash[..4], &0x1234_5678u32.to_le_bytes());
+    assert!(flash[4..].iter().all(|&b| b == 0xff));
+}
+
+#[test]
+fn digests() {
+    let elf = Elf::parse(FIXTURE).unwrap();
+    let digests = icm_digests::digests(&elf).unwrap();
+    assert_eq!(hex(&digests[0].1), TEXT_DIGEST);
+    assert_eq!(hex(&digests[1].1), RODATA_DIGEST);
+}
+
+#[test]
+fn patch() {
+    let mut file = FIXTURE.to_vec();
+    let (_, changed) = icm_digests::patch(&mut file).unwrap();
+    assert!(changed);
+
+    let section = &file[SECTION_OFFSET..SECTION_OFFSET + MAX_REGIONS * DIGEST_LEN];
+    assert_eq!(hex(&section[..DIGEST_LEN]), TEXT_DIGEST);
+    assert_eq!(hex(&section[DIGEST_LEN..2 * DIGEST_LEN]), RODATA_DIGEST);
+    assert!(section[2 * DIGEST_LEN..].iter().all(|&b| b == 0));
+    // Nothing else touched
+    assert_eq!(file[..SECTION_OFFSET], FIXTURE[..SECTION_OFFSET]);
+    assert_eq!(
+        file[SECTION_OFFSET + section.len()..],
+        FIXTURE[SECTION_OFFSET + section.len()..]
+    );
+
+    // Patching again finds the section up to date
+    let patched = file.clone();
+    let (_, changed) = icm_digests::patch(&mut file).unwrap();
+    assert!(!changed);
+    assert_eq!(file, patched);
+}
+
+#[test]
+fn errors() {
+    assert_eq!(
+        icm_digests::patch(&mut b"not an elf".to_vec()).unwrap_err(),
+        Error::Elf(icm_digests::elf::Error::NotElf32)
+    );
+    assert_eq!(
+        icm_digests::patch(&mut FIXTURE[..0x1000].to_vec()).unwrap_err(),
+        Error::Elf(icm_digests::elf::Error::Truncated)
+    );
+    let inverted = Region {
+        name: ".text",
+        start: 0x200,
+        end: 0x100,
+    };
+    assert_eq!(
+        icm_digests::flash(&Elf::parse(FIXTURE).unwrap(), &inverted).unwrap_err(),
+        Error::InvertedRegion(".text")
+    );
+}
@@ -0,0 +1,27 @@
+    .syntax unified
+    .thumb
+
+    .section .vector_table.reset_vector, "a"
+    .word Reset + 1
+
+    .section .text.Reset, "ax"
+    .global Reset
+    .thumb_func
+Reset:
+    ldr r0, =message
+    ldr r1, =counter
+    b Reset
+    .ltorg
+    .fill 300, 1, 0x5a
+
+    .section .rodata.message, "a"
+message:
+    .ascii "atsamd-demo ICM digest fixture"
+    .fill 100, 1, 0xa5
+
+    .section .data.counter, "aw"
+counter:
+    .word 0x12345678
+
+    .section .icm_digests, "a"
+    .fill 128, 1, 0
@@ -0,0 +1,56 @@
+/* Cut-down cortex-m-rt link.x with the .icm_digests part of memory.x
+ *
+ * fixture.elf is built with
+ *   llvm-mc -triple thumbv7em-none-eabihf -filetype=obj fixture.s -o fixture.o
+ *   rust-lld -flavor gnu -T fixture.x fixture.o -o fixture.elf
+ */
+MEMORY
+{
+  FLASH : ORIGIN = 0x00000000, LENGTH = 1024K
+  RAM : ORIGIN = 0x20010000, LENGTH = 64K
+}
+
+ENTRY(Reset);
+
+SECTIONS
+{
+  .vector_table ORIGIN(FLASH) :
+  {
+    LONG(ORIGIN(RAM) + LENGTH(RAM));
+    KEEP(*(.vector_table.reset_vector));
+  } > FLASH
+
+  .text :
+  {
+    __stext = .;
+    *(.text .text.*);
+    . = ALIGN(4);
+    __etext = .;
+  } > FLASH
+
+  .rodata : ALIGN(4)
+  {
+    . = ALIGN(4);
+    __srodata = .;
+    *(.rodata .rodata.*);
+    . = ALIGN(4);
+    __erodata = .;
+  } > FLASH
+
+  .data : ALIGN(4)
+  {
+    . = ALIGN(4);
+    __sdata = .;
+    *(.data .data.*);
+    . = ALIGN(4);
+    __edata = .;
+  } > RAM AT>FLASH
+}
+
+SECTIONS
+{
+  .icm_digests ALIGN(64) :
+  {
+    KEEP(*(.icm_digests));
+  } > FLASH
+} INSERT AFTER .rodata;